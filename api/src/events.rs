@@ -0,0 +1,225 @@
+//! Pushing changes of statuses of withdrawals to subscribers as server-sent events.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::http::StatusCode;
+use axum::{
+    extract::{Path, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use ethers::types::{Address, H256};
+use futures::{stream, Stream};
+use serde::Serialize;
+use sqlx::{postgres::PgListener, PgPool};
+use storage::{EtaModel, FinalizationStatus, WithdrawalsChange};
+use tokio::sync::{watch, OnceCell, OwnedSemaphorePermit};
+
+use crate::{
+    eta_secs, ApiError, ApiState, WithdrawalDetailsResponse, WithdrawalResponse, DEFAULT_PAGE_SIZE,
+    ETA_WINDOW,
+};
+
+// Delay before listening to the notifications again once the connection has failed.
+const LISTENER_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The latest change of withdrawals.
+#[derive(Clone, Default)]
+pub(crate) struct Changes {
+    // Bumped on every change.
+    version: u64,
+    change: Arc<WithdrawalsChange>,
+    // Computed by the first subscriber affected by the change and shared with the rest.
+    model: Arc<OnceCell<EtaModel>>,
+}
+
+/// Publish every notification about changes of withdrawals to `changes`.
+pub(crate) async fn listen_to_changes(pool: PgPool, changes: watch::Sender<Changes>) {
+    loop {
+        if let Ok(mut listener) = PgListener::connect_with(&pool).await {
+            if listener.listen(storage::WITHDRAWALS_CHANNEL).await.is_ok() {
+                // `Ok(None)` is returned once the connection is lost, notifications
+                // may have been missed until it is re-established on the next call.
+                while let Ok(notification) = listener.try_recv().await {
+                    let change = match notification {
+                        Some(notification) => {
+                            WithdrawalsChange::from_payload(notification.payload())
+                        }
+                        None => WithdrawalsChange::any("reconnected"),
+                    };
+
+                    changes.send_modify(|changes| {
+                        changes.version += 1;
+                        changes.change = Arc::new(change);
+                        changes.model = Default::default();
+                    });
+                }
+            }
+        }
+
+        tokio::time::sleep(LISTENER_RECONNECT_DELAY).await;
+    }
+}
+
+// Withdrawals a subscriber is pushed the changes of.
+enum Subscription {
+    // The latest withdrawals to or from an address.
+    Address(Address),
+    // All withdrawals made in a transaction.
+    Transaction(H256),
+}
+
+struct Subscriber {
+    pool: PgPool,
+    subscription: Subscription,
+    changes: watch::Receiver<Changes>,
+    version: u64,
+    statuses: HashMap<u64, FinalizationStatus>,
+    pending: VecDeque<Event>,
+    checked: bool,
+    // Released once the subscriber disconnects.
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Subscriber {
+    fn new(state: ApiState, subscription: Subscription) -> Result<Self, ApiError> {
+        let permit = state
+            .subscribers
+            .try_acquire_owned()
+            .map_err(|_| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "too many subscribers"))?;
+
+        let mut changes = state.changes;
+        let version = changes.borrow_and_update().version;
+
+        Ok(Self {
+            pool: state.pool,
+            subscription,
+            changes,
+            version,
+            statuses: HashMap::new(),
+            pending: VecDeque::new(),
+            checked: false,
+            _permit: permit,
+        })
+    }
+
+    // Queue an event if the status of a withdrawal is new to the subscriber.
+    fn push<T: Serialize>(&mut self, id: u64, status: FinalizationStatus, withdrawal: &T) {
+        if self.statuses.insert(id, status) == Some(status) {
+            return;
+        }
+
+        // Serializing the responses never fails.
+        if let Ok(event) = Event::default().event("withdrawal").json_data(withdrawal) {
+            self.pending.push_back(event);
+        }
+    }
+
+    // Whether the change may affect the withdrawals the subscriber is pushed.
+    fn is_affected_by(&self, change: &WithdrawalsChange) -> bool {
+        let Some(ids) = &change.ids else {
+            return true;
+        };
+
+        ids.iter().any(|id| self.statuses.contains_key(id))
+            || match self.subscription {
+                Subscription::Address(address) => change.addresses.contains(&address),
+                Subscription::Transaction(tx_hash) => change.tx_hashes.contains(&tx_hash),
+            }
+    }
+
+    async fn check(&mut self, model: &OnceCell<EtaModel>) -> Result<(), ApiError> {
+        let model = model
+            .get_or_try_init(|| storage::eta_model(&self.pool, ETA_WINDOW))
+            .await?;
+
+        match self.subscription {
+            Subscription::Address(address) => {
+                let withdrawals = storage::withdrawals_for_address(
+                    &self.pool,
+                    address,
+                    &Default::default(),
+                    DEFAULT_PAGE_SIZE,
+                )
+                .await?;
+
+                // Oldest first.
+                for w in withdrawals.into_iter().rev() {
+                    let (id, status) = (w.id, w.status);
+                    let response = WithdrawalResponse {
+                        eta_secs: eta_secs(model, status, w.commit_l1_block_number, w.seen_at),
+                        ..w.into()
+                    };
+                    self.push(id, status, &response);
+                }
+            }
+            Subscription::Transaction(tx_hash) => {
+                for w in storage::withdrawals_details_in_tx(&self.pool, tx_hash).await? {
+                    let (id, status) = (w.id, w.status);
+                    let response = WithdrawalDetailsResponse {
+                        eta_secs: eta_secs(model, status, w.commit_l1_block_number, w.seen_at),
+                        ..w.into()
+                    };
+                    self.push(id, status, &response);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // The next event, withdrawals are checked at once and then on every change affecting them.
+    async fn next_event(mut self) -> Option<(Result<Event, axum::Error>, Self)> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some((Ok(event), self));
+            }
+
+            let model = if self.checked {
+                if self.changes.changed().await.is_err() {
+                    return None;
+                }
+
+                let changes = self.changes.borrow_and_update().clone();
+                // Changes following each other closely are only seen as the latest one.
+                let skipped = changes.version != self.version + 1;
+                self.version = changes.version;
+
+                if !skipped && !self.is_affected_by(&changes.change) {
+                    continue;
+                }
+
+                changes.model
+            } else {
+                Default::default()
+            };
+            self.checked = true;
+
+            if let Err(e) = self.check(&model).await {
+                let event = Event::default().event("error").data(e.message);
+                return Some((Ok(event), self));
+            }
+        }
+    }
+
+    fn into_sse(self) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+        Sse::new(stream::unfold(self, Self::next_event)).keep_alive(KeepAlive::default())
+    }
+}
+
+pub(crate) async fn address_events(
+    Path(address): Path<Address>,
+    State(state): State<ApiState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    Ok(Subscriber::new(state, Subscription::Address(address))?.into_sse())
+}
+
+pub(crate) async fn transaction_events(
+    Path(tx_hash): Path<H256>,
+    State(state): State<ApiState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    Ok(Subscriber::new(state, Subscription::Transaction(tx_hash))?.into_sse())
+}
@@ -1,65 +1,1059 @@
-use axum::extract::{Path, Query, State};
-use axum::{http::StatusCode, routing::get, Json, Router};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::extract::{FromRef, Path, Query, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{http::StatusCode, Json, Router};
+use client::{WithdrawalKey, ZksyncMiddleware};
 use ethers::abi::Address;
 use ethers::types::{H256, U256};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
-use storage::UserWithdrawal;
+use storage::{
+    EtaModel, FinalizationStatus, GivenUpWithdrawal, StoredWithdrawal, TokenLatency,
+    UserWithdrawal, UserWithdrawalsFilter, Webhook, WebhookDelivery, WithdrawalDetails,
+};
+use tokio::sync::{watch, Semaphore};
 use tower_http::cors::CorsLayer;
+use tx_sender::FeeBudget;
+
+mod events;
+
+/// Role of the instance among the instances sharing the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Waiting to take over once the leader is gone.
+    Standby,
+    /// Running the service.
+    Leader,
+}
+
+#[derive(Clone)]
+struct ApiState {
+    pool: PgPool,
+    fee_budget: FeeBudget,
+    account: Address,
+    role: watch::Receiver<Role>,
+    changes: watch::Receiver<events::Changes>,
+    subscribers: Arc<Semaphore>,
+}
+
+impl FromRef<ApiState> for PgPool {
+    fn from_ref(state: &ApiState) -> Self {
+        state.pool.clone()
+    }
+}
+
+/// Withdrawals listed per page unless requested otherwise.
+pub const DEFAULT_PAGE_SIZE: u64 = 50;
+
+/// The most withdrawals that may be listed per page.
+pub const MAX_PAGE_SIZE: u64 = 500;
+
+/// Window of history the ETAs of withdrawals are estimated from.
+pub const ETA_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The longest window latency statistics may be requested over.
+pub const MAX_STATS_WINDOW: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The most clients that may be subscribed to events of withdrawals at once.
+pub const MAX_SUBSCRIBERS: usize = 1000;
+
+/// Priority manually enqueued withdrawals are given unless specified otherwise.
+pub const MANUAL_FINALIZATION_PRIORITY: i64 = 1;
+
+/// Configuration of the admin endpoints.
+pub struct AdminConfig<M> {
+    /// Bearer token admin requests are authenticated with.
+    pub token: String,
+
+    /// L2 client to fetch parameters of withdrawals with.
+    pub client_l2: Arc<M>,
+}
+
+// Derived `Clone` would require `M: Clone`.
+impl<M> Clone for AdminConfig<M> {
+    fn clone(&self) -> Self {
+        Self {
+            token: self.token.clone(),
+            client_l2: self.client_l2.clone(),
+        }
+    }
+}
+
+struct AdminState<M> {
+    pool: PgPool,
+    token: Arc<str>,
+    client_l2: Arc<M>,
+}
+
+impl<M> Clone for AdminState<M> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            token: self.token.clone(),
+            client_l2: self.client_l2.clone(),
+        }
+    }
+}
+
+impl<M> FromRef<AdminState<M>> for PgPool {
+    fn from_ref(state: &AdminState<M>) -> Self {
+        state.pool.clone()
+    }
+}
+
+/// An error responded with as a JSON body.
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+// Details of storage errors are not exposed to the clients.
+impl From<storage::Error> for ApiError {
+    fn from(_: storage::Error) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "database request failed")
+    }
+}
 
 #[derive(Deserialize, Serialize, Clone)]
-struct WithdrawalRequest {
-    pub limit: u64,
+struct ErrorResponse {
+    pub error: String,
 }
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (
+            self.status,
+            Json(ErrorResponse {
+                error: self.message,
+            }),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct HealthResponse {
+    pub status: String,
+    pub role: Role,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct PageRequest {
+    pub limit: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct WithdrawalsPageRequest {
+    pub limit: Option<u64>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<u64>,
+    pub token: Option<Address>,
+    /// Name of a [`FinalizationStatus`].
+    pub status: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 struct WithdrawalResponse {
+    pub id: u64,
     pub tx_hash: H256,
+    pub event_index_in_tx: u32,
     pub token: Address,
     pub amount: U256,
     pub status: String,
+    /// Estimated seconds until the withdrawal is finalized.
+    pub eta_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct WithdrawalsPageResponse {
+    pub withdrawals: Vec<WithdrawalResponse>,
+    /// Cursor of the next page, `None` on the last page.
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct WithdrawalDetailsResponse {
+    pub id: u64,
+    pub tx_hash: H256,
+    pub event_index_in_tx: u32,
+    pub token: Address,
+    pub amount: U256,
+    pub l2_block_number: u64,
+    pub l1_batch_number: Option<u64>,
+    pub commit_l1_block_number: Option<u64>,
+    pub verify_l1_block_number: Option<u64>,
+    pub execute_l1_block_number: Option<u64>,
+    pub finalization_tx: Option<H256>,
+    pub failed_finalization_attempts: u64,
+    pub last_failure_reason: Option<String>,
+    pub finalizable: bool,
+    pub status: String,
+    pub seen_at: Option<u64>,
+    /// Estimated seconds until the withdrawal is finalized.
+    pub eta_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct LatencyStatsRequest {
+    /// Window in seconds, a day by default.
+    pub window: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct TokenLatencyResponse {
+    pub token: Address,
+    pub finalized: u64,
+    pub p50_secs: u64,
+    pub p90_secs: u64,
+    pub p99_secs: u64,
+}
+
+impl From<TokenLatency> for TokenLatencyResponse {
+    fn from(latency: TokenLatency) -> Self {
+        Self {
+            token: latency.token,
+            finalized: latency.finalized,
+            p50_secs: latency.p50.as_secs(),
+            p90_secs: latency.p90.as_secs(),
+            p99_secs: latency.p99.as_secs(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct LatencyStatsResponse {
+    pub window_secs: u64,
+    pub tokens: Vec<TokenLatencyResponse>,
+}
+
+impl From<WithdrawalDetails> for WithdrawalDetailsResponse {
+    fn from(withdrawal: WithdrawalDetails) -> Self {
+        Self {
+            id: withdrawal.id,
+            tx_hash: withdrawal.tx_hash,
+            event_index_in_tx: withdrawal.event_index_in_tx,
+            token: withdrawal.token,
+            amount: withdrawal.amount,
+            l2_block_number: withdrawal.l2_block_number,
+            l1_batch_number: withdrawal.l1_batch_number,
+            commit_l1_block_number: withdrawal.commit_l1_block_number,
+            verify_l1_block_number: withdrawal.verify_l1_block_number,
+            execute_l1_block_number: withdrawal.execute_l1_block_number,
+            finalization_tx: withdrawal.finalization_tx,
+            failed_finalization_attempts: withdrawal.failed_finalization_attempts,
+            last_failure_reason: withdrawal.last_failure_reason,
+            finalizable: withdrawal.finalizable,
+            status: withdrawal.status.as_str().to_string(),
+            seen_at: withdrawal.seen_at,
+            eta_secs: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct FeeBudgetResponse {
+    pub tx_limit: U256,
+    pub hourly_limit: Option<U256>,
+    pub daily_limit: Option<U256>,
+    pub spent_last_hour: U256,
+    pub spent_last_day: U256,
+    pub remaining: Option<U256>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct GivenUpWithdrawalResponse {
+    pub id: u64,
+    pub tx_hash: H256,
+    pub event_index_in_tx: u32,
+    pub token: Address,
+    pub amount: U256,
+    pub attempts: u64,
+    pub last_failure_reason: Option<String>,
+    pub given_up_at: u64,
+}
+
+impl From<GivenUpWithdrawal> for GivenUpWithdrawalResponse {
+    fn from(withdrawal: GivenUpWithdrawal) -> Self {
+        Self {
+            id: withdrawal.id,
+            tx_hash: withdrawal.tx_hash,
+            event_index_in_tx: withdrawal.event_index_in_tx,
+            token: withdrawal.token,
+            amount: withdrawal.amount,
+            attempts: withdrawal.attempts,
+            last_failure_reason: withdrawal.last_failure_reason,
+            given_up_at: withdrawal.given_up_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct EnqueueRequest {
+    pub priority: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct PriorityRequest {
+    pub priority: i64,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct AdminResponse {
+    pub id: u64,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct WebhookRequest {
+    pub url: String,
+    pub secret: String,
+    pub address: Option<Address>,
+    pub token: Option<Address>,
+}
+
+/// A webhook, its secret is never responded with.
+#[derive(Deserialize, Serialize, Clone)]
+struct WebhookResponse {
+    pub id: u64,
+    pub url: String,
+    pub address: Option<Address>,
+    pub token: Option<Address>,
+}
+
+impl From<Webhook> for WebhookResponse {
+    fn from(webhook: Webhook) -> Self {
+        Self {
+            id: webhook.id,
+            url: webhook.url,
+            address: webhook.address,
+            token: webhook.token,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct DeadWebhookDeliveryResponse {
+    pub id: u64,
+    pub webhook_id: u64,
+    pub url: String,
+    pub event: String,
+    pub attempts: u64,
+    pub last_error: Option<String>,
+    pub withdrawal_id: u64,
+    pub tx_hash: H256,
+    pub event_index_in_tx: u32,
+}
+
+impl From<WebhookDelivery> for DeadWebhookDeliveryResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            id: delivery.id,
+            webhook_id: delivery.webhook_id,
+            url: delivery.url,
+            event: delivery.event.as_str().to_string(),
+            attempts: delivery.attempts,
+            last_error: delivery.last_error,
+            withdrawal_id: delivery.withdrawal.id,
+            tx_hash: delivery.withdrawal.tx_hash,
+            event_index_in_tx: delivery.withdrawal.event_index_in_tx,
+        }
+    }
 }
 
 impl From<UserWithdrawal> for WithdrawalResponse {
     fn from(withdrawal: UserWithdrawal) -> Self {
         Self {
+            id: withdrawal.id,
             tx_hash: withdrawal.tx_hash,
+            event_index_in_tx: withdrawal.event_index_in_tx,
             token: withdrawal.token,
             amount: withdrawal.amount,
-            status: format!("{:?}", withdrawal.status),
+            status: withdrawal.status.as_str().to_string(),
+            eta_secs: None,
         }
     }
 }
 
-pub async fn run_server(pool: PgPool) {
-    let cors_layer = CorsLayer::permissive();
-    let app = Router::new()
-        .route("/withdrawals/:from", get(get_withdrawals))
-        .route("/health", get(health))
-        .layer(cors_layer)
-        .with_state(pool);
+// Seconds since the epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn eta_secs(
+    model: &EtaModel,
+    status: FinalizationStatus,
+    commit_l1_block_number: Option<u64>,
+    seen_at: Option<u64>,
+) -> Option<u64> {
+    model
+        .eta(status, commit_l1_block_number, seen_at, now())
+        .map(|eta| eta.as_secs())
+}
+
+/// Run the API server.
+///
+/// Admin endpoints are only served if `admin` is configured.
+pub async fn run_server<M>(
+    pool: PgPool,
+    fee_budget: FeeBudget,
+    account: Address,
+    admin: Option<AdminConfig<M>>,
+    role: watch::Receiver<Role>,
+) where
+    M: ZksyncMiddleware + 'static,
+{
+    let (changes_tx, changes) = watch::channel(Default::default());
+
+    let app = router(
+        ApiState {
+            pool: pool.clone(),
+            fee_budget,
+            account,
+            role,
+            changes,
+            subscribers: Arc::new(Semaphore::new(MAX_SUBSCRIBERS)),
+        },
+        admin,
+    );
 
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+
+    tokio::select! {
+        r = axum::serve(listener, app) => r.unwrap(),
+        _ = events::listen_to_changes(pool, changes_tx) => (),
+    }
 }
 
-async fn health(State(pool): State<PgPool>) -> Result<&'static str, StatusCode> {
-    pool.acquire()
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    Ok("ok")
+fn router<M>(state: ApiState, admin: Option<AdminConfig<M>>) -> Router
+where
+    M: ZksyncMiddleware + 'static,
+{
+    let cors_layer = CorsLayer::permissive();
+    let mut app = Router::new()
+        .route("/withdrawals/:from", get(get_withdrawals))
+        .route("/withdrawals/:from/events", get(events::address_events))
+        .route(
+            "/withdrawal/:tx_hash/events",
+            get(events::transaction_events),
+        )
+        .route("/withdrawal/:tx_hash/:index", get(get_withdrawal))
+        .route("/given-up-withdrawals", get(get_given_up_withdrawals))
+        .route("/fee-budget", get(get_fee_budget))
+        .route("/stats/latency", get(get_latency_stats))
+        .route("/health", get(health));
+
+    if let Some(admin) = admin {
+        app = app.nest("/admin", admin_router(state.pool.clone(), admin));
+    }
+
+    app.layer(cors_layer).with_state(state)
+}
+
+fn admin_router<M, S>(pool: PgPool, admin: AdminConfig<M>) -> Router<S>
+where
+    M: ZksyncMiddleware + 'static,
+{
+    let state = AdminState {
+        pool,
+        token: admin.token.into(),
+        client_l2: admin.client_l2,
+    };
+
+    Router::new()
+        .route(
+            "/withdrawals/:tx_hash/:index/enqueue",
+            post(enqueue_withdrawal::<M>),
+        )
+        .route(
+            "/withdrawals/:tx_hash/:index/priority",
+            post(set_withdrawal_priority),
+        )
+        .route(
+            "/withdrawals/:tx_hash/:index/reset-attempts",
+            post(reset_finalization_attempts),
+        )
+        .route(
+            "/withdrawals/:tx_hash/:index/unfinalizable",
+            post(set_withdrawal_unfinalizable),
+        )
+        .route("/webhooks", get(get_webhooks).post(add_webhook))
+        .route("/webhooks/:id", delete(delete_webhook))
+        .route("/webhooks/dead-letters", get(get_dead_webhook_deliveries))
+        .route(
+            "/webhooks/dead-letters/:id/retry",
+            post(retry_dead_webhook_delivery),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            authenticate::<M>,
+        ))
+        .with_state(state)
+}
+
+async fn authenticate<M>(
+    State(state): State<AdminState<M>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let unauthorized = || ApiError::new(StatusCode::UNAUTHORIZED, "invalid admin token");
+
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(unauthorized)?;
+
+    if !constant_time_eq(token.as_bytes(), state.token.as_bytes()) {
+        return Err(unauthorized());
+    }
+
+    Ok(next.run(request).await)
+}
+
+// Compare secrets without leaking the length of the common prefix through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+// Id of a withdrawal known to the finalizer and whether its finalization parameters are stored.
+async fn withdrawal_id(
+    pool: &PgPool,
+    tx_hash: H256,
+    event_index_in_tx: u32,
+) -> Result<(u64, bool), ApiError> {
+    let key = WithdrawalKey {
+        tx_hash,
+        event_index_in_tx,
+    };
+
+    storage::withdrawal_id_by_key(pool, key)
+        .await?
+        .ok_or_else(withdrawal_not_found)
+}
+
+fn withdrawal_not_found() -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "withdrawal not found")
+}
+
+// Only withdrawals that have not been finalized yet may be changed.
+fn withdrawal_finalized() -> ApiError {
+    ApiError::new(
+        StatusCode::CONFLICT,
+        "withdrawal has already been finalized",
+    )
+}
+
+async fn enqueue_withdrawal<M>(
+    Path((tx_hash, index)): Path<(H256, u32)>,
+    State(state): State<AdminState<M>>,
+    Query(payload): Query<EnqueueRequest>,
+) -> Result<Json<AdminResponse>, ApiError>
+where
+    M: ZksyncMiddleware,
+{
+    let l2_request_failed = |_| {
+        ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            "failed to request withdrawal from L2",
+        )
+    };
+
+    let key = WithdrawalKey {
+        tx_hash,
+        event_index_in_tx: index,
+    };
+
+    // Withdrawals the watcher has not seen yet are fetched on demand.
+    if storage::withdrawal_id_by_key(&state.pool, key)
+        .await?
+        .is_none()
+    {
+        let event = state
+            .client_l2
+            .get_withdrawal_event(tx_hash, index as usize)
+            .await
+            .map_err(l2_request_failed)?
+            .ok_or_else(withdrawal_not_found)?;
+
+        let withdrawal = StoredWithdrawal {
+            event,
+            index_in_tx: index as usize,
+        };
+
+        storage::add_withdrawals(&state.pool, &[withdrawal]).await?;
+    }
+
+    let (id, has_data) = withdrawal_id(&state.pool, tx_hash, index).await?;
+
+    if !has_data {
+        let mut params = state
+            .client_l2
+            .finalize_withdrawal_params(tx_hash, index as usize)
+            .await
+            .map_err(l2_request_failed)?
+            .ok_or_else(withdrawal_not_found)?;
+        params.id = id;
+
+        storage::add_withdrawals_data(&state.pool, &[params]).await?;
+    }
+
+    let priority = payload.priority.unwrap_or(MANUAL_FINALIZATION_PRIORITY);
+
+    if !storage::enqueue_withdrawal(&state.pool, id, priority).await? {
+        return Err(withdrawal_finalized());
+    }
+
+    Ok(Json(AdminResponse { id }))
+}
+
+async fn set_withdrawal_priority(
+    Path((tx_hash, index)): Path<(H256, u32)>,
+    State(pool): State<PgPool>,
+    Query(payload): Query<PriorityRequest>,
+) -> Result<Json<AdminResponse>, ApiError> {
+    let (id, _) = withdrawal_id(&pool, tx_hash, index).await?;
+
+    if !storage::set_withdrawal_priority(&pool, id, payload.priority).await? {
+        return Err(withdrawal_finalized());
+    }
+
+    Ok(Json(AdminResponse { id }))
+}
+
+async fn reset_finalization_attempts(
+    Path((tx_hash, index)): Path<(H256, u32)>,
+    State(pool): State<PgPool>,
+) -> Result<Json<AdminResponse>, ApiError> {
+    let (id, _) = withdrawal_id(&pool, tx_hash, index).await?;
+
+    if !storage::reset_finalization_attempts(&pool, id).await? {
+        return Err(withdrawal_finalized());
+    }
+
+    Ok(Json(AdminResponse { id }))
+}
+
+async fn set_withdrawal_unfinalizable(
+    Path((tx_hash, index)): Path<(H256, u32)>,
+    State(pool): State<PgPool>,
+) -> Result<Json<AdminResponse>, ApiError> {
+    let (id, _) = withdrawal_id(&pool, tx_hash, index).await?;
+
+    if !storage::set_withdrawal_finalizable(&pool, id, false).await? {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "withdrawal has already been finalized or is unfinalizable",
+        ));
+    }
+
+    Ok(Json(AdminResponse { id }))
+}
+
+async fn add_webhook(
+    State(pool): State<PgPool>,
+    Json(payload): Json<WebhookRequest>,
+) -> Result<Json<AdminResponse>, ApiError> {
+    let url = url::Url::parse(&payload.url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "url must be an http(s) URL"))?;
+
+    if payload.secret.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "secret must not be empty",
+        ));
+    }
+
+    let id = storage::add_webhook(
+        &pool,
+        url.as_str(),
+        &payload.secret,
+        payload.address,
+        payload.token,
+    )
+    .await?;
+
+    Ok(Json(AdminResponse { id }))
+}
+
+async fn get_webhooks(State(pool): State<PgPool>) -> Result<Json<Vec<WebhookResponse>>, ApiError> {
+    let webhooks = storage::webhooks(&pool)
+        .await?
+        .into_iter()
+        .map(WebhookResponse::from)
+        .collect();
+
+    Ok(Json(webhooks))
+}
+
+async fn delete_webhook(
+    Path(id): Path<u64>,
+    State(pool): State<PgPool>,
+) -> Result<Json<AdminResponse>, ApiError> {
+    if !storage::delete_webhook(&pool, id).await? {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "webhook not found"));
+    }
+
+    Ok(Json(AdminResponse { id }))
+}
+
+async fn get_dead_webhook_deliveries(
+    State(pool): State<PgPool>,
+    Query(payload): Query<PageRequest>,
+) -> Result<Json<Vec<DeadWebhookDeliveryResponse>>, ApiError> {
+    let limit = page_limit(payload.limit)?;
+
+    let deliveries = storage::dead_webhook_deliveries(&pool, limit)
+        .await?
+        .into_iter()
+        .map(DeadWebhookDeliveryResponse::from)
+        .collect();
+
+    Ok(Json(deliveries))
+}
+
+async fn retry_dead_webhook_delivery(
+    Path(id): Path<u64>,
+    State(pool): State<PgPool>,
+) -> Result<Json<AdminResponse>, ApiError> {
+    if !storage::retry_dead_webhook_delivery(&pool, id).await? {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "dead-lettered delivery not found",
+        ));
+    }
+
+    Ok(Json(AdminResponse { id }))
+}
+
+async fn health(State(state): State<ApiState>) -> Result<Json<HealthResponse>, ApiError> {
+    state.pool.acquire().await.map_err(|_| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to connect to the database",
+        )
+    })?;
+
+    Ok(Json(HealthResponse {
+        status: "ok".to_string(),
+        role: *state.role.borrow(),
+    }))
+}
+
+// The requested page size, `DEFAULT_PAGE_SIZE` if none is requested.
+fn page_limit(limit: Option<u64>) -> Result<u64, ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("limit has to be between 1 and {MAX_PAGE_SIZE}"),
+        ));
+    }
+
+    Ok(limit)
 }
 
 async fn get_withdrawals(
     Path(from): Path<Address>,
     State(pool): State<PgPool>,
-    Query(payload): Query<WithdrawalRequest>,
-) -> Result<Json<Vec<WithdrawalResponse>>, StatusCode> {
-    let result: Vec<_> = storage::withdrawals_for_address(&pool, from, payload.limit)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    Query(payload): Query<WithdrawalsPageRequest>,
+) -> Result<Json<WithdrawalsPageResponse>, ApiError> {
+    let limit = page_limit(payload.limit)?;
+
+    let status = payload
+        .status
+        .map(|status| status.parse::<FinalizationStatus>())
+        .transpose()
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let filter = UserWithdrawalsFilter {
+        before_id: payload.cursor,
+        token: payload.token,
+        status,
+    };
+
+    let model = storage::eta_model(&pool, ETA_WINDOW).await?;
+
+    let withdrawals: Vec<_> = storage::withdrawals_for_address(&pool, from, &filter, limit)
+        .await?
+        .into_iter()
+        .map(|w| WithdrawalResponse {
+            eta_secs: eta_secs(&model, w.status, w.commit_l1_block_number, w.seen_at),
+            ..w.into()
+        })
+        .collect();
+
+    // A full page may be followed by more withdrawals.
+    let next_cursor = if withdrawals.len() as u64 == limit {
+        withdrawals.last().map(|w| w.id)
+    } else {
+        None
+    };
+
+    Ok(Json(WithdrawalsPageResponse {
+        withdrawals,
+        next_cursor,
+    }))
+}
+
+async fn get_withdrawal(
+    Path((tx_hash, index)): Path<(H256, u32)>,
+    State(pool): State<PgPool>,
+) -> Result<Json<WithdrawalDetailsResponse>, ApiError> {
+    let key = WithdrawalKey {
+        tx_hash,
+        event_index_in_tx: index,
+    };
+
+    let details = storage::withdrawal_details(&pool, key)
+        .await?
+        .ok_or_else(withdrawal_not_found)?;
+
+    let model = storage::eta_model(&pool, ETA_WINDOW).await?;
+
+    Ok(Json(WithdrawalDetailsResponse {
+        eta_secs: eta_secs(
+            &model,
+            details.status,
+            details.commit_l1_block_number,
+            details.seen_at,
+        ),
+        ..details.into()
+    }))
+}
+
+async fn get_latency_stats(
+    State(pool): State<PgPool>,
+    Query(payload): Query<LatencyStatsRequest>,
+) -> Result<Json<LatencyStatsResponse>, ApiError> {
+    let window = payload.window.map_or(ETA_WINDOW, Duration::from_secs);
+
+    if window.is_zero() || window > MAX_STATS_WINDOW {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "window has to be between 1 and {} seconds",
+                MAX_STATS_WINDOW.as_secs()
+            ),
+        ));
+    }
+
+    let tokens = storage::finalization_latencies(&pool, window)
+        .await?
+        .into_iter()
+        .map(TokenLatencyResponse::from)
+        .collect();
+
+    Ok(Json(LatencyStatsResponse {
+        window_secs: window.as_secs(),
+        tokens,
+    }))
+}
+
+async fn get_given_up_withdrawals(
+    State(pool): State<PgPool>,
+    Query(payload): Query<PageRequest>,
+) -> Result<Json<Vec<GivenUpWithdrawalResponse>>, ApiError> {
+    let limit = page_limit(payload.limit)?;
+
+    let result: Vec<_> = storage::given_up_withdrawals(&pool, limit)
+        .await?
         .into_iter()
-        .map(WithdrawalResponse::from)
+        .map(GivenUpWithdrawalResponse::from)
         .collect();
     Ok(Json(result))
 }
+
+async fn get_fee_budget(
+    State(state): State<ApiState>,
+) -> Result<Json<FeeBudgetResponse>, ApiError> {
+    let spent_last_hour = storage::fees_spent(&state.pool, state.account, FeeBudget::HOUR).await?;
+    let spent_last_day = storage::fees_spent(&state.pool, state.account, FeeBudget::DAY).await?;
+    let status = state.fee_budget.status(spent_last_hour, spent_last_day);
+
+    Ok(Json(FeeBudgetResponse {
+        tx_limit: state.fee_budget.tx_limit(),
+        hourly_limit: state.fee_budget.hourly_limit(),
+        daily_limit: state.fee_budget.daily_limit(),
+        spent_last_hour: status.spent_last_hour,
+        spent_last_day: status.spent_last_day,
+        remaining: status.remaining,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{header::AUTHORIZATION, Method, Request, StatusCode},
+        Router,
+    };
+    use ethers::{
+        abi::Address,
+        providers::{MockProvider, Provider},
+        types::H256,
+    };
+    use pretty_assertions::assert_eq;
+    use sqlx::PgPool;
+    use tokio::sync::{watch, Semaphore};
+    use tower::ServiceExt;
+    use tx_sender::FeeBudget;
+
+    use super::{AdminConfig, ApiState, ErrorResponse, Role, MAX_PAGE_SIZE, MAX_SUBSCRIBERS};
+
+    const TOKEN: &str = "token";
+
+    fn app(pool: PgPool) -> Router {
+        let state = ApiState {
+            pool,
+            fee_budget: FeeBudget::default(),
+            account: Address::zero(),
+            role: watch::channel(Role::Leader).1,
+            changes: watch::channel(Default::default()).1,
+            subscribers: Arc::new(Semaphore::new(MAX_SUBSCRIBERS)),
+        };
+        let admin = AdminConfig {
+            token: TOKEN.to_string(),
+            client_l2: Arc::new(Provider::new(MockProvider::new())),
+        };
+
+        super::router(state, Some(admin))
+    }
+
+    // Send a request and return the status and the error it has been responded with, if any.
+    async fn request(
+        pool: &PgPool,
+        method: Method,
+        uri: &str,
+        authorization: Option<&str>,
+    ) -> (StatusCode, Option<String>) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+
+        let response = app(pool.clone())
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let error = serde_json::from_slice::<ErrorResponse>(&body)
+            .ok()
+            .map(|e| e.error);
+
+        (status, error)
+    }
+
+    #[sqlx::test(migrations = "../storage/migrations")]
+    async fn admin_requests_require_bearer_token(pool: PgPool) {
+        for authorization in [
+            None,
+            Some("Bearer wrong"),
+            Some("token"),
+            Some("Basic token"),
+        ] {
+            assert_eq!(
+                request(&pool, Method::GET, "/admin/webhooks", authorization).await,
+                (
+                    StatusCode::UNAUTHORIZED,
+                    Some("invalid admin token".to_string())
+                )
+            );
+        }
+
+        let (status, _) =
+            request(&pool, Method::GET, "/admin/webhooks", Some("Bearer token")).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[sqlx::test(migrations = "../storage/migrations")]
+    async fn page_size_is_bounded(pool: PgPool) {
+        let withdrawals = format!("/withdrawals/{:?}", Address::zero());
+        let limit_error = format!("limit has to be between 1 and {MAX_PAGE_SIZE}");
+
+        for uri in [
+            format!("{withdrawals}?limit=0"),
+            format!("{withdrawals}?limit={}", MAX_PAGE_SIZE + 1),
+            "/given-up-withdrawals?limit=0".to_string(),
+            format!("/given-up-withdrawals?limit={}", MAX_PAGE_SIZE + 1),
+        ] {
+            assert_eq!(
+                request(&pool, Method::GET, &uri, None).await,
+                (StatusCode::BAD_REQUEST, Some(limit_error.clone()))
+            );
+        }
+
+        for uri in [
+            withdrawals.clone(),
+            format!("{withdrawals}?limit={MAX_PAGE_SIZE}"),
+            "/given-up-withdrawals?limit=1".to_string(),
+        ] {
+            let (status, _) = request(&pool, Method::GET, &uri, None).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+    }
+
+    #[sqlx::test(migrations = "../storage/migrations")]
+    async fn unknown_withdrawals_and_webhooks_are_not_found(pool: PgPool) {
+        let withdrawal = format!("{:?}/0", H256::from_low_u64_be(5));
+
+        let (status, _) = request(
+            &pool,
+            Method::GET,
+            &format!("/withdrawal/{withdrawal}"),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        for (method, uri) in [
+            (
+                Method::POST,
+                format!("/admin/withdrawals/{withdrawal}/priority?priority=1"),
+            ),
+            (
+                Method::POST,
+                format!("/admin/withdrawals/{withdrawal}/reset-attempts"),
+            ),
+            (
+                Method::POST,
+                format!("/admin/withdrawals/{withdrawal}/unfinalizable"),
+            ),
+            (Method::DELETE, "/admin/webhooks/1".to_string()),
+            (
+                Method::POST,
+                "/admin/webhooks/dead-letters/1/retry".to_string(),
+            ),
+        ] {
+            let (status, _) = request(&pool, method, &uri, Some("Bearer token")).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+        }
+    }
+}
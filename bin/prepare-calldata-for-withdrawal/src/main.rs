@@ -29,6 +29,17 @@ async fn main() {
 
     let pool = PgPool::connect(&args.database_url).await.unwrap();
 
+    let status = storage::withdrawal_status(&pool, args.withdrawal_id)
+        .await
+        .unwrap()
+        .expect("withdrawal is not known");
+
+    println!("withdrawal status is {}", status.as_str());
+
+    if status.is_finalized() {
+        println!("warning: withdrawal has already been finalized");
+    }
+
     let request_finalize_withdrawal =
         storage::get_finalize_withdrawal_params(&pool, args.withdrawal_id, args.gas)
             .await
@@ -0,0 +1,256 @@
+//! Listening to the events on both chains and storing them.
+
+use std::sync::{atomic::AtomicU64, Arc};
+
+use chain_events::{BlockEvents, L1Confirmations, L2EventsListener};
+use client::ZksyncMiddleware;
+use ethers::{
+    providers::{Http, JsonRpcClient, Middleware, Provider},
+    types::Address,
+};
+use eyre::{anyhow, Result};
+use sqlx::{PgConnection, PgPool};
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+use watcher::Watcher;
+
+use crate::{join_within, metrics::MAIN_FINALIZER_METRICS};
+
+const CHANNEL_CAPACITY: usize = 1024 * 16;
+
+/// Everything needed to (re)start the listeners of the chains and the watcher.
+pub struct ChainWatcher {
+    pub pgpool: PgPool,
+    pub client_l1: Arc<Provider<Http>>,
+    pub client_l2: Arc<Provider<Http>>,
+    pub l1_ws_url: String,
+    pub l2_ws_url: String,
+    pub l1_confirmations: Option<L1Confirmations>,
+    pub confirmed_l1_block: Arc<AtomicU64>,
+    pub diamond_proxy_addr: Address,
+    pub l2_erc20_bridge_addr: Address,
+    pub token_deployer_addrs: Vec<Address>,
+    pub custom_tokens: Vec<Address>,
+    pub finalize_eth_token: bool,
+    pub meter_withdrawals: bool,
+}
+
+impl ChainWatcher {
+    /// Run the listeners and the watcher until one of them ends.
+    ///
+    /// The blocks to start from are determined by the database
+    /// unless `configured_l2_block` is given. On `shutdown` the listeners
+    /// are stopped and the watcher is given `shutdown_timeout` to store
+    /// the events it has accumulated.
+    pub async fn run(
+        &self,
+        configured_l2_block: Option<u64>,
+        shutdown: &CancellationToken,
+        shutdown_timeout: Duration,
+    ) -> Result<()> {
+        let from_l2_block = start_from_l2_block(
+            self.client_l2.clone(),
+            &mut self.pgpool.acquire().await?.detach(),
+            configured_l2_block,
+        )
+        .await?;
+
+        tracing::info!("Starting from L2 block number {from_l2_block}");
+
+        let from_l1_block = start_from_l1_block(
+            self.client_l1.clone(),
+            self.client_l2.clone(),
+            &mut self.pgpool.acquire().await?.detach(),
+        )
+        .await?;
+
+        tracing::info!("Starting from L1 block number {from_l1_block}");
+
+        let (blocks_tx, blocks_rx) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
+
+        let blocks_tx_wrapped = tokio_util::sync::PollSender::new(blocks_tx.clone());
+        let blocks_rx = tokio_stream::wrappers::ReceiverStream::new(blocks_rx);
+
+        let (we_tx, we_rx) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
+
+        let we_tx_wrapped = tokio_util::sync::PollSender::new(we_tx.clone());
+        let we_rx = tokio_stream::wrappers::ReceiverStream::new(we_rx);
+
+        let l1_block_hashes =
+            storage::l1_block_hashes(&mut self.pgpool.acquire().await?.detach()).await?;
+
+        let mut event_mux = BlockEvents::new(&self.l1_ws_url)
+            .with_l1_block_hashes(l1_block_hashes)
+            .with_confirmed_l1_block(self.confirmed_l1_block.clone());
+
+        if let Some(l1_confirmations) = self.l1_confirmations {
+            tracing::info!("acting on L1 blocks with {l1_confirmations:?} confirmations");
+            event_mux = event_mux.with_l1_confirmations(l1_confirmations);
+        }
+
+        let (mut tokens, last_token_seen_at_block) = storage::get_tokens(&self.pgpool).await?;
+
+        tokens.extend_from_slice(&self.custom_tokens);
+
+        tracing::info!("tokens {tokens:?}");
+
+        let l2_events = L2EventsListener::new(
+            &self.l2_ws_url,
+            self.token_deployer_addrs.clone(),
+            tokens.into_iter().collect(),
+            self.finalize_eth_token,
+        );
+
+        let watcher = Watcher::new(
+            self.client_l2.clone(),
+            self.pgpool.clone(),
+            self.meter_withdrawals,
+        );
+
+        let mut withdrawal_events_handle = tokio::spawn(l2_events.run_with_reconnects(
+            from_l2_block,
+            last_token_seen_at_block,
+            we_tx_wrapped,
+        ));
+
+        let mut watcher_handle = tokio::spawn(watcher.run(blocks_rx, we_rx, from_l2_block));
+
+        let mut block_events_handle = tokio::spawn(event_mux.run_with_reconnects(
+            self.diamond_proxy_addr,
+            self.l2_erc20_bridge_addr,
+            from_l1_block,
+            blocks_tx_wrapped,
+        ));
+
+        let channel_metrics_handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(5));
+            loop {
+                interval.tick().await;
+                MAIN_FINALIZER_METRICS
+                    .watcher_l1_channel_capacity
+                    .set(blocks_tx.capacity() as i64);
+                MAIN_FINALIZER_METRICS
+                    .watcher_l2_channel_capacity
+                    .set(we_tx.capacity() as i64);
+            }
+        });
+
+        let ended = tokio::select! {
+            r = &mut block_events_handle => anyhow!("Block Events stream ended with {r:?}"),
+            r = &mut withdrawal_events_handle => {
+                anyhow!("Withdrawals Events stream ended with {r:?}")
+            }
+            r = &mut watcher_handle => anyhow!("Watcher ended with {r:?}"),
+            _ = shutdown.cancelled() => {
+                // Dropping the senders of the events ends the streams of the watcher,
+                // it then flushes the events accumulated so far.
+                block_events_handle.abort();
+                withdrawal_events_handle.abort();
+                channel_metrics_handle.abort();
+
+                join_within("Watcher", watcher_handle, shutdown_timeout).await;
+
+                return Ok(());
+            }
+        };
+
+        // The rest is started again on restart.
+        block_events_handle.abort();
+        withdrawal_events_handle.abort();
+        watcher_handle.abort();
+        channel_metrics_handle.abort();
+
+        Err(ended)
+    }
+}
+
+async fn start_from_l1_block<M1, M2>(
+    client_l1: Arc<M1>,
+    client_l2: Arc<M2>,
+    conn: &mut PgConnection,
+) -> Result<u64>
+where
+    M1: Middleware,
+    <M1 as Middleware>::Provider: JsonRpcClient,
+    M2: Middleware,
+    <M2 as Middleware>::Provider: JsonRpcClient,
+{
+    match (
+        storage::last_l2_to_l1_events_block_seen(conn).await?,
+        storage::last_l1_block_seen(conn).await?,
+    ) {
+        (Some(b1), Some(b2)) => Ok(std::cmp::min(b1, b2)),
+        (b1, b2) => {
+            if b1.is_none() {
+                tracing::info!(concat!(
+                    "information about l2 to l1 events is missing, ",
+                    "starting from L1 block corresponding to L2 block 1"
+                ));
+            }
+
+            if b2.is_none() {
+                tracing::info!(concat!(
+                    "information about last block seen is missing, ",
+                    "starting from L1 block corresponding to L2 block 1"
+                ));
+            }
+
+            let block_details = client_l2
+                .provider()
+                .get_block_details(1)
+                .await?
+                .expect("Always start from the block that there is info about; qed");
+
+            let commit_tx_hash = block_details
+                .commit_tx_hash
+                .expect("A first block on L2 is always committed; qed");
+
+            let commit_tx = client_l1
+                .get_transaction(commit_tx_hash)
+                .await
+                .map_err(|e| anyhow!("{e}"))?
+                .expect("The corresponding L1 tx exists; qed");
+
+            let commit_tx_block_number = commit_tx
+                .block_number
+                .expect("Already mined TX always has a block number; qed")
+                .as_u64();
+
+            Ok(commit_tx_block_number)
+        }
+    }
+}
+
+// Determine an L2 block to start processing withdrawals from.
+//
+// The priority is:
+// 1. Config variable `start_from_l2_block`. If not present:
+// 2. The block of last seen withdrawal event decremented by one. If not present:
+// 3. Last finalized block on L2.
+async fn start_from_l2_block<M: Middleware>(
+    client: Arc<M>,
+    conn: &mut PgConnection,
+    configured_l2_block: Option<u64>,
+) -> Result<u64> {
+    let res = match configured_l2_block {
+        Some(l2_block) => l2_block,
+        None => {
+            if let Some(block_number) = storage::last_l2_block_seen(conn).await? {
+                // May have stored not the last withdrawal event in `block_number`
+                // so to be sure, re-start from the previous block.
+                block_number - 1
+            } else {
+                client
+                    .get_block(1)
+                    .await
+                    .map_err(|err| anyhow!("{err}"))?
+                    .expect("The genesis block always exists; qed")
+                    .number
+                    .expect("The genesis block number is always known; qed")
+                    .as_u64()
+            }
+        }
+    };
+
+    Ok(res)
+}
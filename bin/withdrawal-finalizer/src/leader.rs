@@ -0,0 +1,98 @@
+//! Leader election among the instances sharing the database.
+
+use std::time::Duration;
+
+use api::Role;
+use eyre::{anyhow, Result};
+use sqlx::{postgres::PgConnectOptions, ConnectOptions, PgConnection, PgPool};
+use tokio::sync::watch;
+
+use crate::metrics::MAIN_FINALIZER_METRICS;
+
+// How often standby instances try to take over and the leader checks its lock.
+const LEADERSHIP_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+// The leader steps down if its lock connection does not answer in time.
+//
+// Together with the check interval it is shorter than the time the server takes
+// to notice a dead connection, so the leader steps down before a standby takes over.
+const LEADERSHIP_PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Leadership held through a session advisory lock on a dedicated connection.
+///
+/// The lock is released by the database once the connection is gone.
+/// Server-side keepalives of the connection are tightened for the server
+/// to notice the death of the leader within seconds, the leader itself
+/// notices a dead connection by the lock pings timing out.
+pub struct Leadership {
+    conn: PgConnection,
+}
+
+impl Leadership {
+    /// Wait until this instance becomes the leader of the `group`.
+    ///
+    /// While waiting connections of `pool` are kept warm.
+    pub async fn acquire(
+        options: &PgConnectOptions,
+        pool: &PgPool,
+        group: &str,
+        role: &watch::Sender<Role>,
+    ) -> Result<Self> {
+        // These are settings of the server end of the connection, sqlx
+        // does not configure keepalives of the client socket.
+        let options = options.clone().options([
+            ("tcp_keepalives_idle", "5"),
+            ("tcp_keepalives_interval", "1"),
+            ("tcp_keepalives_count", "3"),
+        ]);
+
+        let mut conn = options.connect().await?;
+
+        role.send_replace(Role::Standby);
+        MAIN_FINALIZER_METRICS.is_leader.set(0);
+
+        while !storage::try_acquire_leadership(&mut conn, group).await? {
+            tracing::debug!("another instance is the leader, standing by");
+
+            let pinged = match pool.acquire().await {
+                Ok(mut conn) => storage::ping(&mut conn).await.map_err(|e| anyhow!("{e}")),
+                Err(e) => Err(anyhow!("{e}")),
+            };
+
+            if let Err(e) = pinged {
+                tracing::warn!("failed to ping the database while standing by: {e}");
+            }
+
+            tokio::time::sleep(LEADERSHIP_CHECK_INTERVAL).await;
+        }
+
+        tracing::info!("this instance has become the leader of {group:?}");
+
+        role.send_replace(Role::Leader);
+        MAIN_FINALIZER_METRICS.is_leader.set(1);
+
+        Ok(Self { conn })
+    }
+
+    /// Hold the leadership, returns the reason once it may have been lost.
+    pub async fn hold(mut self) -> eyre::Report {
+        let e = loop {
+            tokio::time::sleep(LEADERSHIP_CHECK_INTERVAL).await;
+
+            match tokio::time::timeout(LEADERSHIP_PING_TIMEOUT, storage::ping(&mut self.conn)).await
+            {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => break anyhow!("lost the leader lock connection: {e}"),
+                Err(_) => {
+                    break anyhow!(
+                        "the leader lock connection has not answered within {LEADERSHIP_PING_TIMEOUT:?}"
+                    )
+                }
+            }
+        };
+
+        MAIN_FINALIZER_METRICS.is_leader.set(0);
+
+        e
+    }
+}
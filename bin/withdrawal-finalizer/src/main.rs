@@ -5,34 +5,55 @@
 
 //! A withdraw-finalizer
 
-use std::{str::FromStr, sync::Arc, time::Duration};
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::{atomic::AtomicU64, Arc},
+    time::Duration,
+};
 
+use clap::Parser;
 use envconfig::Envconfig;
 use ethers::{
     prelude::SignerMiddleware,
-    providers::{Http, JsonRpcClient, Middleware, Provider},
-    signers::LocalWallet,
+    providers::{Http, Provider},
+    signers::{LocalWallet, Signer},
     types::U256,
 };
 use eyre::{anyhow, Result};
+use finalizer::{supervise, JsonFilePriceSource, ProfitabilityFilter, RestartPolicy};
 use sqlx::{
     postgres::{PgConnectOptions, PgPoolOptions},
-    ConnectOptions, PgConnection,
+    ConnectOptions,
 };
 
-use chain_events::{BlockEvents, L2EventsListener};
-use client::{l1bridge::codegen::IL1Bridge, zksync_contract::codegen::IZkSync, ZksyncMiddleware};
+use api::Role;
+use client::{l1bridge::codegen::IL1Bridge, zksync_contract::codegen::IZkSync};
 use config::Config;
-use tokio::sync::watch;
+use storage::{Lease, RetryPolicy};
+use tokio::{
+    signal::unix::SignalKind,
+    sync::watch,
+    task::{JoinError, JoinHandle},
+};
+use tokio_util::sync::CancellationToken;
+use tx_sender::{FeeBudget, FeeHistoryStrategy};
 use vise_exporter::MetricsExporter;
-use watcher::Watcher;
 
-use crate::metrics::MAIN_FINALIZER_METRICS;
+use crate::{chain_watcher::ChainWatcher, leader::Leadership};
 
+mod chain_watcher;
 mod config;
+mod leader;
 mod metrics;
 
-const CHANNEL_CAPACITY: usize = 1024 * 16;
+const DEFAULT_LEADER_ELECTION_GROUP: &str = "default";
+
+// Withdrawals are leased for long enough to be simulated and sent.
+const DEFAULT_WITHDRAWAL_LEASE_SECS: u64 = 300;
+
+// Fits into the default termination grace period of Kubernetes.
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 25;
 
 fn run_vise_exporter() -> Result<watch::Sender<()>> {
     let (shutdown_sender, mut shutdown_receiver) = watch::channel(());
@@ -45,95 +66,58 @@ fn run_vise_exporter() -> Result<watch::Sender<()>> {
     Ok(shutdown_sender)
 }
 
-async fn start_from_l1_block<M1, M2>(
-    client_l1: Arc<M1>,
-    client_l2: Arc<M2>,
-    conn: &mut PgConnection,
-) -> Result<u64>
+// Cancel the returned token on the first SIGTERM or SIGINT.
+fn shutdown_on_signal() -> Result<CancellationToken> {
+    let shutdown = CancellationToken::new();
+
+    let mut sigterm = tokio::signal::unix::signal(SignalKind::terminate())?;
+    let mut sigint = tokio::signal::unix::signal(SignalKind::interrupt())?;
+
+    let token = shutdown.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = sigterm.recv() => tracing::info!("received SIGTERM, shutting down"),
+            _ = sigint.recv() => tracing::info!("received SIGINT, shutting down"),
+        }
+
+        token.cancel();
+    });
+
+    Ok(shutdown)
+}
+
+// Wait for a task to end for at most `timeout`.
+// The error the process exits with once a component has ended.
+fn component_error<E>(
+    name: &str,
+    r: std::result::Result<std::result::Result<(), E>, JoinError>,
+) -> eyre::Report
 where
-    M1: Middleware,
-    <M1 as Middleware>::Provider: JsonRpcClient,
-    M2: Middleware,
-    <M2 as Middleware>::Provider: JsonRpcClient,
+    E: std::error::Error + Send + Sync + 'static,
 {
-    match (
-        storage::last_l2_to_l1_events_block_seen(conn).await?,
-        storage::last_l1_block_seen(conn).await?,
-    ) {
-        (Some(b1), Some(b2)) => Ok(std::cmp::min(b1, b2)),
-        (b1, b2) => {
-            if b1.is_none() {
-                tracing::info!(concat!(
-                    "information about l2 to l1 events is missing, ",
-                    "starting from L1 block corresponding to L2 block 1"
-                ));
-            }
-
-            if b2.is_none() {
-                tracing::info!(concat!(
-                    "information about last block seen is missing, ",
-                    "starting from L1 block corresponding to L2 block 1"
-                ));
-            }
-
-            let block_details = client_l2
-                .provider()
-                .get_block_details(1)
-                .await?
-                .expect("Always start from the block that there is info about; qed");
-
-            let commit_tx_hash = block_details
-                .commit_tx_hash
-                .expect("A first block on L2 is always committed; qed");
-
-            let commit_tx = client_l1
-                .get_transaction(commit_tx_hash)
-                .await
-                .map_err(|e| anyhow!("{e}"))?
-                .expect("The corresponding L1 tx exists; qed");
-
-            let commit_tx_block_number = commit_tx
-                .block_number
-                .expect("Already mined TX always has a block number; qed")
-                .as_u64();
-
-            Ok(commit_tx_block_number)
-        }
+    match r {
+        Ok(Ok(())) => anyhow!("{name} has ended"),
+        Ok(Err(e)) => eyre::Report::new(e).wrap_err(format!("{name} has failed")),
+        Err(e) => anyhow!("{name} has panicked: {e}"),
     }
 }
 
-// Determine an L2 block to start processing withdrawals from.
-//
-// The priority is:
-// 1. Config variable `start_from_l2_block`. If not present:
-// 2. The block of last seen withdrawal event decremented by one. If not present:
-// 3. Last finalized block on L2.
-async fn start_from_l2_block<M: Middleware>(
-    client: Arc<M>,
-    conn: &mut PgConnection,
-    config: &Config,
-) -> Result<u64> {
-    let res = match config.start_from_l2_block {
-        Some(l2_block) => l2_block,
-        None => {
-            if let Some(block_number) = storage::last_l2_block_seen(conn).await? {
-                // May have stored not the last withdrawal event in `block_number`
-                // so to be sure, re-start from the previous block.
-                block_number - 1
-            } else {
-                client
-                    .get_block(1)
-                    .await
-                    .map_err(|err| anyhow!("{err}"))?
-                    .expect("The genesis block always exists; qed")
-                    .number
-                    .expect("The genesis block number is always known; qed")
-                    .as_u64()
-            }
-        }
-    };
+async fn join_within<T: std::fmt::Debug>(name: &str, handle: JoinHandle<T>, timeout: Duration) {
+    match tokio::time::timeout(timeout, handle).await {
+        Ok(r) => tracing::info!("{name} has shut down with {r:?}"),
+        Err(_) => tracing::warn!("{name} has not shut down within {timeout:?}"),
+    }
+}
 
-    Ok(res)
+#[derive(Parser, Debug)]
+struct Args {
+    /// TOML or YAML config file, variables set in env take precedence
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Print the effective config with secrets redacted and exit
+    #[arg(long)]
+    print_config: bool,
 }
 
 #[tokio::main]
@@ -141,7 +125,20 @@ async fn main() -> Result<()> {
     color_eyre::install()?;
 
     dotenvy::dotenv().ok();
-    let config = Config::init_from_env()?;
+    let args = Args::parse();
+
+    let config = match args.config {
+        Some(ref path) => Config::from_file(path)?,
+        None => Config::init_from_env()?,
+    };
+
+    if args.print_config {
+        let valid = config.validate();
+        println!("{:#?}", config.redacted());
+        return valid;
+    }
+
+    config.validate()?;
 
     let sentry_guard = vlog::init();
 
@@ -158,6 +155,24 @@ async fn main() -> Result<()> {
 
     client::add_predefined_token_addrs(config.token_mappings().as_ref()).await;
     let stop_vise_exporter = run_vise_exporter()?;
+    let shutdown = shutdown_on_signal()?;
+    let shutdown_timeout = Duration::from_secs(
+        config
+            .shutdown_timeout_secs
+            .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECS),
+    );
+
+    let mut restart_policy = RestartPolicy::default();
+
+    if let Some(max_component_restarts) = config.max_component_restarts {
+        restart_policy.max_restarts = max_component_restarts;
+    }
+
+    if let Some(component_restarts_window) = config.component_restarts_window {
+        restart_policy.window = Duration::from_secs(component_restarts_window);
+    }
+
+    tracing::info!("component restart policy {restart_policy:?}");
 
     // Successful reconnections do not reset the reconnection count trackers in the
     // `ethers-rs`. In the logic of reconnections have to happen as long
@@ -171,102 +186,128 @@ async fn main() -> Result<()> {
 
     let client_l2 = Arc::new(provider_l2);
 
-    let event_mux = BlockEvents::new(config.eth_client_ws_url.as_ref());
-    let (blocks_tx, blocks_rx) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
-
-    let blocks_tx_wrapped = tokio_util::sync::PollSender::new(blocks_tx.clone());
-    let blocks_rx = tokio_stream::wrappers::ReceiverStream::new(blocks_rx);
-
     let options =
         PgConnectOptions::from_str(config.database_url.as_str())?.disable_statement_logging();
 
     let pgpool = PgPoolOptions::new()
         .acquire_timeout(Duration::from_secs(2))
-        .connect_with(options)
+        .connect_with(options.clone())
         .await?;
 
-    let from_l2_block = start_from_l2_block(
-        client_l2.clone(),
-        &mut pgpool.acquire().await?.detach(),
-        &config,
-    )
-    .await?;
+    let wallet = config.account_private_key.parse::<LocalWallet>()?;
+    let finalizer_account_address = wallet.address();
 
-    tracing::info!("Starting from L2 block number {from_l2_block}");
+    let mut fee_budget = FeeBudget::default();
 
-    let (we_tx, we_rx) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
+    if let Some(ref tx_fee_limit) = config.tx_fee_limit {
+        fee_budget = fee_budget.with_tx_limit(ethers::utils::parse_ether(tx_fee_limit)?);
+    }
 
-    let we_tx_wrapped = tokio_util::sync::PollSender::new(we_tx.clone());
-    let we_rx = tokio_stream::wrappers::ReceiverStream::new(we_rx);
+    if let Some(ref hourly_fee_limit) = config.hourly_fee_limit {
+        fee_budget = fee_budget.with_hourly_limit(ethers::utils::parse_ether(hourly_fee_limit)?);
+    }
 
-    let from_l1_block = start_from_l1_block(
-        client_l1.clone(),
-        client_l2.clone(),
-        &mut pgpool.acquire().await?.detach(),
-    )
-    .await?;
+    if let Some(ref daily_fee_limit) = config.daily_fee_limit {
+        fee_budget = fee_budget.with_daily_limit(ethers::utils::parse_ether(daily_fee_limit)?);
+    }
 
-    tracing::info!("Starting from L1 block number {from_l1_block}");
+    tracing::info!("fee budget {fee_budget:?}");
+
+    let admin = config
+        .admin_api_token
+        .clone()
+        .map(|token| api::AdminConfig {
+            token,
+            client_l2: client_l2.clone(),
+        });
+
+    // The API is served by standby instances too.
+    let (role_sender, role) = watch::channel(Role::Standby);
+
+    let api_server = tokio::spawn(supervise("api", restart_policy, shutdown.clone(), {
+        let pgpool = pgpool.clone();
+        let fee_budget = fee_budget.clone();
+        move || {
+            api::run_server(
+                pgpool.clone(),
+                fee_budget.clone(),
+                finalizer_account_address,
+                admin.clone(),
+                role.clone(),
+            )
+        }
+    }));
 
-    let (mut tokens, last_token_seen_at_block) = storage::get_tokens(&pgpool.clone()).await?;
+    // Only the leader watches the chains and finalizes withdrawals.
+    let leader_election_group = config
+        .leader_election_group
+        .clone()
+        .unwrap_or_else(|| DEFAULT_LEADER_ELECTION_GROUP.to_string());
 
-    if let Some(ref custom_tokens) = config.custom_token_addresses {
-        tokens.extend_from_slice(custom_tokens.0.as_slice());
-    }
+    let leadership = tokio::select! {
+        leadership = Leadership::acquire(&options, &pgpool, &leader_election_group, &role_sender) => {
+            leadership?
+        }
+        _ = shutdown.cancelled() => {
+            stop_vise_exporter.send_replace(());
+            return Ok(());
+        }
+    };
 
-    tracing::info!("tokens {tokens:?}");
-    if let Some(ref custom_tokens) = config.custom_token_deployer_addresses {
-        tokens.extend_from_slice(custom_tokens.0.as_slice());
-    }
+    let confirmed_l1_block = Arc::new(AtomicU64::default());
 
-    let l2_events = L2EventsListener::new(
-        config.api_web3_json_rpc_ws_url.as_str(),
-        config
-            .custom_token_deployer_addresses
-            .map(|list| list.0)
-            .unwrap_or(vec![config.l2_erc20_bridge_addr]),
-        tokens.into_iter().collect(),
-        config.finalize_eth_token.unwrap_or(true),
-    );
+    let mut custom_tokens = vec![];
 
-    let l1_bridge = IL1Bridge::new(config.l1_erc20_bridge_proxy_addr, client_l1.clone());
+    if let Some(ref custom_tokens_addresses) = config.custom_token_addresses {
+        custom_tokens.extend_from_slice(custom_tokens_addresses.0.as_slice());
+    }
 
-    let zksync_contract = IZkSync::new(config.diamond_proxy_addr, client_l1.clone());
+    if let Some(ref custom_tokens_deployers) = config.custom_token_deployer_addresses {
+        custom_tokens.extend_from_slice(custom_tokens_deployers.0.as_slice());
+    }
 
     // by default meter withdrawals
     let meter_withdrawals = config.enable_withdrawal_metering.unwrap_or(true);
 
-    let watcher = Watcher::new(client_l2.clone(), pgpool.clone(), meter_withdrawals);
-
-    let withdrawal_events_handle = tokio::spawn(l2_events.run_with_reconnects(
-        from_l2_block,
-        last_token_seen_at_block,
-        we_tx_wrapped,
-    ));
+    let chain_watcher = ChainWatcher {
+        pgpool: pgpool.clone(),
+        client_l1: client_l1.clone(),
+        client_l2: client_l2.clone(),
+        l1_ws_url: config.eth_client_ws_url.to_string(),
+        l2_ws_url: config.api_web3_json_rpc_ws_url.to_string(),
+        l1_confirmations: config.l1_confirmations,
+        confirmed_l1_block: confirmed_l1_block.clone(),
+        diamond_proxy_addr: config.diamond_proxy_addr,
+        l2_erc20_bridge_addr: config.l2_erc20_bridge_addr,
+        token_deployer_addrs: config
+            .custom_token_deployer_addresses
+            .as_ref()
+            .map(|list| list.0.clone())
+            .unwrap_or(vec![config.l2_erc20_bridge_addr]),
+        custom_tokens,
+        finalize_eth_token: config.finalize_eth_token.unwrap_or(true),
+        meter_withdrawals,
+    };
 
-    let watcher_handle = tokio::spawn(watcher.run(blocks_rx, we_rx, from_l2_block));
+    // The configured L2 block is only started from once, restarts resume from the database.
+    let mut start_from_l2_block = config.start_from_l2_block;
+    let watcher_shutdown = shutdown.clone();
+    let mut watcher_handle = tokio::spawn(async move {
+        supervise("watcher", restart_policy, watcher_shutdown.clone(), || {
+            chain_watcher.run(
+                start_from_l2_block.take(),
+                &watcher_shutdown,
+                shutdown_timeout,
+            )
+        })
+        .await
+    });
 
-    let block_events_handle = tokio::spawn(event_mux.run_with_reconnects(
-        config.diamond_proxy_addr,
-        config.l2_erc20_bridge_addr,
-        from_l1_block,
-        blocks_tx_wrapped,
-    ));
+    let confirmed_l1_block = config.l1_confirmations.map(|_| confirmed_l1_block);
 
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(Duration::from_secs(5));
-        loop {
-            interval.tick().await;
-            MAIN_FINALIZER_METRICS
-                .watcher_l1_channel_capacity
-                .set(blocks_tx.capacity() as i64);
-            MAIN_FINALIZER_METRICS
-                .watcher_l2_channel_capacity
-                .set(we_tx.capacity() as i64);
-        }
-    });
+    let l1_bridge = IL1Bridge::new(config.l1_erc20_bridge_proxy_addr, client_l1.clone());
 
-    let wallet = config.account_private_key.parse::<LocalWallet>()?;
+    let zksync_contract = IZkSync::new(config.diamond_proxy_addr, client_l1.clone());
 
     let client_l1_with_signer = Arc::new(
         SignerMiddleware::new_with_provider_chain(client_l1, wallet)
@@ -274,18 +315,21 @@ async fn main() -> Result<()> {
             .unwrap(),
     );
 
-    let finalizer_account_address = client_l1_with_signer.address();
-
     let contract = client::withdrawal_finalizer::codegen::WithdrawalFinalizer::new(
         config.withdrawal_finalizer_addr,
         client_l1_with_signer,
     );
     let batch_finalization_gas_limit = U256::from_dec_str(&config.batch_finalization_gas_limit)?;
     let one_withdrawal_gas_limit = U256::from_dec_str(&config.one_withdrawal_gas_limit)?;
+    let max_withdrawal_gas_limit = match config.max_withdrawal_gas_limit {
+        Some(ref max_withdrawal_gas_limit) => U256::from_dec_str(max_withdrawal_gas_limit)?,
+        None => one_withdrawal_gas_limit,
+    };
 
     tracing::info!(
-        "finalization gas limits one: {}, batch: {}",
+        "finalization gas limits one: {}, max one: {}, batch: {}",
         config.one_withdrawal_gas_limit,
+        max_withdrawal_gas_limit,
         config.batch_finalization_gas_limit,
     );
 
@@ -295,6 +339,84 @@ async fn main() -> Result<()> {
         }
         None => None,
     };
+
+    let mut fee_strategy = FeeHistoryStrategy::default();
+
+    if let Some(ref max_fee_per_gas) = config.max_fee_per_gas {
+        let max_fee_per_gas = ethers::utils::parse_units(max_fee_per_gas, "gwei")?.into();
+        tracing::info!("max fee per gas {max_fee_per_gas}");
+        fee_strategy = fee_strategy.with_max_fee_per_gas(max_fee_per_gas);
+    }
+
+    if let Some(priority_fee_percentile) = config.priority_fee_percentile {
+        fee_strategy = fee_strategy.with_reward_percentile(priority_fee_percentile);
+    }
+
+    if let Some(max_fee_replacements) = config.max_fee_replacements {
+        fee_strategy = fee_strategy.with_max_replacements(max_fee_replacements);
+    }
+
+    let mut profitability_filter =
+        ProfitabilityFilter::new(config.token_finalization_thresholds.unwrap_or_default());
+
+    match (config.min_withdrawal_usd_value, config.token_prices_file) {
+        (Some(min_withdrawal_usd_value), Some(token_prices_file)) => {
+            tracing::info!(
+                "skipping withdrawals worth less than {min_withdrawal_usd_value} USD by prices in {token_prices_file}"
+            );
+            profitability_filter = profitability_filter.with_min_usd_value(
+                min_withdrawal_usd_value,
+                Box::new(JsonFilePriceSource::new(token_prices_file)),
+            );
+        }
+        (Some(_), None) => {
+            return Err(anyhow!(
+                "MIN_WITHDRAWAL_USD_VALUE requires TOKEN_PRICES_FILE to be set"
+            ));
+        }
+        _ => (),
+    }
+
+    let dry_run = config.dry_run.unwrap_or(false);
+
+    if dry_run {
+        tracing::info!("running in dry run mode, no transactions are going to be sent");
+    }
+
+    let mut retry_policy = RetryPolicy::default();
+
+    if let Some(max_finalization_attempts) = config.max_finalization_attempts {
+        retry_policy.max_attempts = max_finalization_attempts;
+    }
+
+    if let Some(finalization_retry_backoff) = config.finalization_retry_backoff {
+        retry_policy.initial_backoff = Duration::from_secs(finalization_retry_backoff);
+    }
+
+    if let Some(finalization_retry_max_backoff) = config.finalization_retry_max_backoff {
+        retry_policy.max_backoff = Duration::from_secs(finalization_retry_max_backoff);
+    }
+
+    if let Some(finalization_retry_jitter) = config.finalization_retry_jitter {
+        retry_policy.jitter = finalization_retry_jitter;
+    }
+
+    tracing::info!("finalization retry policy {retry_policy:?}");
+
+    let lease = Lease {
+        holder: config.finalizer_instance_id.clone().unwrap_or_else(|| {
+            let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "withdrawal-finalizer".into());
+            format!("{host}-{}", std::process::id())
+        }),
+        duration: Duration::from_secs(
+            config
+                .withdrawal_lease_secs
+                .unwrap_or(DEFAULT_WITHDRAWAL_LEASE_SECS),
+        ),
+    };
+
+    tracing::info!("leasing withdrawals as {lease:?}");
+
     let finalizer = finalizer::Finalizer::new(
         pgpool.clone(),
         one_withdrawal_gas_limit,
@@ -307,38 +429,93 @@ async fn main() -> Result<()> {
         config.tokens_to_finalize.unwrap_or_default(),
         meter_withdrawals,
         eth_finalization_threshold,
+        profitability_filter,
+        confirmed_l1_block,
+        Arc::new(fee_strategy),
+        fee_budget.clone(),
+        config.max_in_flight_transactions.unwrap_or(1),
+        dry_run,
+        retry_policy,
+        max_withdrawal_gas_limit,
+        config.finalization_order.unwrap_or_default(),
+        lease,
     );
-    let finalizer_handle = tokio::spawn(finalizer.run(client_l2));
-
-    let metrics_handle = tokio::spawn(metrics::meter_unfinalized_withdrawals(
-        pgpool.clone(),
-        eth_finalization_threshold,
+    let mut finalizer_handle = tokio::spawn(finalizer.run(
+        client_l2,
+        shutdown.clone(),
+        shutdown_timeout,
+        restart_policy,
     ));
 
-    let api_server = tokio::spawn(api::run_server(pgpool));
+    let metrics_handle = tokio::spawn(supervise("metrics", restart_policy, shutdown.clone(), {
+        let pgpool = pgpool.clone();
+        move || metrics::meter_unfinalized_withdrawals(pgpool.clone(), eth_finalization_threshold)
+    }));
 
+    let mut webhook_retry_policy = webhooks::default_retry_policy();
+
+    if let Some(webhook_max_attempts) = config.webhook_max_attempts {
+        webhook_retry_policy.max_attempts = webhook_max_attempts;
+    }
+
+    if let Some(webhook_retry_backoff) = config.webhook_retry_backoff {
+        webhook_retry_policy.initial_backoff = Duration::from_secs(webhook_retry_backoff);
+    }
+
+    if let Some(webhook_retry_max_backoff) = config.webhook_retry_max_backoff {
+        webhook_retry_policy.max_backoff = Duration::from_secs(webhook_retry_max_backoff);
+    }
+
+    tracing::info!("webhook retry policy {webhook_retry_policy:?}");
+
+    let webhooks_handle = tokio::spawn(supervise("webhooks", restart_policy, shutdown.clone(), {
+        let pgpool = pgpool.clone();
+        move || webhooks::WebhookSender::new(pgpool.clone(), webhook_retry_policy.clone()).run()
+    }));
+
+    // Components are restarted by their supervisors, the process only exits
+    // once one of them has been restarted too often or the leadership is lost.
+    let mut result = Ok(());
     tokio::select! {
-        r = api_server => {
-            tracing::error!("Api server ended with {r:?}");
+        biased;
+
+        _ = shutdown.cancelled() => {
+            // The watcher stores the events it has accumulated and the finalizer
+            // stops picking new withdrawals and drains the ones in flight.
+            tokio::join!(
+                join_within("Watcher", watcher_handle, shutdown_timeout),
+                join_within("Finalizer", finalizer_handle, shutdown_timeout),
+            );
         }
-        r = block_events_handle => {
-            tracing::error!("Block Events stream ended with {r:?}");
+        e = leadership.hold() => {
+            tracing::error!("Leadership ended with {e}");
+            // Exit with an error for the orchestrator to restart the instance,
+            // it then stands by until it becomes the leader again.
+            result = Err(e.wrap_err("lost the leadership"));
         }
-        r = withdrawal_events_handle => {
-            tracing::error!("Withdrawals Events stream ended with {r:?}");
+        r = api_server => {
+            tracing::error!("Api server ended with {r:?}");
+            result = Err(component_error("api server", r));
         }
-        r = watcher_handle => {
-            tracing::error!("Finalizer main loop ended with {r:?}");
+        r = &mut watcher_handle => {
+            tracing::error!("Watcher ended with {r:?}");
+            result = Err(component_error("watcher", r));
         }
-        r = finalizer_handle => {
+        r = &mut finalizer_handle => {
             tracing::error!("Finalizer ended with {r:?}");
+            result = Err(component_error("finalizer", r));
+        }
+        r = metrics_handle => {
+            tracing::error!("Metrics loop ended with {r:?}");
+            result = Err(component_error("metrics loop", r));
         }
-        _ = metrics_handle => {
-            tracing::error!("Metrics loop has ended");
+        r = webhooks_handle => {
+            tracing::error!("Webhooks loop ended with {r:?}");
+            result = Err(component_error("webhooks loop", r));
         }
     }
 
     stop_vise_exporter.send_replace(());
 
-    Ok(())
+    result
 }
@@ -4,7 +4,8 @@ use std::time::Duration;
 
 use ethers::types::U256;
 use sqlx::PgPool;
-use vise::{Gauge, Metrics};
+use storage::FinalizationStatus;
+use vise::{Gauge, LabeledFamily, Metrics};
 
 const METRICS_REFRESH_PERIOD: Duration = Duration::from_secs(15);
 
@@ -23,6 +24,13 @@ pub(super) struct FinalizerMainMetrics {
 
     /// The withdrawals that
     pub unexecuted_eth_withdrawals_below_current_threshold: Gauge,
+
+    /// Whether this instance is the leader (1) or a standby (0).
+    pub is_leader: Gauge,
+
+    /// Number of withdrawals that are not finalized by their status.
+    #[metrics(labels = ["status"])]
+    pub unfinalized_withdrawals: LabeledFamily<&'static str, Gauge>,
 }
 
 #[vise::register]
@@ -49,5 +57,22 @@ pub async fn meter_unfinalized_withdrawals(pool: PgPool, eth_threshold: Option<U
         MAIN_FINALIZER_METRICS
             .unexecuted_eth_withdrawals_below_current_threshold
             .set(unexecuted);
+
+        let Ok(by_status) = storage::unfinalized_withdrawals_count_by_status(&pool).await else {
+            continue;
+        };
+
+        for status in FinalizationStatus::ALL {
+            if status.is_finalized() {
+                continue;
+            }
+
+            let count = by_status
+                .iter()
+                .find_map(|(s, count)| (*s == status).then_some(*count))
+                .unwrap_or(0);
+
+            MAIN_FINALIZER_METRICS.unfinalized_withdrawals[&status.as_str()].set(count as i64);
+        }
     }
 }
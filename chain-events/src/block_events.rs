@@ -1,18 +1,26 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use ethers::{
     abi::{AbiDecode, Address, RawLog},
     contract::EthEvent,
     prelude::EthLogDecode,
     providers::{Middleware, Provider, PubsubClient, Ws},
-    types::{BlockNumber, Filter, Log, Transaction, ValueOrArray, H256},
+    types::{BlockNumber, Filter, Log, Transaction, ValueOrArray, H256, U64},
 };
-use futures::{Sink, SinkExt, StreamExt};
+use futures::{stream, Sink, SinkExt, StreamExt};
 
 use client::{
     zksync_contract::{
         codegen::{
-            BlockCommitFilter, BlockExecutionFilter, BlocksVerificationFilter, CommitBatchesCall,
+            BlockCommitFilter, BlockExecutionFilter, BlocksRevertFilter, BlocksVerificationFilter,
+            CommitBatchesCall,
         },
         parse_withdrawal_events_l1,
     },
@@ -20,17 +28,26 @@ use client::{
 };
 use ethers_log_decode::EthLogDecode;
 
-use crate::{metrics::CHAIN_EVENTS_METRICS, Error, Result, RECONNECT_BACKOFF};
+use crate::{
+    metrics::CHAIN_EVENTS_METRICS, Error, L1Confirmations, Result, L1_BLOCK_HASHES_WINDOW,
+    RECONNECT_BACKOFF,
+};
 
 // Total timecap for tx querying retry 10 minutes
 const PENDING_TX_RETRY: usize = 12 * 10;
 const PENDING_TX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
 
+enum L1Item<L> {
+    Log(L),
+    Head(Option<U64>),
+}
+
 #[derive(EthLogDecode)]
 enum L1Events {
     BlockCommit(BlockCommitFilter),
     BlocksVerification(BlocksVerificationFilter),
     BlocksExecution(BlockExecutionFilter),
+    BlocksRevert(BlocksRevertFilter),
 }
 
 // A convenience multiplexer for `Block`-related events.
@@ -42,6 +59,45 @@ enum L1Events {
 /// Listener of block events on L1.
 pub struct BlockEvents {
     url: String,
+    l1_block_hashes: L1BlockHashes,
+    l1_confirmations: L1Confirmations,
+    confirmed_l1_block: Arc<AtomicU64>,
+}
+
+// Hashes of the recent L1 blocks in which events have been seen.
+//
+// These are used to detect L1 chain reorganizations: if any of the
+// known blocks is no longer canonical, all events starting from the
+// first non-canonical block have to be replayed.
+#[derive(Debug, Default)]
+struct L1BlockHashes {
+    hashes: BTreeMap<u64, H256>,
+}
+
+impl L1BlockHashes {
+    fn get(&self, block_number: u64) -> Option<H256> {
+        self.hashes.get(&block_number).copied()
+    }
+
+    // The latest known block strictly before `block_number`.
+    fn latest_before(&self, block_number: u64) -> Option<(u64, H256)> {
+        self.hashes
+            .range(..block_number)
+            .next_back()
+            .map(|(n, h)| (*n, *h))
+    }
+
+    fn insert(&mut self, block_number: u64, block_hash: H256) {
+        self.hashes.insert(block_number, block_hash);
+
+        let window_start = block_number.saturating_sub(L1_BLOCK_HASHES_WINDOW);
+        self.hashes = self.hashes.split_off(&window_start);
+    }
+
+    // Forget all blocks starting from `block_number`.
+    fn truncate(&mut self, block_number: u64) {
+        self.hashes.split_off(&block_number);
+    }
 }
 
 impl BlockEvents {
@@ -53,9 +109,44 @@ impl BlockEvents {
     pub fn new(url: &str) -> BlockEvents {
         Self {
             url: url.to_string(),
+            l1_block_hashes: Default::default(),
+            l1_confirmations: L1Confirmations::Blocks(0),
+            confirmed_l1_block: Default::default(),
         }
     }
 
+    /// Sets the depth at which L1 blocks are considered confirmed.
+    pub fn with_l1_confirmations(mut self, l1_confirmations: L1Confirmations) -> Self {
+        self.l1_confirmations = l1_confirmations;
+
+        self
+    }
+
+    /// Number of the last L1 block with enough confirmations.
+    ///
+    /// Updated on every new L1 head while the listener is running,
+    /// `0` until the first head is seen.
+    pub fn confirmed_l1_block(&self) -> Arc<AtomicU64> {
+        self.confirmed_l1_block.clone()
+    }
+
+    /// Shares the number of the last confirmed L1 block with a previous listener.
+    pub fn with_confirmed_l1_block(mut self, confirmed_l1_block: Arc<AtomicU64>) -> Self {
+        self.confirmed_l1_block = confirmed_l1_block;
+
+        self
+    }
+
+    /// Seeds the listener with hashes of previously seen L1 blocks
+    /// so that reorgs that happened while the service was down are detected.
+    pub fn with_l1_block_hashes(mut self, hashes: impl IntoIterator<Item = (u64, H256)>) -> Self {
+        for (block_number, block_hash) in hashes {
+            self.l1_block_hashes.insert(block_number, block_hash);
+        }
+
+        self
+    }
+
     async fn connect(&self) -> Option<Provider<Ws>> {
         match Provider::<Ws>::connect_with_reconnects(&self.url, 0).await {
             Ok(p) => {
@@ -76,7 +167,7 @@ impl BlockEvents {
     // in `ethers-rs`: https://github.com/gakonst/ethers-rs/issues/2418
     // This function is a workaround for that and implements manual re-connecting.
     pub async fn run_with_reconnects<B, S>(
-        self,
+        mut self,
         diamond_proxy_addr: Address,
         l2_erc20_bridge_addr: Address,
         from_block: B,
@@ -103,6 +194,9 @@ impl BlockEvents {
                 from_block,
                 sender.clone(),
                 middleware,
+                &mut self.l1_block_hashes,
+                self.l1_confirmations,
+                &self.confirmed_l1_block,
             )
             .await
             {
@@ -130,12 +224,16 @@ impl BlockEvents {
     /// APIs heavily rely on `&self` and what is worse on `&self`
     /// lifetimes making it practically impossible to decouple
     /// `Event` and `EventStream` types from each other.
+    #[allow(clippy::too_many_arguments)]
     async fn run<B, S, M>(
         diamond_proxy_addr: Address,
         l2_erc20_bridge_addr: Address,
         from_block: B,
         mut sender: S,
         middleware: M,
+        l1_block_hashes: &mut L1BlockHashes,
+        l1_confirmations: L1Confirmations,
+        confirmed_l1_block: &AtomicU64,
     ) -> Result<BlockNumber>
     where
         B: Into<BlockNumber> + Copy,
@@ -163,6 +261,14 @@ impl BlockEvents {
             latest_block.as_u64(),
         );
 
+        update_confirmed_l1_block(
+            &middleware,
+            l1_confirmations,
+            latest_block.as_u64(),
+            confirmed_l1_block,
+        )
+        .await?;
+
         let past_filter = Filter::new()
             .from_block(from_block)
             .to_block(latest_block)
@@ -171,6 +277,7 @@ impl BlockEvents {
                 BlockCommitFilter::signature(),
                 BlocksVerificationFilter::signature(),
                 BlockExecutionFilter::signature(),
+                BlocksRevertFilter::signature(),
             ]);
 
         let filter = Filter::new()
@@ -180,6 +287,7 @@ impl BlockEvents {
                 BlockCommitFilter::signature(),
                 BlocksVerificationFilter::signature(),
                 BlockExecutionFilter::signature(),
+                BlocksRevertFilter::signature(),
             ]);
 
         let past_logs = middleware.get_logs_paginated(&past_filter, 256);
@@ -188,9 +296,30 @@ impl BlockEvents {
             .await
             .map_err(|e| Error::Middleware(e.to_string()))?;
 
-        let mut logs = past_logs.chain(current_logs.map(Ok));
+        let heads = middleware
+            .subscribe_blocks()
+            .await
+            .map_err(|e| Error::Middleware(e.to_string()))?;
 
-        while let Some(log) = logs.next().await {
+        let logs = past_logs.chain(current_logs.map(Ok)).map(L1Item::Log);
+        let mut items = stream::select(logs, heads.map(|head| L1Item::Head(head.number)));
+
+        while let Some(item) = items.next().await {
+            let log = match item {
+                L1Item::Head(head) => {
+                    if let Some(head) = head {
+                        update_confirmed_l1_block(
+                            &middleware,
+                            l1_confirmations,
+                            head.as_u64(),
+                            confirmed_l1_block,
+                        )
+                        .await?;
+                    }
+                    continue;
+                }
+                L1Item::Log(log) => log,
+            };
             let log = match log {
                 Err(e) => {
                     tracing::warn!("L1 block events stream ended with {e}");
@@ -202,6 +331,36 @@ impl BlockEvents {
                 continue;
             };
 
+            if log.removed == Some(true) {
+                tracing::warn!("L1 log {log:?} has been removed from the canonical chain");
+
+                let fork_block_number =
+                    find_fork_block(&middleware, l1_block_hashes, block_number).await?;
+
+                return handle_reorg(fork_block_number, l1_block_hashes, &mut sender).await;
+            }
+
+            let Some(block_hash) = log.block_hash else {
+                continue;
+            };
+
+            if let Some(fork_block_number) =
+                detect_reorg(&middleware, l1_block_hashes, block_number, block_hash).await?
+            {
+                return handle_reorg(fork_block_number, l1_block_hashes, &mut sender).await;
+            }
+
+            if l1_block_hashes.get(block_number).is_none() {
+                l1_block_hashes.insert(block_number, block_hash);
+                sender
+                    .send(BlockEvent::NewL1Block {
+                        block_number,
+                        block_hash,
+                    })
+                    .await
+                    .map_err(|_| Error::ChannelClosing)?;
+            }
+
             last_seen_block = block_number.into();
             let raw_log: RawLog = log.clone().into();
 
@@ -223,6 +382,153 @@ impl BlockEvents {
     }
 }
 
+async fn update_confirmed_l1_block<M>(
+    middleware: &M,
+    l1_confirmations: L1Confirmations,
+    head: u64,
+    confirmed_l1_block: &AtomicU64,
+) -> Result<()>
+where
+    M: Middleware,
+{
+    let confirmed = match l1_confirmations {
+        L1Confirmations::Blocks(confirmations) => head.saturating_sub(confirmations),
+        L1Confirmations::Finalized => {
+            let Some(finalized) = middleware
+                .get_block(BlockNumber::Finalized)
+                .await
+                .map_err(|e| Error::Middleware(e.to_string()))?
+                .and_then(|b| b.number)
+            else {
+                return Ok(());
+            };
+
+            finalized.as_u64()
+        }
+    };
+
+    CHAIN_EVENTS_METRICS.l1_head_block.set(head as i64);
+    CHAIN_EVENTS_METRICS
+        .l1_confirmed_block
+        .set(confirmed as i64);
+    confirmed_l1_block.store(confirmed, Ordering::Relaxed);
+
+    Ok(())
+}
+
+async fn canonical_block_hash<M>(middleware: &M, block_number: u64) -> Result<Option<H256>>
+where
+    M: Middleware,
+{
+    let block = middleware
+        .get_block(block_number)
+        .await
+        .map_err(|e| Error::Middleware(e.to_string()))?;
+
+    Ok(block.and_then(|b| b.hash))
+}
+
+// Checks if a log from `block_hash` at `block_number` is consistent
+// with the known blocks and returns the first non-canonical block if it is not.
+async fn detect_reorg<M>(
+    middleware: &M,
+    l1_block_hashes: &L1BlockHashes,
+    block_number: u64,
+    block_hash: H256,
+) -> Result<Option<u64>>
+where
+    M: Middleware,
+{
+    if let Some(known_hash) = l1_block_hashes.get(block_number) {
+        if known_hash == block_hash {
+            return Ok(None);
+        }
+
+        tracing::warn!(
+            "L1 block {block_number} hash changed from {known_hash:?} to {block_hash:?}"
+        );
+
+        return find_fork_block(middleware, l1_block_hashes, block_number)
+            .await
+            .map(Some);
+    }
+
+    let Some((prev_number, prev_hash)) = l1_block_hashes.latest_before(block_number) else {
+        return Ok(None);
+    };
+
+    let canonical_hash = if prev_number + 1 == block_number {
+        middleware
+            .get_block(block_hash)
+            .await
+            .map_err(|e| Error::Middleware(e.to_string()))?
+            .map(|b| b.parent_hash)
+    } else {
+        canonical_block_hash(middleware, prev_number).await?
+    };
+
+    if canonical_hash == Some(prev_hash) {
+        return Ok(None);
+    }
+
+    tracing::warn!(
+        "L1 block {prev_number} with hash {prev_hash:?} is no longer canonical, found {canonical_hash:?}"
+    );
+
+    find_fork_block(middleware, l1_block_hashes, prev_number)
+        .await
+        .map(Some)
+}
+
+// Walks the known blocks back from `block_number` looking for the latest
+// canonical one and returns the number of the block following it.
+async fn find_fork_block<M>(
+    middleware: &M,
+    l1_block_hashes: &L1BlockHashes,
+    block_number: u64,
+) -> Result<u64>
+where
+    M: Middleware,
+{
+    let mut fork_block_number = block_number;
+
+    for (known_number, known_hash) in l1_block_hashes.hashes.range(..=block_number).rev() {
+        if canonical_block_hash(middleware, *known_number).await? == Some(*known_hash) {
+            return Ok(known_number + 1);
+        }
+
+        fork_block_number = *known_number;
+    }
+
+    tracing::warn!(
+        "No canonical L1 blocks are known, the reorg may be deeper than {fork_block_number}"
+    );
+
+    Ok(fork_block_number)
+}
+
+async fn handle_reorg<S>(
+    fork_block_number: u64,
+    l1_block_hashes: &mut L1BlockHashes,
+    sender: &mut S,
+) -> Result<BlockNumber>
+where
+    S: Sink<BlockEvent> + Unpin,
+    <S as Sink<BlockEvent>>::Error: std::fmt::Debug,
+{
+    tracing::warn!("L1 reorg detected, replaying events from block {fork_block_number}");
+
+    CHAIN_EVENTS_METRICS.l1_reorgs.inc();
+    l1_block_hashes.truncate(fork_block_number);
+
+    sender
+        .send(BlockEvent::L1Reorg { fork_block_number })
+        .await
+        .map_err(|_| Error::ChannelClosing)?;
+
+    Ok(fork_block_number.into())
+}
+
 async fn get_tx_with_retries<M>(
     middleware: &M,
     tx_hash: H256,
@@ -333,6 +639,52 @@ where
                 .await
                 .map_err(|_| Error::ChannelClosing)?;
         }
+        L1Events::BlocksRevert(event) => {
+            tracing::warn!(
+                "Received a blocks revert event {event:?} {:?}",
+                log.transaction_hash
+            );
+
+            CHAIN_EVENTS_METRICS.block_revert_events.inc();
+            sender
+                .send(BlockEvent::BlocksRevert {
+                    block_number,
+                    event: event.clone(),
+                })
+                .await
+                .map_err(|_| Error::ChannelClosing)?;
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn l1_block_hashes_keep_window() {
+        let mut hashes = L1BlockHashes::default();
+
+        for block_number in 1..=L1_BLOCK_HASHES_WINDOW + 10 {
+            hashes.insert(block_number, H256::from_low_u64_be(block_number));
+        }
+
+        assert_eq!(hashes.get(9), None);
+        assert_eq!(hashes.get(10), Some(H256::from_low_u64_be(10)));
+        assert_eq!(
+            hashes.latest_before(20),
+            Some((19, H256::from_low_u64_be(19)))
+        );
+
+        hashes.truncate(20);
+
+        assert_eq!(hashes.get(20), None);
+        assert_eq!(
+            hashes.latest_before(L1_BLOCK_HASHES_WINDOW),
+            Some((19, H256::from_low_u64_be(19)))
+        );
+    }
+}
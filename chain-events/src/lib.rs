@@ -10,12 +10,16 @@ mod error;
 mod l2_events;
 mod metrics;
 
-use std::time::Duration;
+use std::{str::FromStr, time::Duration};
 
 use client::WithdrawalEvent;
 pub use error::{Error, Result};
 
 pub(crate) const RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Number of recent L1 blocks whose hashes are tracked to detect reorgs.
+pub const L1_BLOCK_HASHES_WINDOW: u64 = 128;
+
 pub use block_events::BlockEvents;
 use ethers::{
     providers::{LogQueryError, ProviderError},
@@ -23,6 +27,27 @@ use ethers::{
 };
 pub use l2_events::L2EventsListener;
 
+/// Depth at which an L1 block is considered safe to act upon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L1Confirmations {
+    /// The block is at least this many blocks behind the L1 head.
+    Blocks(u64),
+
+    /// The block has been finalized by the L1 consensus.
+    Finalized,
+}
+
+impl FromStr for L1Confirmations {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "finalized" => Ok(Self::Finalized),
+            s => Ok(Self::Blocks(s.parse()?)),
+        }
+    }
+}
+
 /// All L2 Events the service is interested in.
 #[derive(Debug)]
 pub enum L2Event {
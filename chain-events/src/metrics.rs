@@ -41,6 +41,18 @@ pub(super) struct ChainEventsMetrics {
 
     /// Number of received block execution events
     pub block_execution_events: Counter,
+
+    /// Number of received blocks revert events
+    pub block_revert_events: Counter,
+
+    /// Number of detected L1 chain reorganizations
+    pub l1_reorgs: Counter,
+
+    /// Last seen L1 head block number
+    pub l1_head_block: Gauge,
+
+    /// Last L1 block number with enough confirmations
+    pub l1_confirmed_block: Gauge,
 }
 
 #[vise::register]
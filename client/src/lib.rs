@@ -232,6 +232,18 @@ pub trait ZksyncMiddleware: Middleware {
         index: usize,
     ) -> Result<Option<WithdrawalParams>>;
 
+    /// Get the withdrawal event emitted in a transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_hash`: Hash of the TX in which withdrawal event was emitted
+    /// * `index`: Index of the withdrawal event in transaction.
+    async fn get_withdrawal_event(
+        &self,
+        tx_hash: H256,
+        index: usize,
+    ) -> Result<Option<WithdrawalEvent>>;
+
     /// Get the `zksync` withdrawal logs by tx hash.
     ///
     /// # Arguments
@@ -454,6 +466,46 @@ impl<P: JsonRpcClient> ZksyncMiddleware for Provider<P> {
         }))
     }
 
+    async fn get_withdrawal_event(
+        &self,
+        tx_hash: H256,
+        index: usize,
+    ) -> Result<Option<WithdrawalEvent>> {
+        let latency = CLIENT_METRICS.call[&"get_withdrawal_event"].start();
+
+        let receipt = self.zks_get_transaction_receipt(tx_hash).await?;
+
+        let Some(withdrawal_log) = receipt
+            .logs
+            .into_iter()
+            .filter(|log| {
+                log.topics[0] == BridgeBurnFilter::signature()
+                    || log.topics[0] == WithdrawalFilter::signature()
+            })
+            .nth(index)
+        else {
+            return Ok(None);
+        };
+
+        let raw_log: RawLog = withdrawal_log.clone().into();
+        let amount = match WithdrawalEvents::decode_log(&raw_log)? {
+            WithdrawalEvents::BridgeBurn(b) => b.amount,
+            WithdrawalEvents::Withdrawal(w) => w.amount,
+        };
+
+        latency.observe();
+
+        Ok(Some(WithdrawalEvent {
+            tx_hash,
+            block_number: withdrawal_log
+                .block_number
+                .expect("log always has a block number; qed")
+                .as_u64(),
+            token: withdrawal_log.address,
+            amount,
+        }))
+    }
+
     async fn get_withdrawal_log(
         &self,
         tx_hash: H256,
@@ -67,6 +67,21 @@ pub enum BlockEvent {
         ///events
         events: Vec<L2ToL1Event>,
     },
+
+    /// A new L1 block containing any of the above events has been seen.
+    NewL1Block {
+        /// Number of the block.
+        block_number: u64,
+
+        /// Hash of the block.
+        block_hash: H256,
+    },
+
+    /// L1 chain has been reorganized.
+    L1Reorg {
+        /// Number of the first block that is no longer canonical.
+        fork_block_number: u64,
+    },
 }
 
 // This custom impl sole purpose is pretty hash display instead of [u8; 32]
@@ -106,6 +121,18 @@ impl std::fmt::Display for BlockEvent {
                 .debug_struct("L2ToL1Events")
                 .field("events", &events)
                 .finish(),
+            Self::NewL1Block {
+                block_number,
+                block_hash,
+            } => f
+                .debug_struct("NewL1Block")
+                .field("block_number", block_number)
+                .field("block_hash", block_hash)
+                .finish(),
+            Self::L1Reorg { fork_block_number } => f
+                .debug_struct("L1Reorg")
+                .field("fork_block_number", fork_block_number)
+                .finish(),
         }
     }
 }
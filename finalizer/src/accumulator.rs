@@ -1,11 +1,22 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use ethers::types::U256;
 
 use client::{
-    withdrawal_finalizer::codegen::withdrawal_finalizer::Result as FinalizeResult, WithdrawalParams,
+    withdrawal_finalizer::codegen::{
+        withdrawal_finalizer::Result as FinalizeResult, RequestFinalizeWithdrawal,
+    },
+    WithdrawalParams,
 };
 
+/// A safety margin in percent added to the simulated and
+/// historic gas consumption of withdrawals.
+pub const GAS_SAFETY_MARGIN_PERCENT: u64 = 10;
+
+/// Gas the finalizer contract requires to be left before each request
+/// on top of 64/63 of the gas limit of the request.
+pub const GAS_RESERVED_PER_REQUEST: u64 = 500;
+
 /// A struct that holds `RequestFinalizeWithdrawal`s and computes
 /// when there are enough in a batch to be submitted.
 pub struct WithdrawalsAccumulator {
@@ -13,24 +24,71 @@ pub struct WithdrawalsAccumulator {
     tx_fee_limit: U256,
     batch_finalization_gas_limit: U256,
     one_withdrawal_gas_limit: U256,
+    max_withdrawals: Option<usize>,
     // key: (l_2_block_number, l_2_message_index)
     withdrawals: BTreeMap<(u64, u64), WithdrawalParams>,
+    // estimated gas of each withdrawal by the same key
+    gas: BTreeMap<(u64, u64), U256>,
+    // gas limit of each withdrawal by the same key
+    gas_limits: BTreeMap<(u64, u64), U256>,
+    // withdrawals which gas has already been simulated
+    simulated: BTreeSet<(u64, u64)>,
 }
 
 impl WithdrawalsAccumulator {
-    /// take withdrawals
-    pub fn take_withdrawals(&mut self) -> Vec<WithdrawalParams> {
+    /// take withdrawals with their gas limits
+    pub fn take_withdrawals(&mut self) -> Vec<(WithdrawalParams, U256)> {
+        self.gas.clear();
+        self.simulated.clear();
+        let mut gas_limits = std::mem::take(&mut self.gas_limits);
         std::mem::take(&mut self.withdrawals)
             .into_iter()
-            .map(|v| v.1)
+            .map(|(key, w)| {
+                let gas_limit = gas_limits
+                    .remove(&key)
+                    .unwrap_or(self.one_withdrawal_gas_limit);
+                (w, gas_limit)
+            })
             .collect()
     }
 
+    /// Are there no withdrawals in the current set.
+    pub fn is_empty(&self) -> bool {
+        self.withdrawals.is_empty()
+    }
+
     /// Get a reference to a current set of withdrawals
     pub fn withdrawals(&self) -> impl Iterator<Item = &WithdrawalParams> {
         self.withdrawals.values()
     }
 
+    /// Finalization requests of the current set of withdrawals with their gas limits.
+    pub fn requests(&self) -> Vec<RequestFinalizeWithdrawal> {
+        self.withdrawals
+            .iter()
+            .map(|(key, w)| w.clone().into_request_with_gaslimit(self.gas_limit(key)))
+            .collect()
+    }
+
+    /// Id and gas limit of the withdrawal a result of simulation is returned for.
+    pub fn withdrawal_gas_limit(&self, result: &FinalizeResult) -> Option<(u64, U256)> {
+        let key = (
+            result.l_2_block_number.as_u64(),
+            result.l_2_message_index.as_u64(),
+        );
+
+        self.withdrawals
+            .get(&key)
+            .map(|w| (w.id, self.gas_limit(&key)))
+    }
+
+    fn gas_limit(&self, key: &(u64, u64)) -> U256 {
+        self.gas_limits
+            .get(key)
+            .copied()
+            .unwrap_or(self.one_withdrawal_gas_limit)
+    }
+
     /// Remove unsuccessful withdrawals by returned results.
     pub fn remove_unsuccessful(
         &mut self,
@@ -39,10 +97,13 @@ impl WithdrawalsAccumulator {
         let mut result = Vec::with_capacity(unsuccessful.len());
 
         for u in unsuccessful {
-            if let Some(wp) = self
-                .withdrawals
-                .remove(&(u.l_2_block_number.as_u64(), u.l_2_message_index.as_u64()))
-            {
+            let key = (u.l_2_block_number.as_u64(), u.l_2_message_index.as_u64());
+
+            self.gas.remove(&key);
+            self.gas_limits.remove(&key);
+            self.simulated.remove(&key);
+
+            if let Some(wp) = self.withdrawals.remove(&key) {
                 result.push(wp);
             }
         }
@@ -62,31 +123,324 @@ impl WithdrawalsAccumulator {
             tx_fee_limit,
             batch_finalization_gas_limit,
             one_withdrawal_gas_limit,
+            max_withdrawals: None,
             withdrawals: BTreeMap::new(),
+            gas: BTreeMap::new(),
+            gas_limits: BTreeMap::new(),
+            simulated: BTreeSet::new(),
         }
     }
 
+    /// Consider the batch ready to be finalized once it has this many withdrawals.
+    pub fn with_max_withdrawals(mut self, max_withdrawals: usize) -> Self {
+        self.max_withdrawals = Some(max_withdrawals);
+        self
+    }
+
+    /// Does a withdrawal fit into the batch without the batch needing
+    /// more gas than the batch finalization gas limit.
+    ///
+    /// Withdrawals are only expected to be added once they fit.
+    pub fn fits(&self, estimated_gas: Option<U256>, gas_limit: Option<U256>) -> bool {
+        let withdrawal = self.estimate(estimated_gas, gas_limit);
+
+        self.gas_required_with(Some(withdrawal)) <= self.batch_finalization_gas_limit
+    }
+
     /// Add a finalization withdrawals request.
     ///
     /// # Argument
     ///
     /// * `request` A finalization request.
-    pub fn add_withdrawal(&mut self, data: WithdrawalParams) {
-        self.withdrawals.insert(
-            (data.l1_batch_number.as_u64(), data.l2_message_index.into()),
-            data,
-        );
+    /// * `estimated_gas` Gas usually consumed by withdrawals of this token,
+    ///   the gas limit of the withdrawal is assumed if unknown.
+    /// * `gas_limit` Gas limit of the withdrawal if it differs from `one_withdrawal_gas_limit`.
+    pub fn add_withdrawal(
+        &mut self,
+        data: WithdrawalParams,
+        estimated_gas: Option<U256>,
+        gas_limit: Option<U256>,
+    ) {
+        let key = (data.l1_batch_number.as_u64(), data.l2_message_index.into());
+        let (gas, gas_limit) = self.estimate(estimated_gas, gas_limit);
+
+        self.gas.insert(key, gas);
+        self.gas_limits.insert(key, gas_limit);
+        self.withdrawals.insert(key, data);
+    }
+
+    /// Remove the last withdrawals while the batch needs more gas than the
+    /// batch finalization gas limit, which happens once simulated gas of
+    /// withdrawals exceeds their estimates.
+    ///
+    /// The first withdrawal is always kept.
+    pub fn remove_excess(&mut self) -> Vec<WithdrawalParams> {
+        let mut excess = vec![];
+
+        while self.withdrawals.len() > 1 && self.gas_required() > self.batch_finalization_gas_limit
+        {
+            let Some((key, w)) = self.withdrawals.pop_last() else {
+                break;
+            };
+
+            self.gas.remove(&key);
+            self.gas_limits.remove(&key);
+            self.simulated.remove(&key);
+            excess.push(w);
+        }
+
+        excess.reverse();
+        excess
+    }
+
+    // Estimated gas and the gas limit of a withdrawal.
+    fn estimate(&self, estimated_gas: Option<U256>, gas_limit: Option<U256>) -> (U256, U256) {
+        let gas_limit = gas_limit.unwrap_or(self.one_withdrawal_gas_limit);
+        let gas = match estimated_gas {
+            Some(gas) => with_safety_margin(gas, gas_limit),
+            None => gas_limit,
+        };
+
+        (gas, gas_limit)
+    }
+
+    /// Replace estimated gas consumption of withdrawals by the simulated one.
+    ///
+    /// Returns ids and simulated gas of withdrawals that have been simulated
+    /// for the first time and have succeeded within their gas limits.
+    pub fn set_simulated_gas(&mut self, results: &[FinalizeResult]) -> Vec<(u64, U256)> {
+        let mut simulated = vec![];
+
+        for r in results {
+            let key = (r.l_2_block_number.as_u64(), r.l_2_message_index.as_u64());
+
+            let Some(wp) = self.withdrawals.get(&key) else {
+                continue;
+            };
+            let gas_limit = self.gas_limit(&key);
+
+            if r.success && r.gas <= gas_limit && self.simulated.insert(key) {
+                simulated.push((wp.id, r.gas));
+            }
+
+            self.gas.insert(key, with_safety_margin(r.gas, gas_limit));
+        }
+
+        simulated
+    }
+
+    /// Gas price the withdrawals are accumulated for.
+    pub fn gas_price(&self) -> U256 {
+        self.gas_price
     }
 
     /// Get estimated gas consumption of the current set.
     pub fn current_gas_usage(&self) -> U256 {
-        self.one_withdrawal_gas_limit * self.withdrawals.len()
+        self.gas
+            .values()
+            .fold(U256::zero(), |acc, gas| acc.saturating_add(*gas))
+    }
+
+    /// Gas the current set needs to be given by the finalization transaction.
+    ///
+    /// Before each request the contract requires [`reserved_gas`] of its gas
+    /// limit to be left after the requests before it have consumed their
+    /// estimated gas. This is bounded by the estimated gas of the whole set
+    /// and the largest reserve on top of the estimated gas of its request.
+    pub fn gas_required(&self) -> U256 {
+        self.gas_required_with(None)
+    }
+
+    fn gas_required_with(&self, withdrawal: Option<(U256, U256)>) -> U256 {
+        let mut consumed = U256::zero();
+        let mut reserve = U256::zero();
+
+        let withdrawals = self
+            .gas
+            .iter()
+            .map(|(key, gas)| (*gas, self.gas_limit(key)))
+            .chain(withdrawal);
+
+        for (gas, gas_limit) in withdrawals {
+            consumed = consumed.saturating_add(gas);
+            reserve = std::cmp::max(reserve, reserved_gas(gas_limit).saturating_sub(gas));
+        }
+
+        consumed.saturating_add(reserve)
     }
 
     /// Is this batch of withdrawals ready to be finalized.
     pub fn ready_to_finalize(&self) -> bool {
         let current_gas_usage = self.current_gas_usage();
-        current_gas_usage >= self.batch_finalization_gas_limit
+        self.gas_required() >= self.batch_finalization_gas_limit
             || current_gas_usage * self.gas_price >= self.tx_fee_limit
+            || self
+                .max_withdrawals
+                .is_some_and(|max_withdrawals| self.withdrawals.len() >= max_withdrawals)
+    }
+}
+
+/// Gas the finalizer contract requires to be left before a request with
+/// this gas limit: `require(gasleft() >= _gas * 64 / 63 + 500)`.
+pub fn reserved_gas(gas_limit: U256) -> U256 {
+    (gas_limit.saturating_mul(64.into()) / 63).saturating_add(GAS_RESERVED_PER_REQUEST.into())
+}
+
+/// The highest gas limit of a withdrawal that fits into a batch alone.
+pub fn max_request_gas_limit(batch_finalization_gas_limit: U256) -> U256 {
+    let available = batch_finalization_gas_limit.saturating_sub(GAS_RESERVED_PER_REQUEST.into());
+
+    // The largest gas limit with `gas_limit * 64 / 63 <= available`.
+    (available.saturating_add(1.into()).saturating_mul(63.into()) - 1) / 64
+}
+
+fn with_safety_margin(gas: U256, gas_limit: U256) -> U256 {
+    std::cmp::min(gas * (100 + GAS_SAFETY_MARGIN_PERCENT) / 100, gas_limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Address, H256, U256};
+    use pretty_assertions::assert_eq;
+
+    use client::{
+        withdrawal_finalizer::codegen::withdrawal_finalizer::Result as FinalizeResult,
+        WithdrawalParams,
+    };
+
+    use super::{max_request_gas_limit, reserved_gas, WithdrawalsAccumulator};
+
+    fn withdrawal(id: u64, l1_batch_number: u64) -> WithdrawalParams {
+        WithdrawalParams {
+            tx_hash: H256::from_low_u64_be(id),
+            event_index_in_tx: 0,
+            id,
+            l2_block_number: id,
+            l1_batch_number: l1_batch_number.into(),
+            l2_message_index: 0,
+            l2_tx_number_in_block: 0,
+            message: Default::default(),
+            sender: Address::zero(),
+            proof: vec![],
+        }
+    }
+
+    fn result(l1_batch_number: u64, gas: u64, success: bool) -> FinalizeResult {
+        FinalizeResult {
+            l_2_block_number: l1_batch_number.into(),
+            l_2_message_index: U256::zero(),
+            gas: gas.into(),
+            success,
+        }
+    }
+
+    #[test]
+    fn gas_usage_follows_simulated_gas() {
+        let mut accumulator =
+            WithdrawalsAccumulator::new(1.into(), U256::MAX, 2500.into(), 1000.into());
+
+        accumulator.add_withdrawal(withdrawal(1, 10), None, None);
+        accumulator.add_withdrawal(withdrawal(2, 20), Some(500.into()), None);
+
+        assert_eq!(accumulator.current_gas_usage(), 1550.into());
+
+        let results = [result(10, 300, true), result(20, 2000, false)];
+
+        assert_eq!(
+            accumulator.set_simulated_gas(&results),
+            vec![(1, 300.into())]
+        );
+        assert_eq!(accumulator.current_gas_usage(), 1330.into());
+        assert_eq!(accumulator.set_simulated_gas(&results), vec![]);
+
+        let removed = accumulator.remove_unsuccessful(&results[1..]);
+
+        assert_eq!(removed.len(), 1);
+        assert_eq!(accumulator.current_gas_usage(), 330.into());
+        assert!(!accumulator.ready_to_finalize());
+
+        accumulator.take_withdrawals();
+
+        assert_eq!(accumulator.current_gas_usage(), U256::zero());
+    }
+
+    #[test]
+    fn withdrawals_are_limited_by_their_own_gas_limits() {
+        let mut accumulator =
+            WithdrawalsAccumulator::new(1.into(), U256::MAX, 10_000.into(), 1000.into())
+                .with_max_withdrawals(2);
+
+        accumulator.add_withdrawal(withdrawal(1, 10), None, Some(3000.into()));
+
+        assert_eq!(accumulator.current_gas_usage(), 3000.into());
+        assert_eq!(
+            accumulator.withdrawal_gas_limit(&result(10, 0, false)),
+            Some((1, 3000.into()))
+        );
+        assert!(!accumulator.ready_to_finalize());
+
+        accumulator.add_withdrawal(withdrawal(2, 20), Some(2000.into()), Some(2000.into()));
+
+        assert_eq!(accumulator.current_gas_usage(), 5000.into());
+        assert!(accumulator.ready_to_finalize());
+
+        let gas_limits: Vec<_> = accumulator.requests().into_iter().map(|r| r.gas).collect();
+        assert_eq!(gas_limits, vec![3000.into(), 2000.into()]);
+    }
+
+    #[test]
+    fn batch_is_filled_up_to_its_gas_limit() {
+        // Each withdrawal needs 1000 gas and 1000 * 64 / 63 + 500 = 1515 gas
+        // to be left before it, so four of them need 3000 + 1515 = 4515 gas.
+        let mut accumulator =
+            WithdrawalsAccumulator::new(1.into(), U256::MAX, 4515.into(), 1000.into());
+
+        for id in 1..=3 {
+            assert!(accumulator.fits(None, None));
+            accumulator.add_withdrawal(withdrawal(id, id * 10), None, None);
+            assert!(!accumulator.ready_to_finalize());
+        }
+
+        assert!(accumulator.fits(None, None));
+        accumulator.add_withdrawal(withdrawal(4, 40), None, None);
+
+        assert_eq!(accumulator.gas_required(), 4515.into());
+        assert!(accumulator.ready_to_finalize());
+        assert!(!accumulator.fits(Some(1.into()), None));
+        assert!(accumulator.remove_excess().is_empty());
+    }
+
+    #[test]
+    fn raised_gas_limits_are_reserved() {
+        // A raised gas limit of 5000 needs 5000 * 64 / 63 + 500 = 5579 gas
+        // to be left before the request however little it usually consumes.
+        let mut accumulator =
+            WithdrawalsAccumulator::new(1.into(), U256::MAX, 10_000.into(), 1000.into())
+                .with_max_withdrawals(4);
+
+        assert_eq!(reserved_gas(5000.into()), 5579.into());
+
+        accumulator.add_withdrawal(withdrawal(1, 10), Some(4000.into()), Some(5000.into()));
+        assert!(accumulator.fits(Some(4000.into()), Some(5000.into())));
+        accumulator.add_withdrawal(withdrawal(2, 20), Some(4000.into()), Some(5000.into()));
+
+        assert_eq!(accumulator.gas_required(), 9979.into());
+        assert!(!accumulator.fits(Some(100.into()), Some(5000.into())));
+
+        // The second withdrawal no longer fits once it is simulated to consume its gas limit.
+        accumulator.set_simulated_gas(&[result(20, 5000, true)]);
+        let excess = accumulator.remove_excess();
+
+        assert_eq!(excess.iter().map(|w| w.id).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(accumulator.gas_required(), 5579.into());
+    }
+
+    #[test]
+    fn largest_gas_limit_fits_alone() {
+        let batch_finalization_gas_limit = U256::from(10_000);
+        let gas_limit = max_request_gas_limit(batch_finalization_gas_limit);
+
+        assert!(reserved_gas(gas_limit) <= batch_finalization_gas_limit);
+        assert!(reserved_gas(gas_limit + 1) > batch_finalization_gas_limit);
     }
 }
@@ -17,8 +17,14 @@ pub enum Error {
     #[error("middleware error {0}")]
     Middleware(String),
 
-    #[error("withdrawal transaction was reverted")]
-    WithdrawalTransactionReverted,
+    #[error("price source error {0}")]
+    PriceSource(String),
+
+    #[error("max fee per gas is below the base fee")]
+    MaxFeePerGasBelowBaseFee,
+
+    #[error("{0} has been restarted too often")]
+    TooManyRestarts(&'static str),
 }
 
 impl<M: Middleware> From<ContractError<M>> for Error {
@@ -0,0 +1,72 @@
+//! Classification of reasons withdrawal finalization fails for.
+
+use ethers::{contract::ContractError, providers::Middleware};
+use storage::{FailureClass, FinalizationFailure};
+
+/// Revert reasons of `IL1Bridge::finalizeWithdrawal` and
+/// `IZkSync::finalizeEthWithdrawal` of an already finalized withdrawal.
+const ALREADY_FINALIZED_REASONS: [&str; 2] = ["pw", "jj"];
+
+/// Revert reasons of `IL1Bridge::finalizeWithdrawal` and
+/// `IZkSync::finalizeEthWithdrawal` of a withdrawal with an invalid proof.
+const INVALID_PROOF_REASONS: [&str; 2] = ["nq", "pi"];
+
+/// Classify a revert reason or an error message of a failed finalization.
+pub fn classify(reason: &str) -> FailureClass {
+    let lowercase = reason.to_lowercase();
+
+    if ALREADY_FINALIZED_REASONS.contains(&reason) || lowercase.contains("already finalized") {
+        FailureClass::AlreadyFinalized
+    } else if INVALID_PROOF_REASONS.contains(&reason) || lowercase.contains("invalid proof") {
+        FailureClass::InvalidProof
+    } else if lowercase.contains("paused") {
+        FailureClass::TokenPaused
+    } else if lowercase.contains("out of gas") || lowercase.contains("gas required exceeds") {
+        FailureClass::OutOfGas
+    } else {
+        FailureClass::Unknown
+    }
+}
+
+/// A failure of a withdrawal finalization call that has returned an error.
+///
+/// Revert data is decoded as a revert reason string if possible.
+pub(crate) fn from_contract_error<M: Middleware>(
+    withdrawal_id: i64,
+    e: &ContractError<M>,
+) -> FinalizationFailure {
+    let reason = e.decode_revert::<String>().unwrap_or_else(|| e.to_string());
+
+    FinalizationFailure {
+        withdrawal_id,
+        class: classify(&reason),
+        reason,
+        revert_data: e.as_revert().map(|data| data.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use storage::FailureClass;
+
+    use super::classify;
+
+    #[test]
+    fn revert_reasons_are_classified() {
+        assert_eq!(classify("pw"), FailureClass::AlreadyFinalized);
+        assert_eq!(classify("jj"), FailureClass::AlreadyFinalized);
+        assert_eq!(classify("nq"), FailureClass::InvalidProof);
+        assert_eq!(classify("pi"), FailureClass::InvalidProof);
+        assert_eq!(classify("Pausable: paused"), FailureClass::TokenPaused);
+        assert_eq!(
+            classify("(code: -32000, message: out of gas, data: None)"),
+            FailureClass::OutOfGas
+        );
+        assert_eq!(
+            classify("ERC20: transfer amount exceeds balance"),
+            FailureClass::Unknown
+        );
+        assert_eq!(classify("pwned"), FailureClass::Unknown);
+    }
+}
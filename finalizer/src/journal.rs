@@ -0,0 +1,70 @@
+//! Journal of finalization transactions kept in the `sent_transactions` table.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+use storage::{SentTransaction, SentTransactionHash};
+use tx_sender::{Journal, JournaledTransaction, Submission};
+
+/// Submissions of a transaction recorded into its journal entry.
+pub(crate) struct SentTransactionJournal {
+    pool: PgPool,
+    sent_transaction_id: u64,
+    submitted: AtomicBool,
+}
+
+impl SentTransactionJournal {
+    pub(crate) fn new(pool: PgPool, sent_transaction_id: u64) -> Self {
+        Self {
+            pool,
+            sent_transaction_id,
+            submitted: AtomicBool::new(false),
+        }
+    }
+
+    /// Has any submission of the transaction been recorded, even if it
+    /// has failed to be written.
+    pub(crate) fn is_submitted(&self) -> bool {
+        self.submitted.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl Journal for SentTransactionJournal {
+    async fn record(
+        &self,
+        submission: &Submission,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.submitted.store(true, Ordering::Relaxed);
+
+        let hash = SentTransactionHash {
+            tx_hash: submission.tx_hash,
+            max_fee_per_gas: submission.max_fee_per_gas,
+            max_priority_fee_per_gas: submission.max_priority_fee_per_gas,
+        };
+
+        storage::add_sent_transaction_hash(&self.pool, self.sent_transaction_id, &hash).await?;
+
+        Ok(())
+    }
+}
+
+/// The journal entry as a transaction to resume sending.
+pub(crate) fn journaled_transaction(sent_tx: &SentTransaction) -> JournaledTransaction {
+    JournaledTransaction {
+        nonce: sent_tx.nonce,
+        to: sent_tx.to,
+        calldata: sent_tx.calldata.clone(),
+        gas_limit: sent_tx.gas_limit,
+        submissions: sent_tx
+            .tx_hashes
+            .iter()
+            .map(|hash| Submission {
+                tx_hash: hash.tx_hash,
+                max_fee_per_gas: hash.max_fee_per_gas,
+                max_priority_fee_per_gas: hash.max_priority_fee_per_gas,
+            })
+            .collect(),
+    }
+}
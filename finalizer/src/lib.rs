@@ -5,39 +5,64 @@
 
 //! Finalization logic implementation.
 
-use std::{collections::HashSet, str::FromStr, time::Duration};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use accumulator::WithdrawalsAccumulator;
 use ethers::{
     abi::Address,
     providers::{Middleware, MiddlewareError},
-    types::{H256, U256},
+    types::{
+        transaction::eip2718::TypedTransaction, BlockNumber, Eip1559TransactionRequest,
+        TransactionReceipt, TransactionRequest, H256, U256, U64,
+    },
 };
-use futures::TryFutureExt;
+use futures::{stream::FuturesUnordered, Future, FutureExt, StreamExt, TryFutureExt};
 use serde::Deserialize;
 use sqlx::PgPool;
+use storage::{
+    DryRunBatch, FailureClass, FinalizationFailure, Lease, OrderingPolicy, RetryPolicy,
+    SentTransaction, WithdrawalsToFinalize,
+};
+use tokio_util::sync::CancellationToken;
+use tx_sender::{FeeBudget, FeeStrategy, InFlightTransactions, NonceManager};
 
 use client::{
     is_eth, withdrawal_finalizer::codegen::withdrawal_finalizer::Result as FinalizeResult,
     WithdrawalKey,
 };
 use client::{
-    l1bridge::codegen::IL1Bridge, withdrawal_finalizer::codegen::WithdrawalFinalizer,
-    zksync_contract::codegen::IZkSync, WithdrawalParams, ZksyncMiddleware,
+    l1bridge::codegen::IL1Bridge,
+    withdrawal_finalizer::codegen::{RequestFinalizeWithdrawal, WithdrawalFinalizer},
+    zksync_contract::codegen::IZkSync,
+    WithdrawalParams, ZksyncMiddleware,
 };
 use withdrawals_meterer::{MeteringComponent, WithdrawalsMeter};
 
 use crate::{
     error::{Error, Result},
+    journal::{journaled_transaction, SentTransactionJournal},
     metrics::FINALIZER_METRICS,
 };
 
 mod accumulator;
 mod error;
+mod failures;
+mod journal;
 mod metrics;
+mod profitability;
+mod supervisor;
 
-/// A limit to cap a transaction fee (in ether) for safety reasons.
-const TX_FEE_LIMIT: f64 = 0.8;
+pub use profitability::{JsonFilePriceSource, PriceSource, ProfitabilityFilter, TokenThresholds};
+pub use supervisor::{supervise, RestartPolicy};
 
 /// When finalizer runs out of money back off this amount of time.
 const OUT_OF_FUNDS_BACKOFF: Duration = Duration::from_secs(10);
@@ -45,6 +70,58 @@ const OUT_OF_FUNDS_BACKOFF: Duration = Duration::from_secs(10);
 /// Backoff period if one of the loop iterations has failed.
 const LOOP_ITERATION_ERROR_BACKOFF: Duration = Duration::from_secs(5);
 
+/// Gas limit of a plain transfer used to fill a gap in nonces.
+const NONCE_GAP_FILLER_GAS_LIMIT: u64 = 21_000;
+
+/// Approximate number of last samples per-token gas averages are taken over.
+const MAX_GAS_SAMPLES: u64 = 1000;
+
+/// Gas limits of withdrawals that run out of gas are raised by this percent at a time.
+const GAS_LIMIT_RAISE_PERCENT: u64 = 50;
+
+/// Maximal number of withdrawals with raised gas limits finalized in a single batch.
+const RAISED_GAS_LIMIT_BATCH_SIZE: usize = 4;
+
+/// Resumed journaled transactions not mined within this period are left to the next reconciliation.
+const RESUMED_TX_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// A reason a sent transaction has failed.
+#[derive(Debug)]
+enum SendError {
+    /// An error returned by the provider.
+    Provider(String),
+    /// The account does not have enough funds to pay for gas.
+    GasRequiredExceedsAllowance(String),
+    /// The task sending the transaction has panicked or has been cancelled.
+    Aborted(String),
+    /// Any other error.
+    Other(String),
+}
+
+impl SendError {
+    fn new<M: Middleware>(e: <M as Middleware>::Error) -> Self {
+        if let Some(provider_error) = e.as_provider_error() {
+            Self::Provider(provider_error.to_string())
+        } else if is_gas_required_exceeds_allowance::<M>(&e) {
+            Self::GasRequiredExceedsAllowance(e.to_string())
+        } else {
+            Self::Other(e.to_string())
+        }
+    }
+}
+
+/// A transaction sent in the background with the outcome of sending it.
+struct SentBatch {
+    sent_transaction_id: u64,
+    nonce: U256,
+    ids: Vec<i64>,
+    withdrawals: Vec<WithdrawalKey>,
+    highest_batch_number: Option<U64>,
+    // Whether the transaction may have been submitted to the network.
+    submitted: bool,
+    result: std::result::Result<Option<TransactionReceipt>, SendError>,
+}
+
 /// An `enum` that defines a set of tokens that Finalizer finalizes.
 #[derive(Deserialize, Debug, Eq, PartialEq)]
 pub enum TokenList {
@@ -94,16 +171,28 @@ pub struct Finalizer<M1, M2> {
     finalizer_contract: WithdrawalFinalizer<M1>,
     zksync_contract: IZkSync<M2>,
     l1_bridge: IL1Bridge<M2>,
-    unsuccessful: Vec<WithdrawalParams>,
+    unsuccessful: Vec<(WithdrawalParams, FinalizeResult)>,
 
     no_new_withdrawals_backoff: Duration,
     query_db_pagination_limit: u64,
-    tx_fee_limit: U256,
+    fee_budget: FeeBudget,
     tx_retry_timeout: Duration,
     account_address: Address,
     withdrawals_meterer: Option<WithdrawalsMeter>,
     token_list: TokenList,
     eth_threshold: Option<U256>,
+    profitability_filter: ProfitabilityFilter,
+    confirmed_l1_block: Option<Arc<AtomicU64>>,
+    nonce_manager: NonceManager,
+    fee_strategy: Arc<dyn FeeStrategy<Arc<M1>>>,
+    max_in_flight: usize,
+    dry_run: bool,
+    retry_policy: RetryPolicy,
+    max_withdrawal_gas_limit: U256,
+    ordering_policy: OrderingPolicy,
+    lease: Lease,
+    in_flight: InFlightTransactions,
+    sent_batches: FuturesUnordered<Pin<Box<dyn Future<Output = SentBatch> + Send + Sync>>>,
 }
 
 const NO_NEW_WITHDRAWALS_BACKOFF: Duration = Duration::from_secs(5);
@@ -134,15 +223,23 @@ where
         token_list: TokenList,
         meter_withdrawals: bool,
         eth_threshold: Option<U256>,
+        profitability_filter: ProfitabilityFilter,
+        confirmed_l1_block: Option<Arc<AtomicU64>>,
+        fee_strategy: Arc<dyn FeeStrategy<Arc<S>>>,
+        fee_budget: FeeBudget,
+        max_in_flight: usize,
+        dry_run: bool,
+        retry_policy: RetryPolicy,
+        max_withdrawal_gas_limit: U256,
+        ordering_policy: OrderingPolicy,
+        lease: Lease,
     ) -> Self {
         let withdrawals_meterer = meter_withdrawals.then_some(WithdrawalsMeter::new(
             pgpool.clone(),
             MeteringComponent::FinalizedWithdrawals,
         ));
-        let tx_fee_limit = ethers::utils::parse_ether(TX_FEE_LIMIT)
-            .expect("{TX_FEE_LIMIT} ether is a parsable amount; qed");
 
-        tracing::info!("finalizing tokens {token_list:?}");
+        tracing::info!("finalizing tokens {token_list:?} ordered by {ordering_policy:?}");
 
         Self {
             pgpool,
@@ -154,51 +251,93 @@ where
             unsuccessful: vec![],
             no_new_withdrawals_backoff: NO_NEW_WITHDRAWALS_BACKOFF,
             query_db_pagination_limit: QUERY_DB_PAGINATION_LIMIT,
-            tx_fee_limit,
+            fee_budget,
             tx_retry_timeout: Duration::from_secs(tx_retry_timeout as u64),
             account_address,
             withdrawals_meterer,
             token_list,
             eth_threshold,
+            profitability_filter,
+            confirmed_l1_block,
+            nonce_manager: NonceManager::new(account_address),
+            fee_strategy,
+            max_in_flight: max_in_flight.max(1),
+            dry_run,
+            retry_policy,
+            // Withdrawals with raised gas limits have to fit into a batch at least alone.
+            max_withdrawal_gas_limit: std::cmp::max(
+                std::cmp::min(
+                    max_withdrawal_gas_limit,
+                    accumulator::max_request_gas_limit(batch_finalization_gas_limit),
+                ),
+                one_withdrawal_gas_limit,
+            ),
+            ordering_policy,
+            lease,
+            in_flight: InFlightTransactions::default(),
+            sent_batches: FuturesUnordered::new(),
         }
     }
 
     /// [`Finalizer`] main loop.
     ///
     /// `M2` is expected to be an [`ZksyncMiddleware`] to connect to L2.
-    pub async fn run<M2>(self, middleware: M2) -> Result<()>
+    ///
+    /// Once `shutdown` is cancelled no new withdrawals are picked and the
+    /// transactions in flight are waited for at most `drain_timeout`.
+    /// Loops that end are restarted by the `restart_policy`.
+    pub async fn run<M2>(
+        self,
+        middleware: M2,
+        shutdown: CancellationToken,
+        drain_timeout: Duration,
+        restart_policy: RestartPolicy,
+    ) -> Result<()>
     where
-        M2: ZksyncMiddleware + 'static,
+        M2: ZksyncMiddleware + Clone + 'static,
     {
-        let params_fetcher_handle = tokio::spawn(params_fetcher_loop(
-            self.pgpool.clone(),
-            middleware,
-            self.zksync_contract.clone(),
-            self.l1_bridge.clone(),
-        ));
-
-        let finalizer_handle = tokio::spawn(self.finalizer_loop());
+        let pgpool = self.pgpool.clone();
+        let zksync_contract = self.zksync_contract.clone();
+        let l1_bridge = self.l1_bridge.clone();
+
+        let params_fetcher = supervise("params_fetcher", restart_policy, shutdown.clone(), || {
+            params_fetcher_loop(
+                pgpool.clone(),
+                middleware.clone(),
+                zksync_contract.clone(),
+                l1_bridge.clone(),
+            )
+        });
+
+        // Transactions in flight are not forgotten by restarts of the loop.
+        let finalizer = tokio::sync::Mutex::new(self);
+        let finalizer_loop = supervise(
+            "finalizer_loop",
+            restart_policy,
+            shutdown.clone(),
+            || async {
+                finalizer
+                    .lock()
+                    .await
+                    .finalizer_loop(shutdown.clone(), drain_timeout)
+                    .await
+            },
+        );
 
+        // The params fetcher only ends once it has been restarted too often.
         tokio::select! {
-            m = params_fetcher_handle => {
-                tracing::error!("migrator ended with {m:?}");
-            }
-            f = finalizer_handle => {
-                tracing::error!("finalizer ended with {f:?}");
-            }
+            r = params_fetcher => r,
+            r = finalizer_loop => r,
         }
-
-        Ok(())
     }
 
-    async fn predict_fails<'a, W: Iterator<Item = &'a WithdrawalParams>>(
+    /// Simulate finalization of accumulated withdrawals, update their
+    /// gas estimates and return the ones predicted to fail.
+    async fn predict_fails(
         &mut self,
-        withdrawals: W,
+        accumulator: &mut WithdrawalsAccumulator,
     ) -> Result<Vec<FinalizeResult>> {
-        let w: Vec<_> = withdrawals
-            .cloned()
-            .map(|r| r.into_request_with_gaslimit(self.one_withdrawal_gas_limit))
-            .collect();
+        let w = accumulator.requests();
 
         let results = self
             .finalizer_contract
@@ -207,201 +346,873 @@ where
             .await?;
         tracing::info!("predicted results for withdrawals: {results:?}");
 
+        let samples: Vec<_> = accumulator
+            .set_simulated_gas(&results)
+            .into_iter()
+            .map(|(id, gas)| (id as i64, gas))
+            .collect();
+
+        if !samples.is_empty() {
+            storage::add_withdrawals_gas_samples(&self.pgpool, &samples, MAX_GAS_SAMPLES).await?;
+        }
+
         Ok(results
             .into_iter()
-            .filter(|p| !p.success || p.gas > self.one_withdrawal_gas_limit)
+            .filter(|p| {
+                let gas_limit = accumulator
+                    .withdrawal_gas_limit(p)
+                    .map_or(self.one_withdrawal_gas_limit, |(_, gas_limit)| gas_limit);
+
+                !p.success || p.gas > gas_limit
+            })
             .collect())
     }
 
-    async fn finalize_batch(&mut self, withdrawals: Vec<WithdrawalParams>) -> Result<()> {
-        let Some(highest_batch_number) = withdrawals.iter().map(|w| w.l1_batch_number).max() else {
+    // Raise gas limits of withdrawals that have run out of gas in simulation
+    // unless they have reached the maximal gas limit.
+    //
+    // Returns results of the withdrawals which gas limits have been raised
+    // and the rest of the withdrawals predicted to fail.
+    async fn raise_gas_limits(
+        &self,
+        accumulator: &WithdrawalsAccumulator,
+        predicted_to_fail: Vec<FinalizeResult>,
+    ) -> Result<(Vec<FinalizeResult>, Vec<FinalizeResult>)> {
+        let mut raised = vec![];
+        let mut rest = vec![];
+        let mut gas_limits = vec![];
+
+        for r in predicted_to_fail {
+            match accumulator.withdrawal_gas_limit(&r) {
+                Some((id, gas_limit))
+                    if gas_limit < self.max_withdrawal_gas_limit
+                        && is_out_of_gas(&r, gas_limit) =>
+                {
+                    let raised_gas_limit = std::cmp::min(
+                        gas_limit * (100 + GAS_LIMIT_RAISE_PERCENT) / 100,
+                        self.max_withdrawal_gas_limit,
+                    );
+
+                    tracing::info!(
+                        "withdrawal {id} has run out of {gas_limit} gas, raising its gas limit to {raised_gas_limit}"
+                    );
+
+                    gas_limits.push((id as i64, raised_gas_limit));
+                    raised.push(r);
+                }
+                _ => rest.push(r),
+            }
+        }
+
+        if !gas_limits.is_empty() {
+            storage::raise_withdrawals_gas_limits(&self.pgpool, &gas_limits).await?;
+
+            FINALIZER_METRICS
+                .raised_gas_limits
+                .inc_by(gas_limits.len() as u64);
+        }
+
+        Ok((raised, rest))
+    }
+
+    async fn finalize_batch(&mut self, withdrawals: Vec<(WithdrawalParams, U256)>) -> Result<()> {
+        let Some(highest_batch_number) = withdrawals.iter().map(|(w, _)| w.l1_batch_number).max()
+        else {
             return Ok(());
         };
 
         tracing::info!(
             "finalizing batch {:?}",
-            withdrawals.iter().map(|w| w.id).collect::<Vec<_>>()
+            withdrawals.iter().map(|(w, _)| w.id).collect::<Vec<_>>()
         );
 
-        let w: Vec<_> = withdrawals
-            .iter()
-            .cloned()
-            .map(|r| r.into_request_with_gaslimit(self.one_withdrawal_gas_limit))
-            .collect();
+        let (withdrawals, w): (Vec<_>, Vec<_>) = withdrawals
+            .into_iter()
+            .map(|(w, gas_limit)| (w.clone(), w.into_request_with_gaslimit(gas_limit)))
+            .unzip();
 
         let tx = self.finalizer_contract.finalize_withdrawals(w);
+        let ids: Vec<_> = withdrawals.iter().map(|w| w.id as i64).collect();
+
+        // Turn actual withdrawals into info to update db with.
+        let withdrawals = withdrawals.into_iter().map(|w| w.key()).collect::<Vec<_>>();
+
+        self.wait_for_in_flight_slot().await?;
+
         let nonce = self
-            .finalizer_contract
-            .client()
-            .get_transaction_count(self.account_address, None)
+            .nonce_manager
+            .next(self.finalizer_contract.client())
             .await
             .map_err(|e| Error::Middleware(format!("{e}")))?;
 
-        let tx = tx_sender::send_tx_adjust_gas(
-            self.finalizer_contract.client(),
-            tx.tx.clone(),
-            self.tx_retry_timeout,
+        self.send_transaction(
+            tx.tx,
             nonce,
             self.batch_finalization_gas_limit,
+            ids,
+            withdrawals,
+            Some(highest_batch_number),
         )
-        .await;
+        .await
+    }
 
-        let ids: Vec<_> = withdrawals.iter().map(|w| w.id as i64).collect();
+    // Journal a transaction and send it in the background with the given nonce.
+    //
+    // The outcome of sending is processed by `process_sent_batch` once it is
+    // taken from `sent_batches`.
+    async fn send_transaction(
+        &mut self,
+        tx: TypedTransaction,
+        nonce: U256,
+        gas_limit: U256,
+        ids: Vec<i64>,
+        withdrawals: Vec<WithdrawalKey>,
+        highest_batch_number: Option<U64>,
+    ) -> Result<()> {
+        let to = tx.to_addr().copied().unwrap_or_default();
+
+        let sent_transaction_id = match storage::add_sent_transaction(
+            &self.pgpool,
+            self.account_address,
+            nonce,
+            to,
+            tx.data().map(|d| d.as_ref()).unwrap_or_default(),
+            gas_limit,
+            &ids,
+        )
+        .await
+        {
+            Ok(id) => id,
+            Err(e) => {
+                self.nonce_manager.reset();
+                return Err(e.into());
+            }
+        };
 
-        // Turn actual withdrawals into info to update db with.
-        let withdrawals = withdrawals.into_iter().map(|w| w.key()).collect::<Vec<_>>();
+        self.in_flight.register(nonce);
+
+        let client = self.finalizer_contract.client();
+        let journal = SentTransactionJournal::new(self.pgpool.clone(), sent_transaction_id);
+        let retry_timeout = self.tx_retry_timeout;
+        let in_flight = self.in_flight.clone();
+        let fee_strategy = self.fee_strategy.clone();
+
+        let sending = tokio::spawn({
+            let in_flight = in_flight.clone();
+
+            async move {
+                let result = tx_sender::send_journaled_tx_adjust_gas(
+                    client,
+                    fee_strategy.as_ref(),
+                    &journal,
+                    tx,
+                    retry_timeout,
+                    nonce,
+                    gas_limit,
+                    &in_flight,
+                )
+                .await
+                .map_err(SendError::new::<S>);
 
-        match tx {
-            Ok(Some(tx)) if tx.status.expect("EIP-658 is enabled; qed").is_zero() => {
-                tracing::error!(
-                    "withdrawal transaction {:?} was reverted",
-                    tx.transaction_hash
-                );
+                in_flight.finished(nonce);
+
+                (result, journal.is_submitted())
+            }
+        });
+
+        self.sent_batches.push(Box::pin(sending.map(move |sent| {
+            let (result, submitted) = sent.unwrap_or_else(|e| {
+                // The task has not got to stop tracking its nonce.
+                in_flight.finished(nonce);
+                (Err(SendError::Aborted(e.to_string())), true)
+            });
+
+            SentBatch {
+                sent_transaction_id,
+                nonce,
+                ids,
+                withdrawals,
+                highest_batch_number,
+                submitted,
+                result,
+            }
+        })));
+
+        Ok(())
+    }
 
-                FINALIZER_METRICS.reverted_withdrawal_transactions.inc();
+    // Wait until there is room for one more transaction in flight.
+    async fn wait_for_in_flight_slot(&mut self) -> Result<()> {
+        while self.sent_batches.len() >= self.max_in_flight {
+            let Some(sent) = self.sent_batches.next().await else {
+                break;
+            };
 
-                storage::inc_unsuccessful_finalization_attempts(&self.pgpool, &withdrawals).await?;
+            self.process_sent_batch(sent).await?;
+        }
 
-                return Err(Error::WithdrawalTransactionReverted);
-            }
+        Ok(())
+    }
+
+    // Process the outcome of all sent transactions that are already known
+    // without waiting for the rest.
+    async fn process_sent_batches(&mut self) -> Result<()> {
+        while let Some(Some(sent)) = self.sent_batches.next().now_or_never() {
+            self.process_sent_batch(sent).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn process_sent_batch(&mut self, sent: SentBatch) -> Result<()> {
+        let SentBatch {
+            sent_transaction_id,
+            nonce,
+            ids,
+            withdrawals,
+            highest_batch_number,
+            submitted,
+            result,
+        } = sent;
+
+        match result {
             Ok(Some(tx)) => {
-                tracing::info!(
-                    "withdrawal transaction {:?} successfully mined",
-                    tx.transaction_hash
-                );
+                let succeeded = self
+                    .process_mined_transaction(sent_transaction_id, &ids, tx)
+                    .await?;
 
-                storage::finalization_data_set_finalized_in_tx(
+                if let Some(highest_batch_number) = highest_batch_number.filter(|_| succeeded) {
+                    FINALIZER_METRICS
+                        .highest_finalized_batch_number
+                        .set(highest_batch_number.as_u64() as i64);
+                }
+            }
+            // The transaction has not been sent as its max fee per gas is below
+            // the base fee, its nonce has not been used and the journal entry
+            // is dropped by the reconciler once nothing is in flight.
+            Ok(None) if !submitted => {
+                tracing::warn!("transaction with nonce {nonce} has not been sent");
+
+                if !self.in_flight.nonces().into_iter().any(|n| n > nonce) {
+                    self.nonce_manager.reset();
+                }
+            }
+            // The transaction has been dropped from the mempool or has not been
+            // mined in time, its nonce may be free again and the journal entry
+            // is resumed by the reconciler once nothing is in flight.
+            Ok(None) => {
+                tracing::warn!("sent transaction resolved with none result",);
+                self.try_fill_nonce_gap(sent_transaction_id, nonce).await;
+            }
+            Err(e) => {
+                // The lease and the failures are recorded before filling the gap
+                // for them to not be lost if filling the gap fails.
+                let recorded = self.record_send_error(&ids, &withdrawals, e).await;
+                self.try_fill_nonce_gap(sent_transaction_id, nonce).await;
+
+                recorded?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Record the failure of sending a finalization transaction.
+    async fn record_send_error(
+        &self,
+        ids: &[i64],
+        withdrawals: &[WithdrawalKey],
+        e: SendError,
+    ) -> Result<()> {
+        tracing::error!(
+            "waiting for transaction status withdrawals failed with an error {:?}",
+            e
+        );
+
+        match e {
+            SendError::Provider(provider_error) => {
+                tracing::error!("failed to send finalization transaction: {provider_error}");
+            }
+            SendError::GasRequiredExceedsAllowance(e) => {
+                tracing::error!("failed to send finalization withdrawal tx: {e}");
+                FINALIZER_METRICS
+                    .failed_to_finalize_low_gas
+                    .inc_by(withdrawals.len() as u64);
+
+                tokio::time::sleep(OUT_OF_FUNDS_BACKOFF).await;
+            }
+            // The transaction may or may not have been sent, if it has the
+            // withdrawals are not picked again while its journal entry is pending.
+            SendError::Aborted(e) => {
+                tracing::error!("sending finalization transaction has been aborted: {e}");
+                FINALIZER_METRICS.aborted_sending_tasks.inc();
+
+                storage::release_leased_withdrawals(&self.pgpool, &self.lease.holder, ids).await?;
+            }
+            SendError::Other(e) => {
+                tracing::error!("finalization transaction has failed: {e}");
+                let failures: Vec<_> = ids
+                    .iter()
+                    .map(|id| FinalizationFailure {
+                        withdrawal_id: *id,
+                        class: failures::classify(&e),
+                        reason: format!("finalization transaction failed: {e}"),
+                        revert_data: None,
+                    })
+                    .collect();
+
+                count_failures(&failures);
+
+                storage::inc_unsuccessful_finalization_attempts(
                     &self.pgpool,
-                    &withdrawals,
-                    tx.transaction_hash,
+                    &failures,
+                    &self.retry_policy,
                 )
                 .await?;
+            }
+        }
+        // no need to bump the counter here, waiting for tx
+        // has failed becuase of networking or smth, but at
+        // this point tx has already been accepted into tx pool
 
-                FINALIZER_METRICS
-                    .highest_finalized_batch_number
-                    .set(highest_batch_number.as_u64() as i64);
+        Ok(())
+    }
 
-                if let Some(ref mut withdrawals_meterer) = self.withdrawals_meterer {
-                    if let Err(e) = withdrawals_meterer.meter_withdrawals_storage(&ids).await {
-                        tracing::error!("Failed to meter the withdrawals: {e}");
-                    }
+    // Fill the gap of a nonce that has not been used, logging a failure to.
+    async fn try_fill_nonce_gap(&mut self, sent_transaction_id: u64, nonce: U256) {
+        if let Err(e) = self.fill_nonce_gap(sent_transaction_id, nonce).await {
+            tracing::error!("failed to fill the gap of nonce {nonce}: {e}");
+        }
+    }
+
+    // A transaction with `nonce` has not been mined. If its nonce has never
+    // been used while transactions with higher nonces are in flight those
+    // would be stuck forever, so the gap is filled with a zero-value
+    // transfer to self.
+    async fn fill_nonce_gap(&mut self, sent_transaction_id: u64, nonce: U256) -> Result<()> {
+        if !self.in_flight.nonces().into_iter().any(|n| n > nonce) {
+            self.nonce_manager.reset();
+            return Ok(());
+        }
+
+        let pending_nonce = self
+            .finalizer_contract
+            .client()
+            .get_transaction_count(self.account_address, Some(BlockNumber::Pending.into()))
+            .await
+            .map_err(|e| Error::Middleware(format!("{e}")))?;
+
+        if pending_nonce > nonce {
+            return Ok(());
+        }
+
+        tracing::warn!("nonce {nonce} has not been used, filling the gap");
+        FINALIZER_METRICS.filled_nonce_gaps.inc();
+
+        storage::sent_transaction_dropped(&self.pgpool, sent_transaction_id).await?;
+
+        let tx = TransactionRequest::new().to(self.account_address).value(0);
+
+        self.send_transaction(
+            tx.into(),
+            nonce,
+            NONCE_GAP_FILLER_GAS_LIMIT.into(),
+            vec![],
+            vec![],
+            None,
+        )
+        .await
+    }
+
+    // Update the storage with the outcome of a mined finalization transaction.
+    //
+    // A reverted transaction is recorded as failed attempts of its withdrawals,
+    // returns whether the transaction has succeeded.
+    async fn process_mined_transaction(
+        &mut self,
+        sent_transaction_id: u64,
+        ids: &[i64],
+        tx: TransactionReceipt,
+    ) -> Result<bool> {
+        let fee_paid = tx
+            .gas_used
+            .unwrap_or_default()
+            .saturating_mul(tx.effective_gas_price.unwrap_or_default());
+
+        if tx.status.expect("EIP-658 is enabled; qed").is_zero() {
+            tracing::error!(
+                "withdrawal transaction {:?} was reverted",
+                tx.transaction_hash
+            );
+
+            FINALIZER_METRICS.reverted_withdrawal_transactions.inc();
+
+            let failures = self.replay_reverted_withdrawals(ids).await?;
+            count_failures(&failures);
+
+            storage::sent_transaction_reverted(
+                &self.pgpool,
+                sent_transaction_id,
+                tx.transaction_hash,
+                fee_paid,
+                &failures,
+                &self.retry_policy,
+            )
+            .await?;
+
+            return Ok(false);
+        }
+
+        tracing::info!(
+            "withdrawal transaction {:?} successfully mined",
+            tx.transaction_hash
+        );
+
+        storage::sent_transaction_mined(
+            &self.pgpool,
+            sent_transaction_id,
+            tx.transaction_hash,
+            fee_paid,
+        )
+        .await?;
+
+        if let Some(ref mut withdrawals_meterer) = self.withdrawals_meterer {
+            if let Err(e) = withdrawals_meterer.meter_withdrawals_storage(ids).await {
+                tracing::error!("Failed to meter the withdrawals: {e}");
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Resolve the outcome of the journaled finalization transactions that
+    // have never been seen mined, for instance because the finalizer has been
+    // restarted while they were in flight.
+    //
+    // * transactions never sent to the network are dropped.
+    // * transactions with any of the submissions mined are attributed to it.
+    // * transactions whose nonce has been used by an unknown transaction are dropped.
+    // * other transactions are resumed with bumped fees concurrently, the ones
+    //   still pending on `shutdown` are left to the next reconciliation.
+    async fn reconcile_sent_transactions(&mut self, shutdown: &CancellationToken) -> Result<()> {
+        let pending =
+            storage::pending_sent_transactions(&self.pgpool, self.account_address).await?;
+
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        tracing::info!("reconciling {} journaled transactions", pending.len());
+
+        let client = self.finalizer_contract.client();
+        let latest_nonce = client
+            .get_transaction_count(self.account_address, Some(BlockNumber::Latest.into()))
+            .await
+            .map_err(|e| Error::Middleware(format!("{e}")))?;
+
+        let mut to_resume = vec![];
+
+        for sent_tx in pending {
+            FINALIZER_METRICS.reconciled_sent_transactions.inc();
+
+            if sent_tx.tx_hashes.is_empty() {
+                tracing::info!(
+                    "journaled transaction with nonce {} has never been sent",
+                    sent_tx.nonce
+                );
+                storage::sent_transaction_dropped(&self.pgpool, sent_tx.id).await?;
+                continue;
+            }
+
+            let mut receipt = None;
+            for sent in &sent_tx.tx_hashes {
+                receipt = client
+                    .get_transaction_receipt(sent.tx_hash)
+                    .await
+                    .map_err(|e| Error::Middleware(format!("{e}")))?;
+
+                if receipt.is_some() {
+                    break;
                 }
             }
-            // TODO: why would a pending tx resolve to `None`?
-            Ok(None) => {
-                tracing::warn!("sent transaction resolved with none result",);
+
+            match receipt {
+                Some(tx) => self.process_journaled_transaction(&sent_tx, tx).await,
+                None if latest_nonce > sent_tx.nonce => {
+                    tracing::warn!(
+                        "nonce {} of journaled transaction has been used by an unknown transaction",
+                        sent_tx.nonce
+                    );
+                    storage::sent_transaction_dropped(&self.pgpool, sent_tx.id).await?;
+                }
+                None => to_resume.push(sent_tx),
             }
-            Err(e) => {
-                tracing::error!(
-                    "waiting for transaction status withdrawals failed with an error {:?}",
-                    e
+        }
+
+        let fee_strategy = self.fee_strategy.clone();
+        let pgpool = self.pgpool.clone();
+        let retry_timeout = self.tx_retry_timeout;
+
+        let resuming = futures::future::join_all(to_resume.iter().map(|sent_tx| {
+            let client = client.clone();
+            let fee_strategy = fee_strategy.clone();
+            let journal = SentTransactionJournal::new(pgpool.clone(), sent_tx.id);
+
+            async move {
+                tracing::info!(
+                    "resuming journaled transaction with nonce {}",
+                    sent_tx.nonce
                 );
 
-                if let Some(provider_error) = e.as_provider_error() {
-                    tracing::error!("failed to send finalization transaction: {provider_error}");
-                } else if !is_gas_required_exceeds_allowance::<S>(&e) {
-                    storage::inc_unsuccessful_finalization_attempts(&self.pgpool, &withdrawals)
-                        .await?;
-                } else {
-                    tracing::error!("failed to send finalization withdrawal tx: {e}");
-                    FINALIZER_METRICS
-                        .failed_to_finalize_low_gas
-                        .inc_by(withdrawals.len() as u64);
+                tokio::time::timeout(
+                    RESUMED_TX_TIMEOUT,
+                    tx_sender::resume_journaled_tx(
+                        client,
+                        fee_strategy.as_ref(),
+                        &journal,
+                        &journaled_transaction(sent_tx),
+                        retry_timeout,
+                    ),
+                )
+                .await
+            }
+        }));
+
+        let resumed = tokio::select! {
+            resumed = resuming => resumed,
+            _ = shutdown.cancelled() => {
+                tracing::info!(
+                    "leaving {} resumed transactions to the next reconciliation on shutdown",
+                    to_resume.len()
+                );
+                self.nonce_manager.reset();
+                return Ok(());
+            }
+        };
 
-                    tokio::time::sleep(OUT_OF_FUNDS_BACKOFF).await;
+        for (sent_tx, resumed) in to_resume.iter().zip(resumed) {
+            match resumed {
+                Ok(Ok(Some(tx))) => self.process_journaled_transaction(sent_tx, tx).await,
+                Ok(Ok(None)) => {
+                    tracing::warn!("resumed transaction resolved with none result");
+                }
+                Ok(Err(e)) => {
+                    tracing::error!("failed to resume journaled transaction: {e}");
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        "resumed transaction with nonce {} has not been mined within {RESUMED_TX_TIMEOUT:?}",
+                        sent_tx.nonce
+                    );
+                    FINALIZER_METRICS.timed_out_resumed_transactions.inc();
                 }
-                // no need to bump the counter here, waiting for tx
-                // has failed becuase of networking or smth, but at
-                // this point tx has already been accepted into tx pool
             }
         }
 
+        self.nonce_manager.reset();
+
         Ok(())
     }
 
-    // Create a new withdrawal accumulator given the current gas price.
-    async fn new_accumulator(&self) -> Result<WithdrawalsAccumulator> {
-        let gas_price = self
-            .finalizer_contract
-            .client()
-            .get_gas_price()
+    // Record the outcome of a mined journaled transaction.
+    async fn process_journaled_transaction(
+        &mut self,
+        sent_tx: &SentTransaction,
+        tx: TransactionReceipt,
+    ) {
+        if let Err(e) = self
+            .process_mined_transaction(sent_tx.id, &sent_tx.withdrawal_ids, tx)
+            .await
+        {
+            tracing::error!("journaled transaction {} failed: {e}", sent_tx.id);
+        }
+    }
+
+    // The max fee per gas the fee strategy is going to pay,
+    // `None` if it is below the base fee.
+    async fn max_fee_per_gas(&self) -> Result<Option<U256>> {
+        let mut tx = Eip1559TransactionRequest::new().into();
+
+        let priced = self
+            .fee_strategy
+            .estimate(&self.finalizer_contract.client(), &mut tx)
             .await
             .map_err(|e| Error::Middleware(format!("{e}")))?;
 
-        Ok(WithdrawalsAccumulator::new(
+        Ok(priced.then(|| tx.gas_price().unwrap_or_default()))
+    }
+
+    // Check that the hourly and daily fee budget allows for one more
+    // finalization transaction paying at most `gas_price` per gas.
+    async fn fee_budget_allows_tx(&self, gas_price: U256) -> Result<bool> {
+        let spent_last_hour =
+            storage::fees_spent(&self.pgpool, self.account_address, FeeBudget::HOUR).await?;
+        let spent_last_day =
+            storage::fees_spent(&self.pgpool, self.account_address, FeeBudget::DAY).await?;
+        let status = self.fee_budget.status(spent_last_hour, spent_last_day);
+
+        let Some(remaining) = status.remaining else {
+            return Ok(true);
+        };
+
+        FINALIZER_METRICS.remaining_fee_budget.set(
+            ethers::utils::format_ether(remaining)
+                .parse()
+                .unwrap_or_default(),
+        );
+
+        if remaining >= self.batch_finalization_gas_limit.saturating_mul(gas_price) {
+            return Ok(true);
+        }
+
+        tracing::warn!(
+            "fee budget is exhausted with {} ether spent within last hour and {} ether within last day, pausing finalization",
+            ethers::utils::format_ether(status.spent_last_hour),
+            ethers::utils::format_ether(status.spent_last_day),
+        );
+        FINALIZER_METRICS.paused_by_fee_budget.inc();
+
+        Ok(false)
+    }
+
+    // Create a new withdrawal accumulator given the max fee per gas
+    // the fee strategy is going to pay.
+    async fn new_accumulator(
+        &self,
+        max_withdrawals: Option<usize>,
+    ) -> Result<WithdrawalsAccumulator> {
+        let gas_price = self
+            .max_fee_per_gas()
+            .await?
+            .ok_or(Error::MaxFeePerGasBelowBaseFee)?;
+
+        let accumulator = WithdrawalsAccumulator::new(
             gas_price,
-            self.tx_fee_limit,
+            self.fee_budget.tx_limit(),
             self.batch_finalization_gas_limit,
             self.one_withdrawal_gas_limit,
-        ))
+        );
+
+        Ok(match max_withdrawals {
+            Some(max_withdrawals) => accumulator.with_max_withdrawals(max_withdrawals),
+            None => accumulator,
+        })
     }
 
-    async fn finalizer_loop(mut self)
+    async fn finalizer_loop(&mut self, shutdown: CancellationToken, drain_timeout: Duration)
     where
         S: Middleware,
         M: Middleware,
     {
-        loop {
-            if let Err(e) = self.loop_iteration().await {
+        // Iterations are not interrupted to never leave a sent transaction unrecorded.
+        while !shutdown.is_cancelled() {
+            if let Err(e) = self.loop_iteration(&shutdown).await {
                 tracing::error!("iteration of finalizer loop has ended with {e}");
                 tokio::time::sleep(LOOP_ITERATION_ERROR_BACKOFF).await;
             }
         }
+
+        self.drain(drain_timeout).await;
     }
 
-    async fn loop_iteration(&mut self) -> Result<()> {
-        tracing::debug!("begin iteration of the finalizer loop");
+    // Wait for the transactions in flight to be mined and recorded
+    // and release the leased withdrawals once all of them are.
+    //
+    // Transactions still in flight after the timeout are reconciled on restart.
+    async fn drain(&mut self, timeout: Duration) {
+        tracing::info!(
+            "waiting for {} transactions in flight to be mined",
+            self.sent_batches.len()
+        );
 
-        let try_finalize_these = match &self.token_list {
-            TokenList::All => {
-                storage::withdrawals_to_finalize(
-                    &self.pgpool,
-                    self.query_db_pagination_limit,
-                    self.eth_threshold,
-                )
-                .await?
+        let drained = tokio::time::timeout(timeout, async {
+            while let Some(sent) = self.sent_batches.next().await {
+                // Withdrawals of a transaction that has failed to be recorded
+                // are not picked again while its journal entry is pending.
+                if let Err(e) = self.process_sent_batch(sent).await {
+                    tracing::error!("failed to record a transaction in flight: {e}");
+                }
             }
-            TokenList::WhiteList(w) => {
-                storage::withdrawals_to_finalize_with_whitelist(
-                    &self.pgpool,
-                    self.query_db_pagination_limit,
-                    w,
-                    self.eth_threshold,
-                )
-                .await?
+        })
+        .await;
+
+        match drained {
+            Ok(()) => tracing::info!("all transactions in flight have been processed"),
+            Err(_) => {
+                // Leases are kept for other instances not to send these withdrawals again.
+                tracing::warn!(
+                    "{} transactions are still in flight, they are going to be reconciled on restart",
+                    self.sent_batches.len()
+                );
+                return;
             }
-            TokenList::BlackList(b) => {
-                storage::withdrawals_to_finalize_with_blacklist(
-                    &self.pgpool,
-                    self.query_db_pagination_limit,
-                    b,
-                    self.eth_threshold,
-                )
-                .await?
+        }
+
+        if let Err(e) = storage::release_withdrawal_leases(&self.pgpool, &self.lease.holder).await {
+            tracing::error!("failed to release leased withdrawals: {e}");
+        }
+    }
+
+    async fn loop_iteration(&mut self, shutdown: &CancellationToken) -> Result<()> {
+        tracing::debug!("begin iteration of the finalizer loop");
+
+        // Nothing is sent in dry run mode.
+        if !self.dry_run {
+            self.process_sent_batches().await?;
+
+            // Journaled transactions can only be told apart from the ones
+            // currently in flight once nothing is being sent.
+            if self.sent_batches.is_empty() {
+                self.reconcile_sent_transactions(shutdown).await?;
             }
+        }
+
+        let Some(max_fee_per_gas) = self.max_fee_per_gas().await? else {
+            tracing::warn!("max fee per gas is below the base fee, pausing finalization");
+            FINALIZER_METRICS.paused_by_max_fee_per_gas.inc();
+            tokio::time::sleep(self.no_new_withdrawals_backoff).await;
+            return Ok(());
+        };
+
+        if !self.fee_budget_allows_tx(max_fee_per_gas).await? {
+            tokio::time::sleep(self.no_new_withdrawals_backoff).await;
+            return Ok(());
+        }
+
+        // Only consider batches executed in L1 blocks with enough confirmations.
+        let max_execute_l1_block = self
+            .confirmed_l1_block
+            .as_ref()
+            .map(|b| b.load(Ordering::Relaxed));
+
+        let (eth_threshold, token_thresholds) = self
+            .profitability_filter
+            .thresholds(self.eth_threshold)
+            .await?;
+
+        if eth_threshold.is_some() || !token_thresholds.is_empty() {
+            let unprofitable = storage::unprofitable_withdrawals_count(
+                &self.pgpool,
+                eth_threshold,
+                &token_thresholds,
+            )
+            .await?;
+
+            FINALIZER_METRICS.skipped_as_unprofitable.set(unprofitable);
+        }
+
+        let given_up = storage::given_up_withdrawals_count(&self.pgpool).await?;
+        FINALIZER_METRICS.given_up_withdrawals.set(given_up);
+
+        let query = WithdrawalsToFinalize::new(self.query_db_pagination_limit)
+            .with_eth_threshold(eth_threshold)
+            .with_token_thresholds(&token_thresholds)
+            .with_max_execute_l1_block(max_execute_l1_block)
+            .skip_dry_run(self.dry_run)
+            .ordered_by(self.ordering_policy)
+            .with_lease(self.lease.clone());
+
+        let query = match &self.token_list {
+            TokenList::All => query,
+            TokenList::WhiteList(w) => query.with_whitelist(w),
+            TokenList::BlackList(b) => query.with_blacklist(b),
             TokenList::None => return Ok(()),
         };
 
+        let try_finalize_these = query.fetch(&self.pgpool).await?;
+
         tracing::debug!("trying to finalize these {try_finalize_these:?}");
 
         if try_finalize_these.is_empty() {
-            tokio::time::sleep(self.no_new_withdrawals_backoff).await;
+            if self.sent_batches.is_empty() {
+                tokio::time::sleep(self.no_new_withdrawals_backoff).await;
+            } else if let Ok(Some(sent)) =
+                tokio::time::timeout(self.no_new_withdrawals_backoff, self.sent_batches.next())
+                    .await
+            {
+                self.process_sent_batch(sent).await?;
+            }
             return Ok(());
         }
 
-        let mut accumulator = self.new_accumulator().await?;
-        let mut iter = try_finalize_these.into_iter().peekable();
+        let ids: Vec<_> = try_finalize_these.iter().map(|w| w.id as i64).collect();
+        let gas_estimates: HashMap<_, _> = storage::withdrawals_gas_estimates(&self.pgpool, &ids)
+            .await?
+            .into_iter()
+            .collect();
+        let gas_limits: HashMap<_, _> = storage::withdrawals_gas_limits(&self.pgpool, &ids)
+            .await?
+            .into_iter()
+            .map(|(id, gas_limit)| (id, std::cmp::min(gas_limit, self.max_withdrawal_gas_limit)))
+            .filter(|(_, gas_limit)| *gas_limit > self.one_withdrawal_gas_limit)
+            .collect();
 
-        while let Some(t) = iter.next() {
-            accumulator.add_withdrawal(t);
+        // Withdrawals with raised gas limits are finalized in dedicated smaller
+        // batches to not take the gas of the batches of ordinary withdrawals.
+        let (raised, ordinary): (Vec<_>, Vec<_>) = try_finalize_these
+            .into_iter()
+            .partition(|w| gas_limits.contains_key(&w.id));
+
+        if self
+            .finalize_withdrawals(ordinary, &gas_estimates, &gas_limits, None)
+            .await?
+        {
+            self.finalize_withdrawals(
+                raised,
+                &gas_estimates,
+                &gas_limits,
+                Some(RAISED_GAS_LIMIT_BATCH_SIZE),
+            )
+            .await?;
+        }
+
+        self.process_unsuccessful().await
+    }
+
+    // Accumulate withdrawals into batches and finalize them.
+    //
+    // Returns `false` if finalization has been paused by the fee budget.
+    async fn finalize_withdrawals(
+        &mut self,
+        withdrawals: Vec<WithdrawalParams>,
+        gas_estimates: &HashMap<u64, U256>,
+        gas_limits: &HashMap<u64, U256>,
+        max_withdrawals: Option<usize>,
+    ) -> Result<bool> {
+        if withdrawals.is_empty() {
+            return Ok(true);
+        }
 
-            if accumulator.ready_to_finalize() || iter.peek().is_none() {
+        let mut accumulator = self.new_accumulator(max_withdrawals).await?;
+        let mut pending = VecDeque::from(withdrawals);
+        let mut predicted_failures = vec![];
+
+        while let Some(t) = pending.pop_front() {
+            let estimated_gas = gas_estimates.get(&t.id).copied();
+            let gas_limit = gas_limits.get(&t.id).copied();
+
+            // Withdrawals that do not fit into the batch are left to the next one.
+            let mut full = !accumulator.is_empty() && !accumulator.fits(estimated_gas, gas_limit);
+
+            if full {
+                pending.push_front(t);
+            } else {
+                accumulator.add_withdrawal(t, estimated_gas, gas_limit);
+            }
+
+            if full || accumulator.ready_to_finalize() || pending.is_empty() {
                 tracing::info!(
                     "predicting results for withdrawals: {:?}",
                     accumulator.withdrawals().map(|w| w.id).collect::<Vec<_>>()
                 );
 
-                let predicted_to_fail = self.predict_fails(accumulator.withdrawals()).await?;
+                let predicted_to_fail = self.predict_fails(&mut accumulator).await?;
+
+                // Withdrawals with raised gas limits are retried in the next iterations.
+                let (raised, predicted_to_fail) = self
+                    .raise_gas_limits(&accumulator, predicted_to_fail)
+                    .await?;
+                accumulator.remove_unsuccessful(&raised);
 
                 FINALIZER_METRICS
                     .predicted_to_fail_withdrawals
@@ -410,20 +1221,215 @@ where
                 tracing::debug!("predicted to fail: {predicted_to_fail:?}");
 
                 if !predicted_to_fail.is_empty() {
-                    let mut removed = accumulator.remove_unsuccessful(&predicted_to_fail);
+                    let removed = accumulator.remove_unsuccessful(&predicted_to_fail);
+                    let failures = removed.into_iter().filter_map(|w| {
+                        predicted_to_fail
+                            .iter()
+                            .find(|r| {
+                                r.l_2_block_number.as_u64() == w.l1_batch_number.as_u64()
+                                    && r.l_2_message_index.as_u64() == w.l2_message_index as u64
+                            })
+                            .map(|r| (w, r.clone()))
+                    });
+
+                    if self.dry_run {
+                        predicted_failures.extend(failures);
+                    } else {
+                        self.unsuccessful.extend(failures);
+                    }
+                }
 
-                    self.unsuccessful.append(&mut removed);
+                // Simulated gas may exceed the estimates the withdrawals have been added by.
+                let excess = accumulator.remove_excess();
+                full |= !excess.is_empty();
+
+                for w in excess.into_iter().rev() {
+                    pending.push_front(w);
                 }
             }
 
-            if accumulator.ready_to_finalize() || iter.peek().is_none() {
+            if full || accumulator.ready_to_finalize() || pending.is_empty() {
+                if !self.fee_budget_allows_tx(accumulator.gas_price()).await? {
+                    tokio::time::sleep(self.no_new_withdrawals_backoff).await;
+                    return Ok(false);
+                }
+
                 let requests = accumulator.take_withdrawals();
-                self.finalize_batch(requests).await?;
-                accumulator = self.new_accumulator().await?;
+
+                if self.dry_run {
+                    let predicted_failures = std::mem::take(&mut predicted_failures);
+                    self.record_dry_run_batch(requests, predicted_failures)
+                        .await?;
+                } else {
+                    self.finalize_batch(requests).await?;
+                }
+                accumulator = self.new_accumulator(max_withdrawals).await?;
             }
         }
 
-        self.process_unsuccessful().await
+        Ok(true)
+    }
+
+    // Record a batch of withdrawals that would have been finalized
+    // instead of sending a transaction in dry run mode.
+    async fn record_dry_run_batch(
+        &self,
+        withdrawals: Vec<(WithdrawalParams, U256)>,
+        predicted_failures: Vec<(WithdrawalParams, FinalizeResult)>,
+    ) -> Result<()> {
+        let mut batch = DryRunBatch {
+            withdrawal_ids: vec![],
+            predicted_success: vec![],
+            predicted_gas: vec![],
+            calldata_size: 0,
+            estimated_gas: None,
+        };
+
+        if !withdrawals.is_empty() {
+            let w: Vec<_> = withdrawals
+                .iter()
+                .cloned()
+                .map(|(r, gas_limit)| r.into_request_with_gaslimit(gas_limit))
+                .collect();
+
+            let tx = self.finalizer_contract.finalize_withdrawals(w);
+            batch.calldata_size = tx.tx.data().map_or(0, |d| d.len() as u64);
+
+            let results = tx.call().await?;
+
+            batch.estimated_gas = match tx.estimate_gas().await {
+                Ok(gas) => Some(gas),
+                Err(e) => {
+                    tracing::warn!("failed to estimate gas of a dry run batch: {e}");
+                    None
+                }
+            };
+
+            for ((w, _), r) in withdrawals.iter().zip(results) {
+                batch.withdrawal_ids.push(w.id as i64);
+                batch.predicted_success.push(r.success);
+                batch.predicted_gas.push(r.gas);
+            }
+        }
+
+        for (w, r) in &predicted_failures {
+            batch.withdrawal_ids.push(w.id as i64);
+            batch.predicted_success.push(r.success);
+            batch.predicted_gas.push(r.gas);
+        }
+
+        if batch.withdrawal_ids.is_empty() {
+            return Ok(());
+        }
+
+        tracing::info!(
+            "dry run: would finalize batch {:?} with {} bytes of calldata and estimated gas {:?}, predicted to fail {:?}",
+            withdrawals.iter().map(|(w, _)| w.id).collect::<Vec<_>>(),
+            batch.calldata_size,
+            batch.estimated_gas,
+            predicted_failures.iter().map(|(w, _)| w.id).collect::<Vec<_>>(),
+        );
+
+        FINALIZER_METRICS
+            .dry_run_withdrawals
+            .inc_by(withdrawals.len() as u64);
+
+        storage::add_dry_run_batch(&self.pgpool, &batch).await?;
+
+        Ok(())
+    }
+
+    // Find out why a withdrawal predicted to fail by the finalizer contract fails.
+    async fn classify_predicted_failure(
+        &self,
+        withdrawal: WithdrawalParams,
+        result: FinalizeResult,
+    ) -> FinalizationFailure {
+        let withdrawal_id = withdrawal.id as i64;
+
+        // The withdrawal succeeds but needs more gas than is given to a single withdrawal.
+        if result.success {
+            return FinalizationFailure {
+                withdrawal_id,
+                class: FailureClass::OutOfGas,
+                reason: predicted_failure_reason(&result),
+                revert_data: None,
+            };
+        }
+
+        let request = withdrawal.into_request_with_gaslimit(self.max_withdrawal_gas_limit);
+
+        self.replay_withdrawal(withdrawal_id, request).await
+    }
+
+    // Find out why withdrawals of a reverted finalization transaction fail.
+    async fn replay_reverted_withdrawals(&self, ids: &[i64]) -> Result<Vec<FinalizationFailure>> {
+        let mut requests = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            if let Some(request) = storage::get_finalize_withdrawal_params(
+                &self.pgpool,
+                *id as u64,
+                self.max_withdrawal_gas_limit.as_u64(),
+            )
+            .await?
+            {
+                requests.push((*id, request));
+            }
+        }
+
+        Ok(futures::future::join_all(
+            requests
+                .into_iter()
+                .map(|(id, request)| self.replay_withdrawal(id, request)),
+        )
+        .await)
+    }
+
+    // Replay finalization of a single withdrawal directly in the bridge contract
+    // with `eth_call` to capture the revert data the finalizer contract swallows.
+    async fn replay_withdrawal(
+        &self,
+        withdrawal_id: i64,
+        request: RequestFinalizeWithdrawal,
+    ) -> FinalizationFailure {
+        let result = if request.is_eth {
+            self.zksync_contract
+                .finalize_eth_withdrawal(
+                    request.l_2_block_number,
+                    request.l_2_message_index,
+                    request.l_2_tx_number_in_block,
+                    request.message,
+                    request.merkle_proof,
+                )
+                .from(self.account_address)
+                .gas(request.gas)
+                .call()
+                .await
+        } else {
+            self.l1_bridge
+                .finalize_withdrawal(
+                    request.l_2_block_number,
+                    request.l_2_message_index,
+                    request.l_2_tx_number_in_block,
+                    request.message,
+                    request.merkle_proof,
+                )
+                .from(self.account_address)
+                .gas(request.gas)
+                .call()
+                .await
+        };
+
+        match result {
+            Ok(()) => FinalizationFailure {
+                withdrawal_id,
+                class: FailureClass::Unknown,
+                reason: "finalization succeeds when replayed alone".to_string(),
+                revert_data: None,
+            },
+            Err(e) => failures::from_contract_error(withdrawal_id, &e),
+        }
     }
 
     // process withdrawals that have been predicted as unsuccessful.
@@ -438,24 +1444,34 @@ where
             return Ok(());
         }
 
-        let predicted = std::mem::take(&mut self.unsuccessful);
+        let (predicted, results): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.unsuccessful).into_iter().unzip();
         tracing::debug!("requesting finalization status of withdrawals");
         let are_finalized =
             get_finalized_withdrawals(&predicted, &self.zksync_contract, &self.l1_bridge).await?;
 
         let mut already_finalized = vec![];
-        let mut unsuccessful = vec![];
+        let mut failed = vec![];
 
-        for p in predicted {
+        for (p, result) in predicted.into_iter().zip(results) {
             let key = p.key();
 
             if are_finalized.contains(&key) {
                 already_finalized.push(key);
             } else {
-                unsuccessful.push(key);
+                failed.push((p, result));
             }
         }
 
+        let unsuccessful: Vec<_> = futures::future::join_all(
+            failed
+                .into_iter()
+                .map(|(p, result)| self.classify_predicted_failure(p, result)),
+        )
+        .await;
+
+        count_failures(&unsuccessful);
+
         tracing::debug!(
             "setting unsuccessful finalization attempts to {} withdrawals",
             unsuccessful.len()
@@ -463,7 +1479,12 @@ where
 
         // Either finalization tx has failed for these, or they were
         // predicted to fail.
-        storage::inc_unsuccessful_finalization_attempts(&self.pgpool, &unsuccessful).await?;
+        storage::inc_unsuccessful_finalization_attempts(
+            &self.pgpool,
+            &unsuccessful,
+            &self.retry_policy,
+        )
+        .await?;
 
         tracing::debug!(
             "setting already finalized status to {} withdrawals",
@@ -477,10 +1498,8 @@ where
         //
         // this may happen in two cases:
         // 1. someone else has finalized it
-        // 2. finalizer has finalized it however its course of
-        // execution has been interrupted somewhere between the
-        // submission of transaction and updating the db with the
-        // result of said transaction success.
+        // 2. finalizer has finalized it however the transaction
+        // has not been recorded into the `sent_transactions` journal.
         storage::finalization_data_set_finalized_in_tx(
             &self.pgpool,
             &already_finalized,
@@ -492,6 +1511,44 @@ where
     }
 }
 
+// Log failed finalization attempts and count them by classes of reasons.
+fn count_failures(failures: &[FinalizationFailure]) {
+    for f in failures {
+        tracing::info!(
+            "withdrawal {} failed to finalize ({}): {}",
+            f.withdrawal_id,
+            f.class.as_str(),
+            f.reason
+        );
+
+        FINALIZER_METRICS.failed_withdrawals[&f.class.as_str()].inc();
+    }
+}
+
+// Whether a withdrawal has run out of its gas limit in simulation.
+//
+// A failed withdrawal is considered to have run out of gas if
+// it has consumed all but 1/64 of its gas limit.
+fn is_out_of_gas(result: &FinalizeResult, gas_limit: U256) -> bool {
+    if result.success {
+        result.gas > gas_limit
+    } else {
+        result.gas >= gas_limit - gas_limit / 64
+    }
+}
+
+// Describe why a withdrawal has been predicted to fail.
+fn predicted_failure_reason(result: &FinalizeResult) -> String {
+    if result.success {
+        format!(
+            "predicted gas {} exceeds the gas limit of the withdrawal",
+            result.gas
+        )
+    } else {
+        "predicted to fail".to_string()
+    }
+}
+
 async fn get_finalized_withdrawals<M>(
     withdrawals: &[WithdrawalParams],
     zksync_contract: &IZkSync<M>,
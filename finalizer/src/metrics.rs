@@ -1,6 +1,6 @@
 //! Metrics for finalizer
 
-use vise::{Counter, Gauge, Metrics};
+use vise::{Counter, Gauge, LabeledFamily, Metrics};
 
 /// Finalizer metrics
 #[derive(Debug, Metrics)]
@@ -20,6 +20,47 @@ pub(super) struct FinalizerMetrics {
 
     /// Number of withdrawal transactions that were reverted.
     pub reverted_withdrawal_transactions: Counter,
+
+    /// Number of journaled transactions reconciled after being found pending.
+    pub reconciled_sent_transactions: Counter,
+
+    /// Number of unused nonces filled with a transfer to self.
+    pub filled_nonce_gaps: Counter,
+
+    /// Fees in ether that may be spent before the hourly or daily fee limit is exceeded.
+    pub remaining_fee_budget: Gauge<f64>,
+
+    /// Number of times finalization has been paused because the fee budget is exhausted.
+    pub paused_by_fee_budget: Counter,
+
+    /// Number of times finalization has been paused because the max fee per gas is below the base fee.
+    pub paused_by_max_fee_per_gas: Counter,
+
+    /// Number of tasks sending finalization transactions that have panicked or been cancelled.
+    pub aborted_sending_tasks: Counter,
+
+    /// Number of resumed journaled transactions that have not been mined in time.
+    pub timed_out_resumed_transactions: Counter,
+
+    /// Number of executed withdrawals skipped as unprofitable to finalize.
+    pub skipped_as_unprofitable: Gauge,
+
+    /// Number of failed finalization attempts of withdrawals by classes of reasons.
+    #[metrics(labels = ["class"])]
+    pub failed_withdrawals: LabeledFamily<&'static str, Counter>,
+
+    /// Number of times gas limits of withdrawals have been raised after running out of gas.
+    pub raised_gas_limits: Counter,
+
+    /// Number of withdrawals not retried anymore after too many failed attempts.
+    pub given_up_withdrawals: Gauge,
+
+    /// Number of withdrawals that would have been finalized in dry run mode.
+    pub dry_run_withdrawals: Counter,
+
+    /// Number of restarts of components that have ended.
+    #[metrics(labels = ["component"])]
+    pub component_restarts: LabeledFamily<&'static str, Counter>,
 }
 
 #[vise::register]
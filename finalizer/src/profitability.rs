@@ -0,0 +1,180 @@
+//! Filtering out withdrawals that are not worth the gas spent to finalize them.
+
+use std::{collections::HashMap, fmt::Debug, path::PathBuf, str::FromStr};
+
+use async_trait::async_trait;
+use ethers::types::{Address, U256};
+
+use crate::error::{Error, Result};
+
+/// A source of USD prices of tokens.
+#[async_trait]
+pub trait PriceSource: Debug + Send + Sync {
+    /// USD prices of whole tokens by their L1 or L2 addresses.
+    async fn usd_prices(&self) -> Result<HashMap<Address, f64>>;
+}
+
+/// A [`PriceSource`] reading prices from a JSON file mapping token addresses to USD prices.
+///
+/// The file is read on every request so that prices may be
+/// updated while the finalizer is running.
+#[derive(Debug)]
+pub struct JsonFilePriceSource {
+    path: PathBuf,
+}
+
+impl JsonFilePriceSource {
+    /// Create a new [`JsonFilePriceSource`] reading prices from `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl PriceSource for JsonFilePriceSource {
+    async fn usd_prices(&self) -> Result<HashMap<Address, f64>> {
+        let prices = tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(|e| Error::PriceSource(format!("{}: {e}", self.path.display())))?;
+
+        serde_json::from_str(&prices)
+            .map_err(|e| Error::PriceSource(format!("{}: {e}", self.path.display())))
+    }
+}
+
+/// A newtype that represents minimal amounts of tokens worth
+/// finalizing in token units by token addresses in JSON format.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TokenThresholds(pub HashMap<Address, f64>);
+
+impl FromStr for TokenThresholds {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let res = serde_json::from_str(s)?;
+        Ok(TokenThresholds(res))
+    }
+}
+
+/// Minimal amounts of withdrawals worth finalizing.
+#[derive(Debug, Default)]
+pub struct ProfitabilityFilter {
+    token_thresholds: TokenThresholds,
+    min_usd_value: Option<(f64, Box<dyn PriceSource>)>,
+}
+
+impl ProfitabilityFilter {
+    /// Create a new [`ProfitabilityFilter`] with static per-token thresholds.
+    pub fn new(token_thresholds: TokenThresholds) -> Self {
+        Self {
+            token_thresholds,
+            min_usd_value: None,
+        }
+    }
+
+    /// Additionally skip withdrawals worth less than `min_usd_value`
+    /// according to prices from `price_source`.
+    pub fn with_min_usd_value(
+        mut self,
+        min_usd_value: f64,
+        price_source: Box<dyn PriceSource>,
+    ) -> Self {
+        self.min_usd_value = Some((min_usd_value, price_source));
+        self
+    }
+
+    /// Current thresholds of ETH in wei and of other tokens in token units.
+    pub(crate) async fn thresholds(
+        &self,
+        eth_threshold: Option<U256>,
+    ) -> Result<(Option<U256>, Vec<(Address, f64)>)> {
+        let mut thresholds = self.token_thresholds.0.clone();
+
+        if let Some((min_usd_value, ref price_source)) = self.min_usd_value {
+            for (token, price) in price_source.usd_prices().await? {
+                if price <= 0.0 {
+                    continue;
+                }
+
+                let threshold = thresholds.entry(token).or_default();
+                *threshold = threshold.max(min_usd_value / price);
+            }
+        }
+
+        let mut eth_threshold = eth_threshold;
+        let mut token_thresholds = vec![];
+
+        for (token, threshold) in thresholds {
+            if !client::is_eth(token) {
+                token_thresholds.push((token, threshold));
+                continue;
+            }
+
+            let threshold = ethers::utils::parse_ether(threshold)
+                .map_err(|e| Error::PriceSource(format!("{e}")))?;
+
+            eth_threshold = std::cmp::max(eth_threshold, Some(threshold));
+        }
+
+        Ok((eth_threshold, token_thresholds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+    use ethers::types::{Address, U256};
+    use pretty_assertions::assert_eq;
+
+    use super::{PriceSource, ProfitabilityFilter, TokenThresholds};
+    use crate::error::Result;
+
+    #[derive(Debug)]
+    struct FixedPrices(HashMap<Address, f64>);
+
+    #[async_trait]
+    impl PriceSource for FixedPrices {
+        async fn usd_prices(&self) -> Result<HashMap<Address, f64>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn usd_value_raises_token_thresholds() {
+        let usdc = Address::repeat_byte(1);
+        let dai = Address::repeat_byte(2);
+
+        let filter =
+            ProfitabilityFilter::new(TokenThresholds(HashMap::from([(usdc, 10.0), (dai, 1.0)])))
+                .with_min_usd_value(
+                    5.0,
+                    Box::new(FixedPrices(HashMap::from([
+                        (usdc, 1.0),
+                        (dai, 1.0),
+                        (Address::zero(), 2000.0),
+                    ]))),
+                );
+
+        let (eth_threshold, mut thresholds) = filter.thresholds(Some(U256::one())).await.unwrap();
+        thresholds.sort_by_key(|(token, _)| *token);
+
+        assert_eq!(eth_threshold, Some(U256::exp10(14) * 25));
+        assert_eq!(thresholds, vec![(usdc, 10.0), (dai, 5.0)]);
+    }
+
+    #[test]
+    fn token_thresholds_de() {
+        let thresholds: TokenThresholds =
+            r#"{ "0x3355df6D4c9C3035724Fd0e3914dE96A5a83aaf4": 0.5 }"#
+                .parse()
+                .unwrap();
+
+        let usdc: Address = "0x3355df6D4c9C3035724Fd0e3914dE96A5a83aaf4"
+            .parse()
+            .unwrap();
+
+        assert_eq!(thresholds, TokenThresholds(HashMap::from([(usdc, 0.5)])));
+    }
+}
@@ -0,0 +1,151 @@
+//! Restarts of failed components.
+
+use std::{collections::VecDeque, fmt::Debug, future::Future, panic::AssertUnwindSafe};
+
+use futures::FutureExt;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::{Error, Result},
+    metrics::FINALIZER_METRICS,
+};
+
+/// A policy of restarting components that have ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RestartPolicy {
+    /// Delay before the first restart, doubled by every following restart within the window.
+    pub initial_backoff: Duration,
+
+    /// The upper bound of the delay before a restart.
+    pub max_backoff: Duration,
+
+    /// How many times a component may be restarted within the window.
+    pub max_restarts: usize,
+
+    /// The window the restarts of a component are counted in.
+    pub window: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_restarts: 5,
+            window: Duration::from_secs(600),
+        }
+    }
+}
+
+/// Run a `component` restarting it with backoff once it ends or panics.
+///
+/// Returns once the component has ended after `shutdown` has been cancelled
+/// or with an error once it has ended more than `max_restarts` times within the window.
+pub async fn supervise<F, Fut>(
+    component: &'static str,
+    policy: RestartPolicy,
+    shutdown: CancellationToken,
+    mut start: F,
+) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future,
+    Fut::Output: Debug,
+{
+    let mut restarts = VecDeque::new();
+
+    loop {
+        let ended = AssertUnwindSafe(start()).catch_unwind().await;
+
+        if shutdown.is_cancelled() {
+            return Ok(());
+        }
+
+        match ended {
+            Ok(r) => tracing::error!("{component} ended with {r:?}"),
+            Err(_) => tracing::error!("{component} panicked"),
+        }
+
+        let now = Instant::now();
+        while restarts
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > policy.window)
+        {
+            restarts.pop_front();
+        }
+
+        if restarts.len() >= policy.max_restarts {
+            return Err(Error::TooManyRestarts(component));
+        }
+
+        restarts.push_back(now);
+        FINALIZER_METRICS.component_restarts[&component].inc();
+
+        let backoff = policy
+            .initial_backoff
+            .saturating_mul(1u32 << (restarts.len() - 1).min(16))
+            .min(policy.max_backoff);
+
+        tracing::info!(
+            "restarting {component} in {backoff:?}, restarted {} times recently",
+            restarts.len()
+        );
+
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => (),
+            _ = shutdown.cancelled() => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use pretty_assertions::assert_eq;
+    use tokio::time::Duration;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{supervise, RestartPolicy};
+    use crate::error::Error;
+
+    fn policy(max_restarts: usize) -> RestartPolicy {
+        RestartPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            max_restarts,
+            window: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn components_are_restarted_until_they_fail_too_often() {
+        let runs = AtomicUsize::new(0);
+
+        let supervised = supervise("test", policy(3), CancellationToken::new(), || async {
+            if runs.fetch_add(1, Ordering::Relaxed) == 0 {
+                panic!("component panicked");
+            }
+        })
+        .await;
+
+        assert!(matches!(supervised, Err(Error::TooManyRestarts("test"))));
+        assert_eq!(runs.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test]
+    async fn components_are_not_restarted_on_shutdown() {
+        let runs = AtomicUsize::new(0);
+        let shutdown = CancellationToken::new();
+
+        let supervised = supervise("test", policy(3), shutdown.clone(), || async {
+            runs.fetch_add(1, Ordering::Relaxed);
+            shutdown.cancel();
+        })
+        .await;
+
+        assert!(supervised.is_ok());
+        assert_eq!(runs.load(Ordering::Relaxed), 1);
+    }
+}
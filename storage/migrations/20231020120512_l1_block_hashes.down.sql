@@ -0,0 +1 @@
+DROP TABLE l1_block_hashes;
@@ -0,0 +1,5 @@
+CREATE TABLE l1_block_hashes
+(
+    l1_block_number BIGINT PRIMARY KEY,
+    l1_block_hash BYTEA NOT NULL
+);
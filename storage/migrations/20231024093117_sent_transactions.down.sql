@@ -0,0 +1,2 @@
+DROP TABLE sent_transaction_hashes;
+DROP TABLE sent_transactions;
@@ -0,0 +1,31 @@
+CREATE TABLE sent_transactions
+(
+    id BIGSERIAL PRIMARY KEY,
+    account BYTEA NOT NULL,
+    nonce BIGINT NOT NULL,
+    to_address BYTEA NOT NULL,
+    calldata BYTEA NOT NULL,
+    gas_limit NUMERIC NOT NULL,
+    withdrawal_ids BIGINT [] NOT NULL,
+    status VARCHAR NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'mined', 'reverted', 'dropped')),
+    mined_tx_hash BYTEA DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX sent_transactions_account_nonce ON sent_transactions (account, nonce)
+    WHERE status <> 'dropped';
+
+CREATE TABLE sent_transaction_hashes
+(
+    tx_hash BYTEA PRIMARY KEY,
+    sent_transaction_id BIGINT NOT NULL,
+    max_fee_per_gas NUMERIC NOT NULL,
+    max_priority_fee_per_gas NUMERIC DEFAULT NULL,
+    sent_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    FOREIGN KEY (sent_transaction_id) REFERENCES sent_transactions (id)
+);
+
+CREATE INDEX sent_transaction_hashes_sent_transaction_id ON sent_transaction_hashes (sent_transaction_id);
@@ -0,0 +1 @@
+ALTER TABLE sent_transactions DROP COLUMN fee_paid;
@@ -0,0 +1 @@
+ALTER TABLE sent_transactions ADD COLUMN fee_paid NUMERIC DEFAULT NULL;
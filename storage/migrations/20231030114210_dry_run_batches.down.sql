@@ -0,0 +1 @@
+DROP TABLE dry_run_batches;
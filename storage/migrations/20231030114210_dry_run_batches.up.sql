@@ -0,0 +1,10 @@
+CREATE TABLE dry_run_batches
+(
+    id BIGSERIAL PRIMARY KEY,
+    withdrawal_ids BIGINT [] NOT NULL,
+    predicted_success BOOLEAN [] NOT NULL,
+    predicted_gas NUMERIC [] NOT NULL,
+    calldata_size BIGINT NOT NULL,
+    estimated_gas NUMERIC DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,7 @@
+CREATE TABLE token_gas_usage
+(
+    token BYTEA PRIMARY KEY,
+    average_gas NUMERIC NOT NULL,
+    samples BIGINT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
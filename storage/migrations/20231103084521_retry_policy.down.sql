@@ -0,0 +1,6 @@
+DROP TABLE finalization_attempts;
+
+DROP INDEX finalization_data_given_up_at;
+
+ALTER TABLE finalization_data DROP COLUMN given_up_at;
+ALTER TABLE finalization_data DROP COLUMN next_attempt_at;
@@ -0,0 +1,24 @@
+ALTER TABLE finalization_data ADD next_attempt_at TIMESTAMP DEFAULT NULL;
+ALTER TABLE finalization_data ADD given_up_at TIMESTAMP DEFAULT NULL;
+
+UPDATE finalization_data
+SET next_attempt_at = last_finalization_attempt + INTERVAL '1 minutes'
+WHERE last_finalization_attempt IS NOT NULL;
+
+UPDATE finalization_data
+SET given_up_at = COALESCE(last_finalization_attempt, NOW())
+WHERE failed_finalization_attempts >= 3;
+
+CREATE TABLE finalization_attempts
+(
+    id BIGSERIAL PRIMARY KEY,
+    withdrawal_id BIGINT NOT NULL,
+    attempt BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    FOREIGN KEY (withdrawal_id) REFERENCES withdrawals (id)
+);
+
+CREATE INDEX finalization_attempts_withdrawal_id ON finalization_attempts (withdrawal_id);
+CREATE INDEX finalization_data_given_up_at ON finalization_data (given_up_at) WHERE given_up_at IS NOT NULL;
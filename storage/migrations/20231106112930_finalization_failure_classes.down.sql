@@ -0,0 +1,2 @@
+ALTER TABLE finalization_attempts DROP COLUMN revert_data;
+ALTER TABLE finalization_attempts DROP COLUMN class;
@@ -0,0 +1,3 @@
+ALTER TABLE finalization_attempts ADD class VARCHAR NOT NULL DEFAULT 'unknown'
+    CHECK (class IN ('already_finalized', 'invalid_proof', 'token_paused', 'out_of_gas', 'unknown'));
+ALTER TABLE finalization_attempts ADD revert_data BYTEA DEFAULT NULL;
@@ -0,0 +1,3 @@
+DROP TABLE token_gas_limits;
+
+ALTER TABLE finalization_data DROP COLUMN gas_limit;
@@ -0,0 +1,8 @@
+ALTER TABLE finalization_data ADD gas_limit NUMERIC DEFAULT NULL;
+
+CREATE TABLE token_gas_limits
+(
+    token BYTEA PRIMARY KEY,
+    gas_limit NUMERIC NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
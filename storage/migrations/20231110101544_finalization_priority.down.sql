@@ -0,0 +1,3 @@
+DROP INDEX finalization_data_priority_idx;
+
+ALTER TABLE finalization_data DROP COLUMN priority;
@@ -0,0 +1,3 @@
+ALTER TABLE finalization_data ADD priority BIGINT NOT NULL DEFAULT 0;
+
+CREATE INDEX finalization_data_priority_idx ON finalization_data (priority) WHERE priority <> 0;
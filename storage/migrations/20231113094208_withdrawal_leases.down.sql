@@ -0,0 +1,2 @@
+ALTER TABLE finalization_data DROP COLUMN lease_expires_at;
+ALTER TABLE finalization_data DROP COLUMN leased_by;
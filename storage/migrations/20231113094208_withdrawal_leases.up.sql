@@ -0,0 +1,2 @@
+ALTER TABLE finalization_data ADD leased_by TEXT DEFAULT NULL;
+ALTER TABLE finalization_data ADD lease_expires_at TIMESTAMP DEFAULT NULL;
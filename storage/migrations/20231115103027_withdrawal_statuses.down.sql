@@ -0,0 +1 @@
+DROP VIEW withdrawal_statuses;
@@ -0,0 +1,23 @@
+CREATE VIEW withdrawal_statuses AS
+SELECT
+    w.id AS withdrawal_id,
+    CASE
+        WHEN fd.finalization_tx = decode(repeat('00', 32), 'hex') THEN 'finalized_elsewhere'
+        WHEN fd.finalization_tx IS NOT NULL THEN 'finalized'
+        WHEN NOT w.finalizable THEN 'unfinalizable'
+        WHEN fd.given_up_at IS NOT NULL THEN 'given_up'
+        WHEN EXISTS (
+            SELECT 1
+            FROM sent_transactions st
+            WHERE st.status = 'pending' AND w.id = ANY (st.withdrawal_ids)
+        ) THEN 'finalizing'
+        WHEN b.execute_l1_block_number IS NOT NULL AND fd.withdrawal_id IS NOT NULL THEN 'params_fetched'
+        WHEN b.execute_l1_block_number IS NOT NULL THEN 'executed'
+        WHEN b.verify_l1_block_number IS NOT NULL THEN 'verified'
+        WHEN b.commit_l1_block_number IS NOT NULL THEN 'committed'
+        ELSE 'pending_on_l2'
+    END AS status
+FROM
+    withdrawals w
+    LEFT JOIN finalization_data fd ON fd.withdrawal_id = w.id
+    LEFT JOIN l2_blocks b ON b.l2_block_number = w.l2_block_number;
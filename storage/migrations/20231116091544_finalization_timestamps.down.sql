@@ -0,0 +1,3 @@
+DROP INDEX finalization_data_finalized_at;
+ALTER TABLE finalization_data DROP COLUMN finalized_at;
+ALTER TABLE withdrawals DROP COLUMN created_at;
@@ -0,0 +1,7 @@
+-- Withdrawals seen before this migration have no known timestamps.
+ALTER TABLE withdrawals ADD created_at TIMESTAMP DEFAULT NULL;
+ALTER TABLE withdrawals ALTER COLUMN created_at SET DEFAULT NOW();
+
+ALTER TABLE finalization_data ADD finalized_at TIMESTAMP DEFAULT NULL;
+
+CREATE INDEX finalization_data_finalized_at ON finalization_data (finalized_at) WHERE finalized_at IS NOT NULL;
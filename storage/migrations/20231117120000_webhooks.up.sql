@@ -0,0 +1,33 @@
+CREATE TABLE webhooks
+(
+    id BIGSERIAL PRIMARY KEY,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    -- Only withdrawals to or from this L1 address if set.
+    address BYTEA DEFAULT NULL,
+    -- Only withdrawals of this L1 or L2 token if set.
+    token BYTEA DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE webhook_outbox
+(
+    id BIGSERIAL PRIMARY KEY,
+    webhook_id BIGINT NOT NULL,
+    withdrawal_id BIGINT NOT NULL,
+    event VARCHAR NOT NULL CHECK (event IN ('finalized', 'unfinalizable')),
+    attempts BIGINT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_error TEXT DEFAULT NULL,
+    delivered_at TIMESTAMP DEFAULT NULL,
+    dead_at TIMESTAMP DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    FOREIGN KEY (webhook_id) REFERENCES webhooks (id) ON DELETE CASCADE,
+    FOREIGN KEY (withdrawal_id) REFERENCES withdrawals (id) ON DELETE CASCADE,
+    UNIQUE (webhook_id, withdrawal_id, event)
+);
+
+CREATE INDEX webhook_outbox_due ON webhook_outbox (next_attempt_at)
+    WHERE delivered_at IS NULL AND dead_at IS NULL;
+CREATE INDEX webhook_outbox_dead ON webhook_outbox (dead_at) WHERE dead_at IS NOT NULL;
@@ -0,0 +1,5 @@
+DELETE FROM webhook_outbox WHERE event NOT IN ('finalized', 'unfinalizable');
+
+ALTER TABLE webhook_outbox DROP CONSTRAINT webhook_outbox_event_check;
+ALTER TABLE webhook_outbox ADD CONSTRAINT webhook_outbox_event_check
+    CHECK (event IN ('finalized', 'unfinalizable'));
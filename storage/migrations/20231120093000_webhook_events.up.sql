@@ -0,0 +1,3 @@
+ALTER TABLE webhook_outbox DROP CONSTRAINT webhook_outbox_event_check;
+ALTER TABLE webhook_outbox ADD CONSTRAINT webhook_outbox_event_check
+    CHECK (event IN ('finalized', 'finalized_elsewhere', 'unfinalizable', 'given_up'));
@@ -0,0 +1,150 @@
+//! Notifications about changes of statuses of withdrawals.
+
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+
+use crate::Result;
+
+/// Channel notified once statuses of withdrawals may have changed.
+///
+/// The payload is a [`WithdrawalsChange`] encoded as JSON.
+pub const WITHDRAWALS_CHANNEL: &str = "withdrawal_status";
+
+// Payloads of notifications are limited to 8000 bytes by the server,
+// changes of more withdrawals are split into several notifications.
+const MAX_PAYLOAD_LEN: usize = 7999;
+
+/// A change of statuses of withdrawals notified on [`WITHDRAWALS_CHANNEL`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WithdrawalsChange {
+    /// The change: `seen`, `committed`, `verified`, `executed`, `reverted`,
+    /// `params_fetched`, `finalized`, `finalizable` or `unfinalizable`.
+    pub change: String,
+    /// Ids of the changed withdrawals, `None` if any withdrawal may have changed.
+    pub ids: Option<Vec<u64>>,
+    /// Hashes of the transactions of the withdrawals that have been seen.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tx_hashes: Vec<H256>,
+    /// Addresses the withdrawals have been found to be to or from.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<Address>,
+}
+
+impl WithdrawalsChange {
+    /// A change any withdrawal may have been affected by.
+    pub fn any(change: &str) -> Self {
+        Self {
+            change: change.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn of(change: &str, ids: &[i64]) -> Self {
+        Self {
+            change: change.to_string(),
+            ids: Some(ids.iter().map(|&id| id as u64).collect()),
+            ..Default::default()
+        }
+    }
+
+    /// Decode the payload of a notification.
+    ///
+    /// Payloads that fail to decode are taken as changes of any withdrawal.
+    pub fn from_payload(payload: &str) -> Self {
+        serde_json::from_str(payload).unwrap_or_else(|_| Self::any(payload))
+    }
+
+    // Payloads of notifications listing all the changed withdrawals.
+    fn to_payloads(&self) -> Vec<String> {
+        // Serializing the change never fails.
+        let payload = serde_json::to_string(self).unwrap_or_default();
+
+        if payload.len() <= MAX_PAYLOAD_LEN {
+            return vec![payload];
+        }
+
+        match self.split() {
+            Some((first, second)) => {
+                let mut payloads = first.to_payloads();
+                payloads.extend(second.to_payloads());
+                payloads
+            }
+            None => vec![serde_json::to_string(&Self::any(&self.change)).unwrap_or_default()],
+        }
+    }
+
+    // Split the change into two changes listing half of the withdrawals each.
+    fn split(&self) -> Option<(Self, Self)> {
+        let ids = self.ids.as_ref()?;
+
+        if ids.len() + self.tx_hashes.len() + self.addresses.len() <= 1 {
+            return None;
+        }
+
+        let (first_ids, second_ids) = ids.split_at(ids.len() / 2);
+        let (first_tx_hashes, second_tx_hashes) = self.tx_hashes.split_at(self.tx_hashes.len() / 2);
+        let (first_addresses, second_addresses) = self.addresses.split_at(self.addresses.len() / 2);
+
+        let half = |ids: &[u64], tx_hashes: &[H256], addresses: &[Address]| Self {
+            change: self.change.clone(),
+            ids: Some(ids.to_vec()),
+            tx_hashes: tx_hashes.to_vec(),
+            addresses: addresses.to_vec(),
+        };
+
+        Some((
+            half(first_ids, first_tx_hashes, first_addresses),
+            half(second_ids, second_tx_hashes, second_addresses),
+        ))
+    }
+}
+
+// Notifications sent within a transaction are only delivered once it commits.
+pub(crate) async fn notify_withdrawals_changed<'c, E>(
+    executor: E,
+    change: &WithdrawalsChange,
+) -> Result<()>
+where
+    E: sqlx::PgExecutor<'c>,
+{
+    sqlx::query!(
+        "
+        SELECT
+          pg_notify($1, payload)
+        FROM
+          UNNEST($2 :: TEXT []) AS payload
+        ",
+        WITHDRAWALS_CHANNEL,
+        &change.to_payloads(),
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// Ids of the withdrawals made in a range of L2 blocks.
+pub(crate) async fn withdrawal_ids_in_blocks(
+    conn: &mut PgConnection,
+    range: &[i64],
+) -> Result<Vec<i64>> {
+    let ids = sqlx::query!(
+        "
+        SELECT
+          id
+        FROM
+          withdrawals
+        WHERE
+          l2_block_number = ANY ($1)
+        ",
+        range,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+
+    Ok(ids)
+}
@@ -3,6 +3,15 @@
 pub enum Error {
     #[error(transparent)]
     PgError(#[from] sqlx::Error),
+
+    #[error("unknown ordering policy {0}")]
+    UnknownOrderingPolicy(String),
+
+    #[error("unknown finalization status {0}")]
+    UnknownFinalizationStatus(String),
+
+    #[error("unknown webhook event {0}")]
+    UnknownWebhookEvent(String),
 }
 
 /// Crate result type.
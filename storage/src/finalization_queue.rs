@@ -0,0 +1,441 @@
+//! Selection of withdrawals to finalize.
+
+use std::{str::FromStr, time::Duration};
+
+use ethers::types::{Address, H256, U256};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use client::WithdrawalParams;
+
+use crate::{
+    metrics::STORAGE_METRICS, token_thresholds_params, u256_to_big_decimal, Error, Result,
+};
+
+/// Order in which withdrawals are picked for finalization.
+///
+/// Withdrawals with higher priority always go first, the policy
+/// orders withdrawals of the same priority.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OrderingPolicy {
+    /// Withdrawals from earlier L2 blocks first.
+    #[default]
+    OldestFirst,
+    /// Withdrawals of larger amounts in whole token units first.
+    ///
+    /// Note that small withdrawals may wait for long while larger ones keep coming.
+    LargestAmountFirst,
+    /// The oldest withdrawal of every L1 recipient first, then the
+    /// second oldest withdrawal of every recipient and so on.
+    RoundRobin,
+}
+
+impl FromStr for OrderingPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "oldest_first" => Ok(Self::OldestFirst),
+            "largest_amount_first" => Ok(Self::LargestAmountFirst),
+            "round_robin" => Ok(Self::RoundRobin),
+            s => Err(Error::UnknownOrderingPolicy(s.to_string())),
+        }
+    }
+}
+
+/// A lease of withdrawals by a finalizer instance.
+///
+/// Withdrawals leased by one instance are not picked by other
+/// instances sharing the database until the lease expires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    /// Unique id of the instance holding the lease.
+    pub holder: String,
+    /// For how long the withdrawals are leased.
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+enum TokenFilter {
+    #[default]
+    All,
+    WhiteList(Vec<Vec<u8>>),
+    BlackList(Vec<Vec<u8>>),
+}
+
+/// A query of withdrawals ready to be finalized.
+///
+/// Selects withdrawals from executed batches that are neither finalized, nor
+/// being finalized by a pending transaction, nor given up on or waiting for
+/// their next attempt.
+#[derive(Debug, Clone)]
+pub struct WithdrawalsToFinalize {
+    limit: u64,
+    tokens: TokenFilter,
+    eth_threshold: Option<U256>,
+    token_thresholds: Vec<(Address, f64)>,
+    max_execute_l1_block: Option<u64>,
+    skip_dry_run: bool,
+    ordering: OrderingPolicy,
+    lease: Option<Lease>,
+}
+
+#[derive(sqlx::FromRow)]
+struct WithdrawalParamsRow {
+    tx_hash: Vec<u8>,
+    event_index_in_tx: i32,
+    withdrawal_id: i64,
+    l2_block_number: i64,
+    l1_batch_number: i64,
+    l2_message_index: i32,
+    l2_tx_number_in_block: i16,
+    message: Vec<u8>,
+    sender: Vec<u8>,
+    proof: Vec<u8>,
+}
+
+impl From<WithdrawalParamsRow> for WithdrawalParams {
+    fn from(record: WithdrawalParamsRow) -> Self {
+        WithdrawalParams {
+            tx_hash: H256::from_slice(&record.tx_hash),
+            event_index_in_tx: record.event_index_in_tx as u32,
+            id: record.withdrawal_id as u64,
+            l2_block_number: record.l2_block_number as u64,
+            l1_batch_number: record.l1_batch_number.into(),
+            l2_message_index: record.l2_message_index as u32,
+            l2_tx_number_in_block: record.l2_tx_number_in_block as u16,
+            message: record.message.into(),
+            sender: Address::from_slice(&record.sender),
+            proof: bincode::deserialize(&record.proof)
+                .expect("storage contains data correctly serialized by bincode; qed"),
+        }
+    }
+}
+
+impl WithdrawalsToFinalize {
+    /// Select at most `limit` withdrawals of all tokens.
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            tokens: TokenFilter::All,
+            eth_threshold: None,
+            token_thresholds: vec![],
+            max_execute_l1_block: None,
+            skip_dry_run: false,
+            ordering: OrderingPolicy::default(),
+            lease: None,
+        }
+    }
+
+    /// Only select withdrawals of these tokens.
+    pub fn with_whitelist(mut self, tokens: &[Address]) -> Self {
+        self.tokens = TokenFilter::WhiteList(tokens.iter().map(|a| a.0.to_vec()).collect());
+        self
+    }
+
+    /// Select withdrawals of all tokens but these.
+    pub fn with_blacklist(mut self, tokens: &[Address]) -> Self {
+        self.tokens = TokenFilter::BlackList(tokens.iter().map(|a| a.0.to_vec()).collect());
+        self
+    }
+
+    /// Skip ETH withdrawals below the threshold.
+    pub fn with_eth_threshold(mut self, eth_threshold: Option<U256>) -> Self {
+        self.eth_threshold = eth_threshold;
+        self
+    }
+
+    /// Skip withdrawals below thresholds of their tokens.
+    ///
+    /// Thresholds are given in token units by either L1 or L2 address of the token.
+    pub fn with_token_thresholds(mut self, token_thresholds: &[(Address, f64)]) -> Self {
+        self.token_thresholds = token_thresholds.to_vec();
+        self
+    }
+
+    /// Only select withdrawals from batches executed in L1 blocks up to this one.
+    pub fn with_max_execute_l1_block(mut self, max_execute_l1_block: Option<u64>) -> Self {
+        self.max_execute_l1_block = max_execute_l1_block;
+        self
+    }
+
+    /// Skip withdrawals already recorded in dry run batches.
+    pub fn skip_dry_run(mut self, skip_dry_run: bool) -> Self {
+        self.skip_dry_run = skip_dry_run;
+        self
+    }
+
+    /// Order withdrawals of the same priority by this policy.
+    pub fn ordered_by(mut self, ordering: OrderingPolicy) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    /// Lease the fetched withdrawals, skip withdrawals leased by other holders.
+    pub fn with_lease(mut self, lease: Lease) -> Self {
+        self.lease = Some(lease);
+        self
+    }
+
+    /// Fetch the withdrawals.
+    ///
+    /// If a lease is set, only the withdrawals that have been successfully
+    /// leased are returned, in the same order.
+    pub async fn fetch(&self, pool: &PgPool) -> Result<Vec<WithdrawalParams>> {
+        let latency = STORAGE_METRICS.call[&"withdrawals_to_finalize"].start();
+
+        let mut data: Vec<_> = self
+            .build()
+            .build_query_as::<WithdrawalParamsRow>()
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(WithdrawalParams::from)
+            .collect();
+
+        if let Some(lease) = &self.lease {
+            let ids: Vec<_> = data.iter().map(|w| w.id as i64).collect();
+            let leased = lease_withdrawals(pool, &ids, lease).await?;
+
+            data.retain(|w| leased.contains(&(w.id as i64)));
+        }
+
+        latency.observe();
+
+        Ok(data)
+    }
+
+    fn build(&self) -> QueryBuilder<'_, Postgres> {
+        // if no threshold, query _all_ ethereum withdrawals since all of them are >= 0.
+        let eth_threshold = self.eth_threshold.unwrap_or(U256::zero());
+        // if no limit, consider withdrawals from _all_ executed blocks.
+        let max_execute_l1_block = self.max_execute_l1_block.map_or(i64::MAX, |b| b as i64);
+        let (threshold_tokens, thresholds) = token_thresholds_params(&self.token_thresholds);
+
+        let mut query = QueryBuilder::new(
+            "
+            SELECT
+              w.tx_hash,
+              w.event_index_in_tx,
+              withdrawal_id,
+              finalization_data.l2_block_number,
+              l1_batch_number,
+              l2_message_index,
+              l2_tx_number_in_block,
+              message,
+              sender,
+              proof
+            FROM
+              finalization_data
+              JOIN withdrawals w ON finalization_data.withdrawal_id = w.id
+            WHERE
+              finalization_tx IS NULL
+              AND w.finalizable = TRUE
+              AND given_up_at IS NULL
+              AND (
+                next_attempt_at IS NULL
+                OR next_attempt_at <= NOW()
+              )
+              AND finalization_data.l2_block_number <= COALESCE(
+                (
+                  SELECT
+                    MAX(l2_block_number)
+                  FROM
+                    l2_blocks
+                  WHERE
+                    execute_l1_block_number IS NOT NULL
+                    AND execute_l1_block_number <= ",
+        );
+        query.push_bind(max_execute_l1_block).push(
+            "
+                ),
+                1
+              )
+              AND NOT EXISTS (
+                SELECT
+                  1
+                FROM
+                  sent_transactions
+                WHERE
+                  status = 'pending'
+                  AND finalization_data.withdrawal_id = ANY (withdrawal_ids)
+              )",
+        );
+
+        if let Some(lease) = &self.lease {
+            query
+                .push(
+                    "
+              AND (
+                leased_by IS NULL
+                OR leased_by = ",
+                )
+                .push_bind(&lease.holder)
+                .push(
+                    "
+                OR lease_expires_at <= NOW()
+              )",
+                );
+        }
+
+        match &self.tokens {
+            TokenFilter::All => {}
+            TokenFilter::WhiteList(tokens) => {
+                query
+                    .push(
+                        "
+              AND w.token = ANY (",
+                    )
+                    .push_bind(tokens)
+                    .push(" :: BYTEA [])");
+            }
+            TokenFilter::BlackList(tokens) => {
+                query
+                    .push(
+                        "
+              AND w.token <> ALL (",
+                    )
+                    .push_bind(tokens)
+                    .push(" :: BYTEA [])");
+            }
+        }
+
+        query
+            .push(
+                "
+              AND (
+                CASE WHEN w.token = decode('000000000000000000000000000000000000800A', 'hex') THEN amount >= ",
+            )
+            .push_bind(u256_to_big_decimal(eth_threshold))
+            .push(
+                "
+                ELSE TRUE
+                END
+              )
+              AND NOT EXISTS (
+                SELECT
+                  1
+                FROM
+                  UNNEST (",
+            )
+            .push_bind(threshold_tokens)
+            .push(" :: BYTEA [], ")
+            .push_bind(thresholds)
+            .push(
+                " :: FLOAT8 []) AS thresholds (token, threshold)
+                  JOIN tokens ON thresholds.token IN (tokens.l1_token_address, tokens.l2_token_address)
+                WHERE
+                  tokens.l2_token_address = w.token
+                  AND w.amount < thresholds.threshold :: NUMERIC * POWER(10 :: NUMERIC, tokens.decimals)
+              )
+              AND NOT (",
+            )
+            .push_bind(self.skip_dry_run)
+            .push(
+                "
+                AND EXISTS (
+                  SELECT
+                    1
+                  FROM
+                    dry_run_batches
+                  WHERE
+                    finalization_data.withdrawal_id = ANY (dry_run_batches.withdrawal_ids)
+                )
+              )
+            ORDER BY
+              finalization_data.priority DESC,",
+            );
+
+        query.push(match self.ordering {
+            OrderingPolicy::OldestFirst => "",
+            // ETH is not in the `tokens` table.
+            OrderingPolicy::LargestAmountFirst => {
+                "
+              w.amount / POWER(
+                10 :: NUMERIC,
+                COALESCE(
+                  (
+                    SELECT
+                      decimals
+                    FROM
+                      tokens
+                    WHERE
+                      l2_token_address = w.token
+                    LIMIT
+                      1
+                  ),
+                  18
+                )
+              ) DESC,"
+            }
+            // L1 receiver follows the function selector in messages of both ETH and ERC20 withdrawals.
+            OrderingPolicy::RoundRobin => {
+                "
+              ROW_NUMBER() OVER (
+                PARTITION BY SUBSTRING(message FROM 5 FOR 20)
+                ORDER BY
+                  finalization_data.priority DESC,
+                  finalization_data.l2_block_number,
+                  finalization_data.withdrawal_id
+              ),"
+            }
+        });
+
+        query
+            .push(
+                "
+              finalization_data.l2_block_number,
+              finalization_data.withdrawal_id
+            LIMIT
+              ",
+            )
+            .push_bind(self.limit as i64);
+
+        query
+    }
+}
+
+// Lease withdrawals that are not leased by another holder.
+//
+// Rows locked by concurrent leases are skipped and the lease condition
+// is rechecked on update, so a withdrawal is never leased by two holders.
+//
+// Returns ids of the leased withdrawals.
+async fn lease_withdrawals(pool: &PgPool, ids: &[i64], lease: &Lease) -> Result<Vec<i64>> {
+    let leased = sqlx::query!(
+        "
+        UPDATE
+          finalization_data
+        SET
+          leased_by = $2,
+          lease_expires_at = NOW() + $3 :: FLOAT8 * INTERVAL '1 second'
+        WHERE
+          withdrawal_id IN (
+            SELECT
+              withdrawal_id
+            FROM
+              finalization_data
+            WHERE
+              withdrawal_id = ANY ($1 :: BIGINT [])
+            FOR UPDATE
+              SKIP LOCKED
+          )
+          AND (
+            leased_by IS NULL
+            OR leased_by = $2
+            OR lease_expires_at <= NOW()
+          )
+        RETURNING
+          withdrawal_id
+        ",
+        ids,
+        lease.holder,
+        lease.duration.as_secs_f64(),
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| r.withdrawal_id)
+    .collect();
+
+    Ok(leased)
+}
@@ -5,6 +5,8 @@
 
 //! Finalizer watcher.storage.operations.
 
+use std::{str::FromStr, time::Duration};
+
 use ethers::types::{Address, H160, H256, U256};
 use sqlx::{PgConnection, PgPool};
 
@@ -14,15 +16,31 @@ use client::{
     WithdrawalEvent, WithdrawalKey, WithdrawalParams,
 };
 
+mod changes;
 mod error;
+mod finalization_queue;
 mod metrics;
+mod stats;
 mod utils;
+mod webhooks;
 
 use utils::u256_to_big_decimal;
 
+pub use changes::{WithdrawalsChange, WITHDRAWALS_CHANNEL};
 pub use error::{Error, Result};
+pub use finalization_queue::{Lease, OrderingPolicy, WithdrawalsToFinalize};
+pub use stats::{eta_model, finalization_latencies, EtaModel, TokenLatency, L1_BLOCK_TIME};
+pub use webhooks::{
+    add_webhook, dead_webhook_deliveries, delete_webhook, due_webhook_deliveries,
+    retry_dead_webhook_delivery, webhook_delivered, webhook_delivery_failed, webhooks, Webhook,
+    WebhookDelivery, WebhookEvent, WebhookWithdrawal,
+};
 
-use crate::metrics::STORAGE_METRICS;
+use crate::{
+    changes::{notify_withdrawals_changed, withdrawal_ids_in_blocks},
+    metrics::STORAGE_METRICS,
+    webhooks::enqueue_webhook_deliveries,
+};
 
 /// A convenience struct that couples together [`WithdrawalEvent`]
 /// with index in tx and boolean `is_finalized` value
@@ -70,6 +88,9 @@ pub async fn committed_new_batch(
     .execute(&mut *tx)
     .await?;
 
+    let ids = withdrawal_ids_in_blocks(&mut tx, &range).await?;
+    notify_withdrawals_changed(&mut *tx, &WithdrawalsChange::of("committed", &ids)).await?;
+
     tx.commit().await?;
 
     latency.observe();
@@ -192,6 +213,9 @@ pub async fn verified_new_batch(
     .execute(&mut *tx)
     .await?;
 
+    let ids = withdrawal_ids_in_blocks(&mut tx, &range).await?;
+    notify_withdrawals_changed(&mut *tx, &WithdrawalsChange::of("verified", &ids)).await?;
+
     tx.commit().await?;
     latency.observe();
 
@@ -231,12 +255,283 @@ pub async fn executed_new_batch(
     .execute(&mut *tx)
     .await?;
 
+    let ids = withdrawal_ids_in_blocks(&mut tx, &range).await?;
+    notify_withdrawals_changed(&mut *tx, &WithdrawalsChange::of("executed", &ids)).await?;
+
+    tx.commit().await?;
+    latency.observe();
+
+    Ok(())
+}
+
+/// A set of batches has been reverted on L1, roll back statuses of withdrawal records.
+///
+/// All L2 blocks after the given ones lose their respective commit, verify and
+/// execute statuses. Finalization data of withdrawals in the blocks that are no
+/// longer committed is dropped so that it is re-fetched once the blocks get
+/// committed again, possibly within different batches.
+///
+/// # Arguments
+///
+/// * `last_committed_l2_block`: Last L2 block that remains committed.
+/// * `last_verified_l2_block`: Last L2 block that remains verified.
+/// * `last_executed_l2_block`: Last L2 block that remains executed.
+/// * `last_committed_batch`: Last L1 batch that remains committed.
+///
+/// Returns the number of L2 blocks that are no longer committed.
+pub async fn revert_batches(
+    pool: &PgPool,
+    last_committed_l2_block: u64,
+    last_verified_l2_block: u64,
+    last_executed_l2_block: u64,
+    last_committed_batch: u64,
+) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let latency = STORAGE_METRICS.call[&"revert_batches"].start();
+
+    let reverted = sqlx::query!(
+        "
+        UPDATE
+          l2_blocks
+        SET
+          commit_l1_block_number = NULL,
+          verify_l1_block_number = NULL,
+          execute_l1_block_number = NULL
+        WHERE
+          l2_block_number > $1
+          AND commit_l1_block_number IS NOT NULL
+        ",
+        last_committed_l2_block as i64,
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    sqlx::query!(
+        "
+        UPDATE
+          l2_blocks
+        SET
+          verify_l1_block_number = NULL,
+          execute_l1_block_number = NULL
+        WHERE
+          l2_block_number > $1
+          AND verify_l1_block_number IS NOT NULL
+        ",
+        last_verified_l2_block as i64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        UPDATE
+          l2_blocks
+        SET
+          execute_l1_block_number = NULL
+        WHERE
+          l2_block_number > $1
+          AND execute_l1_block_number IS NOT NULL
+        ",
+        last_executed_l2_block as i64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        DELETE FROM
+          finalization_data
+        WHERE
+          l2_block_number > $1
+          AND finalization_tx IS NULL
+        ",
+        last_committed_l2_block as i64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // `l2_block_number` column of `l2_to_l1_events` holds batch numbers.
+    sqlx::query!(
+        "
+        DELETE FROM
+          l2_to_l1_events
+        WHERE
+          l2_block_number > $1
+        ",
+        last_committed_batch as i64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    notify_withdrawals_changed(&mut *tx, &WithdrawalsChange::any("reverted")).await?;
+
+    tx.commit().await?;
+    latency.observe();
+
+    Ok(reverted)
+}
+
+/// Rewinds the state derived from L1 blocks that are no longer canonical.
+///
+/// All commit, verify and execute statuses, `l2_to_l1_events` and known L1 block
+/// hashes originating from L1 blocks starting at `fork_l1_block_number` are removed.
+///
+/// Returns the number of L2 blocks whose statuses have been rewound.
+pub async fn rewind_to_l1_block(pool: &PgPool, fork_l1_block_number: u64) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let latency = STORAGE_METRICS.call[&"rewind_to_l1_block"].start();
+
+    let rewound = sqlx::query!(
+        "
+        UPDATE
+          l2_blocks
+        SET
+          commit_l1_block_number = NULL,
+          verify_l1_block_number = NULL,
+          execute_l1_block_number = NULL
+        WHERE
+          commit_l1_block_number >= $1
+        ",
+        fork_l1_block_number as i64,
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    sqlx::query!(
+        "
+        UPDATE
+          l2_blocks
+        SET
+          verify_l1_block_number = NULL,
+          execute_l1_block_number = NULL
+        WHERE
+          verify_l1_block_number >= $1
+        ",
+        fork_l1_block_number as i64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        UPDATE
+          l2_blocks
+        SET
+          execute_l1_block_number = NULL
+        WHERE
+          execute_l1_block_number >= $1
+        ",
+        fork_l1_block_number as i64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        DELETE FROM
+          l2_to_l1_events
+        WHERE
+          l1_block_number >= $1
+        ",
+        fork_l1_block_number as i64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        DELETE FROM
+          l1_block_hashes
+        WHERE
+          l1_block_number >= $1
+        ",
+        fork_l1_block_number as i64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    notify_withdrawals_changed(&mut *tx, &WithdrawalsChange::any("reverted")).await?;
+
+    tx.commit().await?;
+    latency.observe();
+
+    Ok(rewound)
+}
+
+/// Records the hash of an L1 block in which events of interest have been seen.
+///
+/// Only hashes of the last `window` blocks are kept.
+pub async fn add_l1_block_hash(
+    pool: &PgPool,
+    block_number: u64,
+    block_hash: H256,
+    window: u64,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let latency = STORAGE_METRICS.call[&"add_l1_block_hash"].start();
+
+    sqlx::query!(
+        "
+        INSERT INTO
+          l1_block_hashes (l1_block_number, l1_block_hash)
+        VALUES
+          ($1, $2) ON CONFLICT (l1_block_number) DO
+        UPDATE
+        SET
+          l1_block_hash = EXCLUDED.l1_block_hash
+        ",
+        block_number as i64,
+        block_hash.as_bytes(),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        DELETE FROM
+          l1_block_hashes
+        WHERE
+          l1_block_number < $1
+        ",
+        block_number.saturating_sub(window) as i64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
     tx.commit().await?;
     latency.observe();
 
     Ok(())
 }
 
+/// Get the known hashes of L1 blocks ordered by block number.
+pub async fn l1_block_hashes(conn: &mut PgConnection) -> Result<Vec<(u64, H256)>> {
+    let latency = STORAGE_METRICS.call[&"l1_block_hashes"].start();
+
+    let res = sqlx::query!(
+        "
+        SELECT
+          l1_block_number,
+          l1_block_hash
+        FROM
+          l1_block_hashes
+        ORDER BY
+          l1_block_number
+        "
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|r| (r.l1_block_number as u64, H256::from_slice(&r.l1_block_hash)))
+    .collect();
+
+    latency.observe();
+
+    Ok(res)
+}
+
 /// Gets withdrawal events from the db by a set of IDs.
 ///
 /// # Arguments
@@ -248,7 +543,13 @@ pub async fn get_withdrawals(pool: &PgPool, ids: &[i64]) -> Result<Vec<StoredWit
 
     let events = sqlx::query!(
         "
-        SELECT * FROM
+        SELECT
+            tx_hash,
+            l2_block_number,
+            token,
+            amount,
+            event_index_in_tx
+        FROM
             withdrawals
         WHERE id in (SELECT * FROM unnest( $1 :: bigint[] ))
         ",
@@ -296,7 +597,7 @@ pub async fn add_withdrawals(pool: &PgPool, events: &[StoredWithdrawal]) -> Resu
 
     let latency = STORAGE_METRICS.call[&"add_withdrawals"].start();
 
-    sqlx::query!(
+    let seen = sqlx::query!(
         "
         INSERT INTO
           withdrawals (
@@ -329,6 +630,9 @@ pub async fn add_withdrawals(pool: &PgPool, events: &[StoredWithdrawal]) -> Resu
             tx_hash,
             event_index_in_tx
           ) DO NOTHING
+        RETURNING
+          id,
+          tx_hash
         ",
         &tx_hashes,
         &block_numbers,
@@ -336,9 +640,17 @@ pub async fn add_withdrawals(pool: &PgPool, events: &[StoredWithdrawal]) -> Resu
         amounts.as_slice(),
         &indices_in_tx,
     )
-    .execute(pool)
+    .fetch_all(pool)
     .await?;
 
+    let ids: Vec<_> = seen.iter().map(|r| r.id).collect();
+    let mut change = WithdrawalsChange::of("seen", &ids);
+    change.tx_hashes = seen.iter().map(|r| H256::from_slice(&r.tx_hash)).collect();
+    change.tx_hashes.sort();
+    change.tx_hashes.dedup();
+
+    notify_withdrawals_changed(pool, &change).await?;
+
     latency.observe();
 
     Ok(())
@@ -590,48 +902,63 @@ pub async fn add_withdrawals_data(pool: &PgPool, wd: &[WithdrawalParams]) -> Res
 
     let latency = STORAGE_METRICS.call[&"add_withdrawals_data"].start();
 
-    sqlx::query!(
+    let fetched = sqlx::query!(
         "
-        INSERT INTO
-          finalization_data (
+        WITH inserted AS (
+          INSERT INTO
+            finalization_data (
+              withdrawal_id,
+              l2_block_number,
+              l1_batch_number,
+              l2_message_index,
+              l2_tx_number_in_block,
+              message,
+              sender,
+              proof
+            )
+          SELECT
+            u.id,
+            u.l2_block_number,
+            u.l1_batch_number,
+            u.l2_message_index,
+            u.l2_tx_number_in_block,
+            u.message,
+            u.sender,
+            u.proof
+          FROM
+            UNNEST (
+              $1 :: bigint [],
+              $2 :: bigint [],
+              $3 :: bigint [],
+              $4 :: integer [],
+              $5 :: integer [],
+              $6 :: BYTEA [],
+              $7 :: BYTEA [],
+              $8 :: BYTEA []
+            ) AS u(
+              id,
+              l2_block_number,
+              l1_batch_number,
+              l2_message_index,
+              l2_tx_number_in_block,
+              message,
+              sender,
+              proof
+            ) ON CONFLICT (withdrawal_id) DO NOTHING
+          RETURNING
             withdrawal_id,
-            l2_block_number,
             l1_batch_number,
-            l2_message_index,
             l2_tx_number_in_block,
-            message,
-            sender,
-            proof
-          )
+            sender
+        )
         SELECT
-          u.id,
-          u.l2_block_number,
-          u.l1_batch_number,
-          u.l2_message_index,
-          u.l2_tx_number_in_block,
-          u.message,
-          u.sender,
-          u.proof
+          inserted.withdrawal_id,
+          inserted.sender,
+          l2_to_l1_events.to_address AS \"to_address?\"
         FROM
-          UNNEST (
-            $1 :: bigint [],
-            $2 :: bigint [],
-            $3 :: bigint [],
-            $4 :: integer [],
-            $5 :: integer [],
-            $6 :: BYTEA [],
-            $7 :: BYTEA [],
-            $8 :: BYTEA []
-          ) AS u(
-            id,
-            l2_block_number,
-            l1_batch_number,
-            l2_message_index,
-            l2_tx_number_in_block,
-            message,
-            sender,
-            proof
-          ) ON CONFLICT (withdrawal_id) DO NOTHING
+          inserted
+          LEFT JOIN l2_to_l1_events ON l2_to_l1_events.l2_block_number = inserted.l1_batch_number
+          AND l2_to_l1_events.tx_number_in_block = inserted.l2_tx_number_in_block
         ",
         &ids,
         &l2_block_number,
@@ -642,9 +969,25 @@ pub async fn add_withdrawals_data(pool: &PgPool, wd: &[WithdrawalParams]) -> Res
         &sender,
         &proof
     )
-    .execute(pool)
+    .fetch_all(pool)
     .await?;
 
+    // Withdrawals are listed once per their L2 to L1 event.
+    let mut ids: Vec<_> = fetched.iter().map(|r| r.withdrawal_id).collect();
+    ids.sort_unstable();
+    ids.dedup();
+
+    let mut change = WithdrawalsChange::of("params_fetched", &ids);
+    change.addresses = fetched
+        .iter()
+        .flat_map(|r| std::iter::once(&r.sender).chain(&r.to_address))
+        .map(|a| Address::from_slice(a))
+        .collect();
+    change.addresses.sort();
+    change.addresses.dedup();
+
+    notify_withdrawals_changed(pool, &change).await?;
+
     latency.observe();
 
     Ok(())
@@ -678,14 +1021,13 @@ pub async fn get_withdrawals_with_no_data(
             ),
             1
           )
-          AND id > COALESCE(
-            (
-              SELECT
-                MAX(withdrawal_id)
-              FROM
-                finalization_data
-            ),
-            1
+          AND NOT EXISTS (
+            SELECT
+              1
+            FROM
+              finalization_data
+            WHERE
+              withdrawal_id = withdrawals.id
           )
           AND finalizable = TRUE
         ORDER BY
@@ -719,9 +1061,10 @@ pub async fn set_withdrawal_unfinalizable(
     tx_hash: H256,
     event_index_in_tx: usize,
 ) -> Result<()> {
+    let mut tx = pool.begin().await?;
     let latency = STORAGE_METRICS.call[&"set_withdrawal_unfinalizable"].start();
 
-    sqlx::query!(
+    let ids: Vec<_> = sqlx::query!(
         "
             UPDATE withdrawals
             SET finalizable = false 
@@ -729,249 +1072,309 @@ pub async fn set_withdrawal_unfinalizable(
               tx_hash = $1
               AND
               event_index_in_tx = $2
+              AND
+              finalizable
+            RETURNING id
         ",
         tx_hash.as_bytes(),
         event_index_in_tx as i32,
     )
-    .execute(pool)
-    .await?;
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+
+    enqueue_webhook_deliveries(&mut tx, &ids, WebhookEvent::Unfinalizable).await?;
 
+    if !ids.is_empty() {
+        notify_withdrawals_changed(&mut *tx, &WithdrawalsChange::of("unfinalizable", &ids)).await?;
+    }
+
+    tx.commit().await?;
     latency.observe();
 
     Ok(())
 }
 
-/// Get the earliest withdrawals never attempted to be finalized before
-pub async fn withdrawals_to_finalize_with_blacklist(
+/// Look up a withdrawal by its transaction hash and event index.
+///
+/// Returns the id of the withdrawal and whether its finalization parameters are stored.
+pub async fn withdrawal_id_by_key(
     pool: &PgPool,
-    limit_by: u64,
-    token_blacklist: &[Address],
-    eth_threshold: Option<U256>,
-) -> Result<Vec<WithdrawalParams>> {
-    let blacklist: Vec<_> = token_blacklist.iter().map(|a| a.0.to_vec()).collect();
-    // if no threshold, query _all_ ethereum withdrawals since all of them are >= 0.
-    let eth_threshold = eth_threshold.unwrap_or(U256::zero());
+    key: WithdrawalKey,
+) -> Result<Option<(u64, bool)>> {
+    let latency = STORAGE_METRICS.call[&"withdrawal_id_by_key"].start();
 
-    let data = sqlx::query!(
+    let withdrawal = sqlx::query!(
         "
         SELECT
-          w.tx_hash,
-          w.event_index_in_tx,
-          withdrawal_id,
-          finalization_data.l2_block_number,
-          l1_batch_number,
-          l2_message_index,
-          l2_tx_number_in_block,
-          message,
-          sender,
-          proof
+          w.id,
+          finalization_data.withdrawal_id IS NOT NULL AS \"has_data!\"
         FROM
-          finalization_data
-          JOIN withdrawals w ON finalization_data.withdrawal_id = w.id
+          withdrawals w
+          LEFT JOIN finalization_data ON finalization_data.withdrawal_id = w.id
         WHERE
-          finalization_tx IS NULL
-          AND failed_finalization_attempts < 3
-          AND finalization_data.l2_block_number <= COALESCE(
-            (
-              SELECT
-                MAX(l2_block_number)
-              FROM
-                l2_blocks
-              WHERE
-                execute_l1_block_number IS NOT NULL
-            ),
-            1
-          )
-          AND w.token NOT IN (SELECT * FROM UNNEST (
-            $2 :: BYTEA []
-          ))
-          AND (
-            CASE WHEN token = decode('000000000000000000000000000000000000800A', 'hex') THEN amount >= $3
-            ELSE TRUE
-            END
-          )
-        LIMIT
-          $1
+          w.tx_hash = $1
+          AND w.event_index_in_tx = $2
         ",
-        limit_by as i64,
-        &blacklist,
-        u256_to_big_decimal(eth_threshold),
+        key.tx_hash.as_bytes(),
+        key.event_index_in_tx as i32,
     )
-    .fetch_all(pool)
+    .fetch_optional(pool)
+    .await?
+    .map(|r| (r.id as u64, r.has_data));
+
+    latency.observe();
+
+    Ok(withdrawal)
+}
+
+/// Set whether a withdrawal may be finalized.
+///
+/// Returns `false` if there is no such withdrawal, it has already been
+/// finalized or it already is or is not finalizable.
+pub async fn set_withdrawal_finalizable(pool: &PgPool, id: u64, finalizable: bool) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    let latency = STORAGE_METRICS.call[&"set_withdrawal_finalizable"].start();
+
+    let ids: Vec<_> = sqlx::query!(
+        "
+        UPDATE
+          withdrawals
+        SET
+          finalizable = $2
+        WHERE
+          id = $1
+          AND finalizable = NOT $2
+          AND NOT EXISTS (
+            SELECT
+              1
+            FROM
+              finalization_data
+            WHERE
+              withdrawal_id = withdrawals.id
+              AND finalization_tx IS NOT NULL
+          )
+        RETURNING
+          id
+        ",
+        id as i64,
+        finalizable,
+    )
+    .fetch_all(&mut *tx)
     .await?
     .into_iter()
-    .map(|record| WithdrawalParams {
-        tx_hash: H256::from_slice(&record.tx_hash),
-        event_index_in_tx: record.event_index_in_tx as u32,
-        id: record.withdrawal_id as u64,
-        l2_block_number: record.l2_block_number as u64,
-        l1_batch_number: record.l1_batch_number.into(),
-        l2_message_index: record.l2_message_index as u32,
-        l2_tx_number_in_block: record.l2_tx_number_in_block as u16,
-        message: record.message.into(),
-        sender: Address::from_slice(&record.sender),
-        proof: bincode::deserialize(&record.proof)
-            .expect("storage contains data correctly serialized by bincode; qed"),
-    })
+    .map(|r| r.id)
     .collect();
 
-    Ok(data)
+    if !finalizable {
+        enqueue_webhook_deliveries(&mut tx, &ids, WebhookEvent::Unfinalizable).await?;
+    }
+
+    if !ids.is_empty() {
+        let change = if finalizable {
+            "finalizable"
+        } else {
+            "unfinalizable"
+        };
+        notify_withdrawals_changed(&mut *tx, &WithdrawalsChange::of(change, &ids)).await?;
+    }
+
+    tx.commit().await?;
+    latency.observe();
+
+    Ok(!ids.is_empty())
 }
 
-/// Get the earliest withdrawals never attempted to be finalized before
-pub async fn withdrawals_to_finalize_with_whitelist(
-    pool: &PgPool,
-    limit_by: u64,
-    token_whitelist: &[Address],
-    eth_threshold: Option<U256>,
-) -> Result<Vec<WithdrawalParams>> {
-    let whitelist: Vec<_> = token_whitelist.iter().map(|a| a.0.to_vec()).collect();
-    // if no threshold, query _all_ ethereum withdrawals since all of them are >= 0.
-    let eth_threshold = eth_threshold.unwrap_or(U256::zero());
+/// Set finalization priority of a withdrawal, withdrawals with
+/// higher priority are picked for finalization first.
+///
+/// Returns `false` if the withdrawal has no finalization parameters
+/// or has already been finalized.
+pub async fn set_withdrawal_priority(pool: &PgPool, id: u64, priority: i64) -> Result<bool> {
+    let latency = STORAGE_METRICS.call[&"set_withdrawal_priority"].start();
+
+    let updated = sqlx::query!(
+        "
+        UPDATE
+          finalization_data
+        SET
+          priority = $2
+        WHERE
+          withdrawal_id = $1
+          AND finalization_tx IS NULL
+        ",
+        id as i64,
+        priority,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    latency.observe();
+
+    Ok(updated > 0)
+}
+
+/// Forget failed finalization attempts of a withdrawal so that it is
+/// retried at once even if it has been given up on.
+///
+/// The history of the attempts in `finalization_attempts` is kept.
+///
+/// Returns `false` if the withdrawal has no finalization parameters
+/// or has already been finalized.
+pub async fn reset_finalization_attempts(pool: &PgPool, id: u64) -> Result<bool> {
+    let latency = STORAGE_METRICS.call[&"reset_finalization_attempts"].start();
+
+    let updated = sqlx::query!(
+        "
+        UPDATE
+          finalization_data
+        SET
+          failed_finalization_attempts = 0,
+          next_attempt_at = NULL,
+          given_up_at = NULL
+        WHERE
+          withdrawal_id = $1
+          AND finalization_tx IS NULL
+        ",
+        id as i64,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    latency.observe();
+
+    Ok(updated > 0)
+}
+
+/// Enqueue a withdrawal for finalization: make it finalizable, forget its
+/// failed finalization attempts and give it a `priority` at once.
+///
+/// Returns `false` without changing anything if the withdrawal has no
+/// finalization parameters or has already been finalized.
+pub async fn enqueue_withdrawal(pool: &PgPool, id: u64, priority: i64) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    let latency = STORAGE_METRICS.call[&"enqueue_withdrawal"].start();
 
-    let data = sqlx::query!(
+    let pending = sqlx::query!(
         "
         SELECT
-          w.tx_hash,
-          w.event_index_in_tx,
-          withdrawal_id,
-          finalization_data.l2_block_number,
-          l1_batch_number,
-          l2_message_index,
-          l2_tx_number_in_block,
-          message,
-          sender,
-          proof
+          finalization_tx IS NULL AS \"pending!\"
         FROM
           finalization_data
-          JOIN withdrawals w ON finalization_data.withdrawal_id = w.id
         WHERE
-          finalization_tx IS NULL
-          AND failed_finalization_attempts < 3
-          AND finalization_data.l2_block_number <= COALESCE(
-            (
-              SELECT
-                MAX(l2_block_number)
-              FROM
-                l2_blocks
-              WHERE
-                execute_l1_block_number IS NOT NULL
-            ),
-            1
-          )
-          AND w.token IN (SELECT * FROM UNNEST (
-            $2 :: BYTEA []
-          ))
-          AND (
-            CASE WHEN token = decode('000000000000000000000000000000000000800A', 'hex') THEN amount >= $3
-            ELSE TRUE
-            END
-          )
-        LIMIT
-          $1
+          withdrawal_id = $1
+        FOR UPDATE
         ",
-        limit_by as i64,
-        &whitelist,
-        u256_to_big_decimal(eth_threshold),
+        id as i64,
     )
-    .fetch_all(pool)
+    .fetch_optional(&mut *tx)
     .await?
-    .into_iter()
-    .map(|record| WithdrawalParams {
-        tx_hash: H256::from_slice(&record.tx_hash),
-        event_index_in_tx: record.event_index_in_tx as u32,
-        id: record.withdrawal_id as u64,
-        l2_block_number: record.l2_block_number as u64,
-        l1_batch_number: record.l1_batch_number.into(),
-        l2_message_index: record.l2_message_index as u32,
-        l2_tx_number_in_block: record.l2_tx_number_in_block as u16,
-        message: record.message.into(),
-        sender: Address::from_slice(&record.sender),
-        proof: bincode::deserialize(&record.proof)
-            .expect("storage contains data correctly serialized by bincode; qed"),
-    })
-    .collect();
+    .is_some_and(|r| r.pending);
+
+    if !pending {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        "
+        UPDATE
+          withdrawals
+        SET
+          finalizable = TRUE
+        WHERE
+          id = $1
+        ",
+        id as i64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        UPDATE
+          finalization_data
+        SET
+          failed_finalization_attempts = 0,
+          next_attempt_at = NULL,
+          given_up_at = NULL,
+          priority = $2
+        WHERE
+          withdrawal_id = $1
+        ",
+        id as i64,
+        priority,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    latency.observe();
+
+    Ok(true)
+}
 
-    Ok(data)
+// Split thresholds of tokens into columns to be passed to `UNNEST`.
+fn token_thresholds_params(token_thresholds: &[(Address, f64)]) -> (Vec<Vec<u8>>, Vec<f64>) {
+    token_thresholds
+        .iter()
+        .map(|(token, threshold)| (token.as_bytes().to_vec(), *threshold))
+        .unzip()
 }
 
-/// Get the earliest withdrawals never attempted to be finalized before
-pub async fn withdrawals_to_finalize(
+/// Count executed withdrawals that are not finalized because their amount
+/// is below the ETH threshold or the threshold of their token.
+///
+/// Thresholds of tokens are given in token units by either L1 or L2 address of the token.
+pub async fn unprofitable_withdrawals_count(
     pool: &PgPool,
-    limit_by: u64,
     eth_threshold: Option<U256>,
-) -> Result<Vec<WithdrawalParams>> {
-    let latency = STORAGE_METRICS.call[&"withdrawals_to_finalize"].start();
-    // if no threshold, query _all_ ethereum withdrawals since all of them are >= 0.
+    token_thresholds: &[(Address, f64)],
+) -> Result<i64> {
+    let latency = STORAGE_METRICS.call[&"unprofitable_withdrawals_count"].start();
     let eth_threshold = eth_threshold.unwrap_or(U256::zero());
+    let (threshold_tokens, thresholds) = token_thresholds_params(token_thresholds);
 
-    let data = sqlx::query!(
+    let count = sqlx::query!(
         "
         SELECT
-          w.tx_hash,
-          w.event_index_in_tx,
-          withdrawal_id,
-          finalization_data.l2_block_number,
-          l1_batch_number,
-          l2_message_index,
-          l2_tx_number_in_block,
-          message,
-          sender,
-          proof
+          COUNT(*) AS \"count!\"
         FROM
           finalization_data
           JOIN withdrawals w ON finalization_data.withdrawal_id = w.id
         WHERE
           finalization_tx IS NULL
-          AND failed_finalization_attempts < 3
-          AND finalization_data.l2_block_number <= COALESCE(
+          AND (
             (
+              token = decode('000000000000000000000000000000000000800A', 'hex')
+              AND amount < $1
+            )
+            OR EXISTS (
               SELECT
-                MAX(l2_block_number)
+                1
               FROM
-                l2_blocks
+                UNNEST ($2 :: BYTEA [], $3 :: FLOAT8 []) AS thresholds (token, threshold)
+                JOIN tokens ON thresholds.token IN (tokens.l1_token_address, tokens.l2_token_address)
               WHERE
-                execute_l1_block_number IS NOT NULL
-            ),
-            1
-          )
-          AND (
-            last_finalization_attempt IS NULL
-          OR
-            last_finalization_attempt < NOW() - INTERVAL '1 minutes'
-          )
-          AND (
-            CASE WHEN token = decode('000000000000000000000000000000000000800A', 'hex') THEN amount >= $2
-            ELSE TRUE
-            END
+                tokens.l2_token_address = w.token
+                AND w.amount < thresholds.threshold :: NUMERIC * POWER(10 :: NUMERIC, tokens.decimals)
+            )
           )
-        LIMIT
-          $1
         ",
-        limit_by as i64,
         u256_to_big_decimal(eth_threshold),
+        &threshold_tokens,
+        &thresholds,
     )
-    .fetch_all(pool)
+    .fetch_one(pool)
     .await?
-    .into_iter()
-    .map(|record| WithdrawalParams {
-        tx_hash: H256::from_slice(&record.tx_hash),
-        event_index_in_tx: record.event_index_in_tx as u32,
-        id: record.withdrawal_id as u64,
-        l2_block_number: record.l2_block_number as u64,
-        l1_batch_number: record.l1_batch_number.into(),
-        l2_message_index: record.l2_message_index as u32,
-        l2_tx_number_in_block: record.l2_tx_number_in_block as u16,
-        message: record.message.into(),
-        sender: Address::from_slice(&record.sender),
-        proof: bincode::deserialize(&record.proof)
-            .expect("storage contains data correctly serialized by bincode; qed"),
-    })
-    .collect();
+    .count;
 
     latency.observe();
 
-    Ok(data)
+    Ok(count)
 }
 
 /// Get the number of ETH withdrawals not yet executed and finalized and above some threshold
@@ -1098,14 +1501,16 @@ pub async fn finalization_data_set_finalized_in_tx(
         event_index_in_tx.push(w.event_index_in_tx as i32);
     });
 
+    let mut tx = pool.begin().await?;
     let latency = STORAGE_METRICS.call[&"finalization_data_set_finalized_in_tx"].start();
 
-    sqlx::query!(
+    let ids: Vec<_> = sqlx::query!(
         "
         UPDATE
           finalization_data
         SET
-          finalization_tx = $1
+          finalization_tx = $1,
+          finalized_at = COALESCE(finalized_at, NOW())
         FROM
           (
             SELECT
@@ -1122,316 +1527,3380 @@ pub async fn finalization_data_set_finalized_in_tx(
               tx_hash = u.tx_hash
               AND event_index_in_tx = u.event_index_in_tx
           )
+        RETURNING finalization_data.withdrawal_id
         ",
         &tx_hash.0.as_ref(),
         &tx_hashes,
         &event_index_in_tx,
     )
-    .execute(pool)
-    .await?;
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|r| r.withdrawal_id)
+    .collect();
+
+    // Withdrawals found finalized by someone else are marked with a zero hash.
+    let event = if tx_hash.is_zero() {
+        WebhookEvent::FinalizedElsewhere
+    } else {
+        WebhookEvent::Finalized
+    };
 
+    enqueue_webhook_deliveries(&mut tx, &ids, event).await?;
+    notify_withdrawals_changed(&mut *tx, &WithdrawalsChange::of("finalized", &ids)).await?;
+
+    tx.commit().await?;
     latency.observe();
 
     Ok(())
 }
 
-/// Increment unsuccessful transaction attempt count for a set
-/// of withdrawals
-pub async fn inc_unsuccessful_finalization_attempts(
-    pool: &PgPool,
-    withdrawals: &[WithdrawalKey],
-) -> Result<()> {
-    let mut tx_hashes = Vec::with_capacity(withdrawals.len());
-    let mut event_index_in_tx = Vec::with_capacity(withdrawals.len());
+/// A policy of retrying failed finalization attempts of withdrawals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Give up on a withdrawal after this many failed attempts.
+    pub max_attempts: u64,
+    /// Delay before the first retry of a withdrawal.
+    pub initial_backoff: Duration,
+    /// Maximal delay between attempts.
+    pub max_backoff: Duration,
+    /// Multiplier of the delay after every failed attempt.
+    pub multiplier: f64,
+    /// Delays are randomly extended by up to this fraction of them.
+    pub jitter: f64,
+}
 
-    withdrawals.iter().for_each(|w| {
-        tx_hashes.push(w.tx_hash.0.to_vec());
-        event_index_in_tx.push(w.event_index_in_tx as i32);
-    });
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(60 * 60),
+            multiplier: 2.0,
+            jitter: 0.1,
+        }
+    }
+}
 
-    let latency = STORAGE_METRICS.call[&"inc_unsuccessful_finalization_attempts"].start();
+/// A class of a reason a finalization attempt has failed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureClass {
+    /// The withdrawal has already been finalized.
+    AlreadyFinalized,
+    /// The proof of inclusion of the withdrawal message is invalid.
+    InvalidProof,
+    /// Transfers of the withdrawn token are paused.
+    TokenPaused,
+    /// Finalization has run out of gas.
+    OutOfGas,
+    /// The reason is not known.
+    Unknown,
+}
 
-    sqlx::query!(
-        "
-        UPDATE
-          finalization_data
-        SET
-          last_finalization_attempt = NOW(),
-          failed_finalization_attempts = failed_finalization_attempts + 1
-        FROM
-          (
-            SELECT
-              UNNEST ($1 :: BYTEA []) AS tx_hash,
-              UNNEST ($2 :: integer []) AS event_index_in_tx
-          ) AS u
-        WHERE
-          finalization_data.withdrawal_id = (
-            SELECT
-              id
-            FROM
-              withdrawals
-            WHERE
-              tx_hash = u.tx_hash
-              AND event_index_in_tx = u.event_index_in_tx
-          )
-        ",
-        &tx_hashes,
-        &event_index_in_tx,
-    )
-    .execute(pool)
-    .await?;
+impl FailureClass {
+    /// Name of the class as stored in the `finalization_attempts` table.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AlreadyFinalized => "already_finalized",
+            Self::InvalidProof => "invalid_proof",
+            Self::TokenPaused => "token_paused",
+            Self::OutOfGas => "out_of_gas",
+            Self::Unknown => "unknown",
+        }
+    }
 
-    latency.observe();
+    /// Whether a withdrawal that has failed for this class of reasons may ever succeed.
+    pub fn is_retriable(&self) -> bool {
+        !matches!(self, Self::InvalidProof)
+    }
+}
 
-    Ok(())
+/// A failed finalization attempt of a withdrawal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalizationFailure {
+    /// Withdrawal id.
+    pub withdrawal_id: i64,
+    /// Class of the reason of the failure.
+    pub class: FailureClass,
+    /// Human readable reason of the failure.
+    pub reason: String,
+    /// Revert data of the failed call, if any.
+    pub revert_data: Option<Vec<u8>>,
 }
 
-/// Fetch decimals and L1 address for a token.
-///
-/// # Arguments
-///
-/// * `pool` - `PgPool`
-/// * `token` - L2 token address.
-pub async fn token_decimals_and_l1_address(
-    pool: &PgPool,
-    token: Address,
-) -> Result<Option<(u32, Address)>> {
-    let latency = STORAGE_METRICS.call[&"token_decimals_and_l1_address"].start();
+// Record failed finalization attempts of withdrawals, schedule their next
+// attempts or give up on them according to `retry_policy`.
+//
+// Withdrawals that have failed for a reason that is not retriable are given up on at once.
+async fn record_failed_attempts(
+    conn: &mut PgConnection,
+    failures: &[FinalizationFailure],
+    retry_policy: &RetryPolicy,
+) -> Result<()> {
+    let mut ids = Vec::with_capacity(failures.len());
+    let mut classes = Vec::with_capacity(failures.len());
+    let mut reasons = Vec::with_capacity(failures.len());
+    let mut revert_data = Vec::with_capacity(failures.len());
+    let mut give_up = Vec::with_capacity(failures.len());
+
+    for f in failures {
+        ids.push(f.withdrawal_id);
+        classes.push(f.class.as_str().to_string());
+        reasons.push(f.reason.clone());
+        revert_data.push(f.revert_data.clone().unwrap_or_default());
+        give_up.push(!f.class.is_retriable());
+    }
 
-    let result = sqlx::query!(
+    let given_up: Vec<_> = sqlx::query!(
         "
+        WITH failed AS (
+          UPDATE
+            finalization_data
+          SET
+            last_finalization_attempt = NOW(),
+            failed_finalization_attempts = COALESCE(failed_finalization_attempts, 0) + 1,
+            next_attempt_at = NOW() + LEAST(
+              $6 :: FLOAT8 * POWER($8 :: FLOAT8, COALESCE(failed_finalization_attempts, 0)),
+              $7 :: FLOAT8
+            ) * (1 + $9 :: FLOAT8 * RANDOM()) * INTERVAL '1 second',
+            given_up_at = CASE
+              WHEN u.give_up OR COALESCE(failed_finalization_attempts, 0) + 1 >= $10 THEN NOW()
+              ELSE NULL
+            END
+          FROM
+            UNNEST (
+              $1 :: BIGINT [],
+              $2 :: VARCHAR [],
+              $3 :: TEXT [],
+              $4 :: BYTEA [],
+              $5 :: BOOLEAN []
+            ) AS u (withdrawal_id, class, reason, revert_data, give_up)
+          WHERE
+            finalization_data.withdrawal_id = u.withdrawal_id
+          RETURNING
+            finalization_data.withdrawal_id,
+            finalization_data.failed_finalization_attempts,
+            finalization_data.given_up_at IS NOT NULL AS given_up,
+            u.class,
+            u.reason,
+            u.revert_data
+        ),
+        attempts AS (
+          INSERT INTO
+            finalization_attempts (withdrawal_id, attempt, class, reason, revert_data)
+          SELECT
+            withdrawal_id,
+            COALESCE(failed_finalization_attempts, 0),
+            class,
+            reason,
+            NULLIF(revert_data, '' :: BYTEA)
+          FROM
+            failed
+        )
         SELECT
-            decimals,
-            l1_token_address
+          withdrawal_id AS \"withdrawal_id!\"
         FROM
-            tokens
+          failed
         WHERE
-            l2_token_address = $1
+          given_up
         ",
-        token.as_bytes(),
+        &ids,
+        &classes,
+        &reasons,
+        &revert_data,
+        &give_up,
+        retry_policy.initial_backoff.as_secs_f64(),
+        retry_policy.max_backoff.as_secs_f64(),
+        retry_policy.multiplier,
+        retry_policy.jitter,
+        retry_policy.max_attempts as i64,
     )
-    .fetch_optional(pool)
+    .fetch_all(&mut *conn)
     .await?
-    .map(|r| (r.decimals as u32, Address::from_slice(&r.l1_token_address)));
+    .into_iter()
+    .map(|r| r.withdrawal_id)
+    .collect();
 
-    latency.observe();
-    Ok(result)
+    enqueue_webhook_deliveries(conn, &given_up, WebhookEvent::GivenUp).await?;
+
+    Ok(())
 }
 
-async fn wipe_finalization_data(pool: &PgPool, delete_batch_size: usize) -> Result<()> {
-    loop {
-        let deleted_ids = sqlx::query!(
-            "
-            DELETE FROM
-              finalization_data
-            WHERE
-              withdrawal_id in (
-                SELECT
-                  withdrawal_id
-                from
-                  finalization_data
-                LIMIT
-                  $1
-              )
-            RETURNING withdrawal_id
-            ",
-            delete_batch_size as i64,
-        )
-        .fetch_all(pool)
-        .await?;
+/// Record failed finalization attempts of withdrawals,
+/// retries are scheduled according to `retry_policy`.
+pub async fn inc_unsuccessful_finalization_attempts(
+    pool: &PgPool,
+    failures: &[FinalizationFailure],
+    retry_policy: &RetryPolicy,
+) -> Result<()> {
+    let latency = STORAGE_METRICS.call[&"inc_unsuccessful_finalization_attempts"].start();
 
-        if deleted_ids.is_empty() {
-            return Ok(());
-        }
-    }
+    let mut tx = pool.begin().await?;
+    record_failed_attempts(&mut tx, failures, retry_policy).await?;
+    tx.commit().await?;
+
+    latency.observe();
+
+    Ok(())
 }
 
-async fn wipe_l2_blocks(pool: &PgPool, delete_batch_size: usize) -> Result<()> {
-    loop {
-        let deleted_ids = sqlx::query!(
-            "
-            DELETE FROM
-              l2_blocks
-            WHERE
-              l2_block_number in (
-                SELECT
-                  l2_block_number
-                from
-                  l2_blocks
-                LIMIT
-                  $1
-              )
-            RETURNING l2_block_number
-            ",
-            delete_batch_size as i64,
-        )
-        .fetch_all(pool)
-        .await?;
+/// A withdrawal that is not retried anymore after too many failed attempts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GivenUpWithdrawal {
+    /// Withdrawal id.
+    pub id: u64,
+    /// Transaction hash.
+    pub tx_hash: H256,
+    /// Event index in the transaction.
+    pub event_index_in_tx: u32,
+    /// Token address.
+    pub token: Address,
+    /// Amount.
+    pub amount: U256,
+    /// Number of failed finalization attempts.
+    pub attempts: u64,
+    /// Reason of the last failed attempt, if recorded.
+    pub last_failure_reason: Option<String>,
+    /// Unix timestamp of the moment the withdrawal was given up on.
+    pub given_up_at: u64,
+}
 
-        if deleted_ids.is_empty() {
-            return Ok(());
-        }
-    }
+/// Count withdrawals that are not retried anymore after too many failed attempts.
+pub async fn given_up_withdrawals_count(pool: &PgPool) -> Result<i64> {
+    let latency = STORAGE_METRICS.call[&"given_up_withdrawals_count"].start();
+
+    let count = sqlx::query!(
+        "
+        SELECT
+          COUNT(*) AS \"count!\"
+        FROM
+          finalization_data
+        WHERE
+          finalization_tx IS NULL
+          AND given_up_at IS NOT NULL
+        "
+    )
+    .fetch_one(pool)
+    .await?
+    .count;
+
+    latency.observe();
+
+    Ok(count)
 }
 
-async fn wipe_l2_to_l1_events(pool: &PgPool, delete_batch_size: usize) -> Result<()> {
-    loop {
-        let deleted_ids = sqlx::query!(
-            "
-            DELETE FROM
-              l2_to_l1_events
+/// The latest withdrawals that are not retried anymore after too many failed attempts.
+pub async fn given_up_withdrawals(pool: &PgPool, limit: u64) -> Result<Vec<GivenUpWithdrawal>> {
+    let latency = STORAGE_METRICS.call[&"given_up_withdrawals"].start();
+
+    let withdrawals = sqlx::query!(
+        "
+        SELECT
+          w.id,
+          w.tx_hash,
+          w.event_index_in_tx,
+          w.token,
+          w.amount,
+          COALESCE(finalization_data.failed_finalization_attempts, 0) AS \"attempts!\",
+          (
+            SELECT
+              reason
+            FROM
+              finalization_attempts
             WHERE
-              l1_block_number in (
-                SELECT
-                  l1_block_number
-                from
-                  l2_to_l1_events
-                LIMIT
-                  $1
-              )
-            RETURNING l1_block_number
-            ",
-            delete_batch_size as i64,
-        )
-        .fetch_all(pool)
-        .await?;
+              finalization_attempts.withdrawal_id = w.id
+            ORDER BY
+              finalization_attempts.id DESC
+            LIMIT
+              1
+          ) AS last_failure_reason,
+          EXTRACT(EPOCH FROM finalization_data.given_up_at) :: BIGINT AS \"given_up_at!\"
+        FROM
+          finalization_data
+          JOIN withdrawals w ON finalization_data.withdrawal_id = w.id
+        WHERE
+          finalization_tx IS NULL
+          AND given_up_at IS NOT NULL
+        ORDER BY
+          given_up_at DESC
+        LIMIT
+          $1
+        ",
+        limit as i64,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| GivenUpWithdrawal {
+        id: r.id as u64,
+        tx_hash: H256::from_slice(&r.tx_hash),
+        event_index_in_tx: r.event_index_in_tx as u32,
+        token: Address::from_slice(&r.token),
+        amount: utils::bigdecimal_to_u256(r.amount),
+        attempts: r.attempts as u64,
+        last_failure_reason: r.last_failure_reason,
+        given_up_at: r.given_up_at as u64,
+    })
+    .collect();
 
-        if deleted_ids.is_empty() {
-            return Ok(());
-        }
-    }
+    latency.observe();
+
+    Ok(withdrawals)
 }
 
-async fn wipe_tokens(pool: &PgPool) -> Result<()> {
-    sqlx::query!("DELETE FROM tokens").execute(pool).await?;
+/// A finalization transaction recorded in the `sent_transactions` journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentTransaction {
+    /// ID of the journal entry.
+    pub id: u64,
 
-    Ok(())
-}
+    /// Nonce of the transaction.
+    pub nonce: U256,
 
-async fn wipe_withdrawals(pool: &PgPool, delete_batch_size: usize) -> Result<()> {
-    loop {
-        let deleted_ids = sqlx::query!(
-            "
-            DELETE FROM
-              withdrawals
-            WHERE
-              id in (
-                SELECT
-                  id
-                from
-                  withdrawals
-                LIMIT
-                  $1
-              ) RETURNING id
-            ",
-            delete_batch_size as i64,
-        )
-        .fetch_all(pool)
-        .await?;
+    /// Recipient of the transaction.
+    pub to: Address,
 
-        if deleted_ids.is_empty() {
-            return Ok(());
-        }
-    }
+    /// Calldata of the transaction.
+    pub calldata: Vec<u8>,
+
+    /// Gas limit of the transaction.
+    pub gas_limit: U256,
+
+    /// IDs of the withdrawals finalized by the transaction.
+    pub withdrawal_ids: Vec<i64>,
+
+    /// All hashes the transaction has been sent with, oldest first.
+    pub tx_hashes: Vec<SentTransactionHash>,
 }
 
-/// Delete all content from finalizer db tables
-pub async fn delete_db_content(pool: &PgPool, delete_batch_size: usize) -> Result<()> {
-    wipe_finalization_data(pool, delete_batch_size).await?;
+/// A single submission of a [`SentTransaction`] to the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SentTransactionHash {
+    /// Hash of the submitted transaction.
+    pub tx_hash: H256,
 
-    wipe_l2_blocks(pool, delete_batch_size).await?;
+    /// Max fee per gas or the gas price of a legacy transaction.
+    pub max_fee_per_gas: U256,
 
-    wipe_l2_to_l1_events(pool, delete_batch_size).await?;
+    /// Max priority fee per gas, `None` for legacy transactions.
+    pub max_priority_fee_per_gas: Option<U256>,
+}
 
-    wipe_tokens(pool).await?;
+/// Records a new finalization transaction into the journal before it is sent.
+///
+/// Returns the ID of the journal entry.
+pub async fn add_sent_transaction(
+    pool: &PgPool,
+    account: Address,
+    nonce: U256,
+    to: Address,
+    calldata: &[u8],
+    gas_limit: U256,
+    withdrawal_ids: &[i64],
+) -> Result<u64> {
+    let latency = STORAGE_METRICS.call[&"add_sent_transaction"].start();
+
+    let id = sqlx::query!(
+        "
+        INSERT INTO
+          sent_transactions (
+            account,
+            nonce,
+            to_address,
+            calldata,
+            gas_limit,
+            withdrawal_ids
+          )
+        VALUES
+          ($1, $2, $3, $4, $5, $6)
+        RETURNING id
+        ",
+        account.as_bytes(),
+        nonce.as_u64() as i64,
+        to.as_bytes(),
+        calldata,
+        u256_to_big_decimal(gas_limit),
+        withdrawal_ids,
+    )
+    .fetch_one(pool)
+    .await?
+    .id;
 
-    wipe_withdrawals(pool, delete_batch_size).await?;
+    latency.observe();
 
-    Ok(())
+    Ok(id as u64)
 }
 
-/// Delete all content from `finalization_data` table.
-pub async fn delete_finalization_data_content(
+/// Records a submission of a journaled transaction to the network.
+pub async fn add_sent_transaction_hash(
     pool: &PgPool,
-    delete_batch_size: usize,
+    sent_transaction_id: u64,
+    hash: &SentTransactionHash,
 ) -> Result<()> {
-    wipe_finalization_data(pool, delete_batch_size).await?;
+    let latency = STORAGE_METRICS.call[&"add_sent_transaction_hash"].start();
 
-    Ok(())
-}
+    sqlx::query!(
+        "
+        INSERT INTO
+          sent_transaction_hashes (
+            tx_hash,
+            sent_transaction_id,
+            max_fee_per_gas,
+            max_priority_fee_per_gas
+          )
+        VALUES
+          ($1, $2, $3, $4) ON CONFLICT (tx_hash) DO NOTHING
+        ",
+        hash.tx_hash.as_bytes(),
+        sent_transaction_id as i64,
+        u256_to_big_decimal(hash.max_fee_per_gas),
+        hash.max_priority_fee_per_gas.map(u256_to_big_decimal),
+    )
+    .execute(pool)
+    .await?;
 
-/// Finalization status of a withdrawal
-#[derive(Debug, Clone)]
-pub enum FinalizationStatus {
-    /// Withdrawal has been finalized
-    Finalized,
-    /// Withdrawal has not been finalized
-    NotFinalized,
-}
+    latency.observe();
 
-/// Withdrawal event requested for address
-pub struct UserWithdrawal {
-    /// Transaction hash
-    pub tx_hash: H256,
-    /// Token address
-    pub token: Address,
-    /// Amount
-    pub amount: U256,
-    /// Status
-    pub status: FinalizationStatus,
+    Ok(())
 }
 
-/// Request withdrawals for a given address.
-pub async fn withdrawals_for_address(
+/// Get journaled transactions of an account whose outcome is not yet known.
+pub async fn pending_sent_transactions(
     pool: &PgPool,
-    address: Address,
-    limit: u64,
-) -> Result<Vec<UserWithdrawal>> {
-    let latency = STORAGE_METRICS.call[&"withdrawals_for_address"].start();
+    account: Address,
+) -> Result<Vec<SentTransaction>> {
+    let latency = STORAGE_METRICS.call[&"pending_sent_transactions"].start();
 
-    let events = sqlx::query!(
+    let mut sent_transactions: Vec<_> = sqlx::query!(
         "
-         SELECT
-             l2_to_l1_events.l1_token_addr,
-             l2_to_l1_events.amount,
-             withdrawals.tx_hash,
-             finalization_data.finalization_tx
-         FROM
-             l2_to_l1_events
-         JOIN finalization_data ON
-             finalization_data.l1_batch_number = l2_to_l1_events.l2_block_number
-         AND finalization_data.l2_tx_number_in_block = l2_to_l1_events.tx_number_in_block
-         JOIN withdrawals ON
-             withdrawals.id = finalization_data.withdrawal_id
-         WHERE
-            l2_to_l1_events.to_address = $1
-            OR
-            finalization_data.sender = $1
-         ORDER BY l2_to_l1_events.l2_block_number DESC
-         LIMIT $2
+        SELECT
+          id,
+          nonce,
+          to_address,
+          calldata,
+          gas_limit,
+          withdrawal_ids
+        FROM
+          sent_transactions
+        WHERE
+          account = $1
+          AND status = 'pending'
+        ORDER BY
+          nonce
         ",
-        address.as_bytes(),
-        limit as i64,
+        account.as_bytes(),
     )
     .fetch_all(pool)
     .await?
     .into_iter()
-    .map(|r| {
-        let status = if r.finalization_tx.is_some() {
-            FinalizationStatus::Finalized
-        } else {
-            FinalizationStatus::NotFinalized
-        };
-        UserWithdrawal {
-            tx_hash: H256::from_slice(&r.tx_hash),
-            token: Address::from_slice(&r.l1_token_addr),
-            amount: utils::bigdecimal_to_u256(r.amount),
-            status,
-        }
+    .map(|r| SentTransaction {
+        id: r.id as u64,
+        nonce: (r.nonce as u64).into(),
+        to: Address::from_slice(&r.to_address),
+        calldata: r.calldata,
+        gas_limit: utils::bigdecimal_to_u256(r.gas_limit),
+        withdrawal_ids: r.withdrawal_ids,
+        tx_hashes: vec![],
     })
     .collect();
 
-    latency.observe();
+    let ids: Vec<_> = sent_transactions.iter().map(|t| t.id as i64).collect();
 
-    Ok(events)
+    let hashes = sqlx::query!(
+        "
+        SELECT
+          sent_transaction_id,
+          tx_hash,
+          max_fee_per_gas,
+          max_priority_fee_per_gas
+        FROM
+          sent_transaction_hashes
+        WHERE
+          sent_transaction_id = ANY ($1)
+        ORDER BY
+          sent_at
+        ",
+        &ids,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for r in hashes {
+        if let Some(t) = sent_transactions
+            .iter_mut()
+            .find(|t| t.id == r.sent_transaction_id as u64)
+        {
+            t.tx_hashes.push(SentTransactionHash {
+                tx_hash: H256::from_slice(&r.tx_hash),
+                max_fee_per_gas: utils::bigdecimal_to_u256(r.max_fee_per_gas),
+                max_priority_fee_per_gas: r.max_priority_fee_per_gas.map(utils::bigdecimal_to_u256),
+            });
+        }
+    }
+
+    latency.observe();
+
+    Ok(sent_transactions)
+}
+
+/// A journaled transaction has been successfully mined in a transaction
+/// with a given hash, mark all withdrawals it covers as finalized in it.
+pub async fn sent_transaction_mined(
+    pool: &PgPool,
+    sent_transaction_id: u64,
+    tx_hash: H256,
+    fee_paid: U256,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let latency = STORAGE_METRICS.call[&"sent_transaction_mined"].start();
+
+    let withdrawal_ids = sqlx::query!(
+        "
+        UPDATE
+          sent_transactions
+        SET
+          status = 'mined',
+          mined_tx_hash = $2,
+          fee_paid = $3,
+          updated_at = NOW()
+        WHERE
+          id = $1
+        RETURNING withdrawal_ids
+        ",
+        sent_transaction_id as i64,
+        tx_hash.as_bytes(),
+        u256_to_big_decimal(fee_paid),
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .withdrawal_ids;
+
+    sqlx::query!(
+        "
+        UPDATE
+          finalization_data
+        SET
+          finalization_tx = $1,
+          finalized_at = COALESCE(finalized_at, NOW())
+        WHERE
+          withdrawal_id = ANY ($2)
+        ",
+        tx_hash.as_bytes(),
+        &withdrawal_ids,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    enqueue_webhook_deliveries(&mut tx, &withdrawal_ids, WebhookEvent::Finalized).await?;
+    notify_withdrawals_changed(
+        &mut *tx,
+        &WithdrawalsChange::of("finalized", &withdrawal_ids),
+    )
+    .await?;
+
+    tx.commit().await?;
+    latency.observe();
+
+    Ok(())
+}
+
+/// A journaled transaction has been mined in a transaction with a given hash
+/// paying `fee_paid` but has been reverted, record failed attempts of all withdrawals it covers.
+///
+/// Withdrawals missing from `failures` are recorded to have failed for an unknown reason.
+pub async fn sent_transaction_reverted(
+    pool: &PgPool,
+    sent_transaction_id: u64,
+    tx_hash: H256,
+    fee_paid: U256,
+    failures: &[FinalizationFailure],
+    retry_policy: &RetryPolicy,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let latency = STORAGE_METRICS.call[&"sent_transaction_reverted"].start();
+
+    let withdrawal_ids = sqlx::query!(
+        "
+        UPDATE
+          sent_transactions
+        SET
+          status = 'reverted',
+          mined_tx_hash = $2,
+          fee_paid = $3,
+          updated_at = NOW()
+        WHERE
+          id = $1
+        RETURNING withdrawal_ids
+        ",
+        sent_transaction_id as i64,
+        tx_hash.as_bytes(),
+        u256_to_big_decimal(fee_paid),
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .withdrawal_ids;
+
+    let failures: Vec<_> = withdrawal_ids
+        .into_iter()
+        .map(|id| {
+            failures
+                .iter()
+                .find(|f| f.withdrawal_id == id)
+                .cloned()
+                .unwrap_or_else(|| FinalizationFailure {
+                    withdrawal_id: id,
+                    class: FailureClass::Unknown,
+                    reason: format!("transaction {tx_hash:?} reverted"),
+                    revert_data: None,
+                })
+        })
+        .collect();
+
+    record_failed_attempts(&mut tx, &failures, retry_policy).await?;
+
+    tx.commit().await?;
+    latency.observe();
+
+    Ok(())
+}
+
+/// Fees spent by `account` within the last `period`.
+///
+/// Includes fees paid by transactions mined within the `period` and the
+/// maximal fees pending transactions may pay given their last submission.
+pub async fn fees_spent(pool: &PgPool, account: Address, period: Duration) -> Result<U256> {
+    let latency = STORAGE_METRICS.call[&"fees_spent"].start();
+
+    let fees = sqlx::query!(
+        "
+        SELECT
+          COALESCE(SUM(fee), 0) AS \"fee!\"
+        FROM
+          (
+            SELECT
+              fee_paid AS fee
+            FROM
+              sent_transactions
+            WHERE
+              account = $1
+              AND fee_paid IS NOT NULL
+              AND updated_at > NOW() - make_interval(secs => $2)
+            UNION ALL
+            SELECT
+              sent_transactions.gas_limit * last_sent.max_fee_per_gas AS fee
+            FROM
+              sent_transactions
+              JOIN LATERAL (
+                SELECT
+                  max_fee_per_gas
+                FROM
+                  sent_transaction_hashes
+                WHERE
+                  sent_transaction_id = sent_transactions.id
+                ORDER BY
+                  sent_at DESC
+                LIMIT
+                  1
+              ) last_sent ON TRUE
+            WHERE
+              account = $1
+              AND status = 'pending'
+          ) fees
+        ",
+        account.as_bytes(),
+        period.as_secs_f64(),
+    )
+    .fetch_one(pool)
+    .await?
+    .fee;
+
+    latency.observe();
+
+    Ok(utils::bigdecimal_to_u256(fees))
+}
+
+/// A journaled transaction will never be mined, its nonce may be reused.
+pub async fn sent_transaction_dropped(pool: &PgPool, sent_transaction_id: u64) -> Result<()> {
+    let latency = STORAGE_METRICS.call[&"sent_transaction_dropped"].start();
+
+    sqlx::query!(
+        "
+        UPDATE
+          sent_transactions
+        SET
+          status = 'dropped',
+          updated_at = NOW()
+        WHERE
+          id = $1
+        ",
+        sent_transaction_id as i64,
+    )
+    .execute(pool)
+    .await?;
+
+    latency.observe();
+
+    Ok(())
+}
+
+/// Historic average gas spent on finalization of withdrawals of the same tokens.
+///
+/// Returns pairs of withdrawal IDs and average gas, withdrawals of
+/// tokens without any gas samples are omitted.
+pub async fn withdrawals_gas_estimates(pool: &PgPool, ids: &[i64]) -> Result<Vec<(u64, U256)>> {
+    let latency = STORAGE_METRICS.call[&"withdrawals_gas_estimates"].start();
+
+    let estimates = sqlx::query!(
+        "
+        SELECT
+          w.id,
+          token_gas_usage.average_gas
+        FROM
+          withdrawals w
+          JOIN token_gas_usage ON token_gas_usage.token = w.token
+        WHERE
+          w.id = ANY ($1)
+        ",
+        ids,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| (r.id as u64, utils::bigdecimal_to_u256(r.average_gas)))
+    .collect();
+
+    latency.observe();
+
+    Ok(estimates)
+}
+
+/// Add samples of gas spent on finalization of withdrawals to the
+/// averages of their tokens.
+///
+/// Averages are taken over at most `max_samples` last samples approximately.
+pub async fn add_withdrawals_gas_samples(
+    pool: &PgPool,
+    samples: &[(i64, U256)],
+    max_samples: u64,
+) -> Result<()> {
+    let latency = STORAGE_METRICS.call[&"add_withdrawals_gas_samples"].start();
+
+    let (ids, gas): (Vec<_>, Vec<_>) = samples
+        .iter()
+        .map(|(id, gas)| (*id, u256_to_big_decimal(*gas)))
+        .unzip();
+
+    sqlx::query!(
+        "
+        INSERT INTO
+          token_gas_usage (token, average_gas, samples)
+        SELECT
+          w.token,
+          ROUND(AVG(samples.gas)),
+          COUNT(*)
+        FROM
+          UNNEST ($1 :: BIGINT [], $2 :: NUMERIC []) AS samples (id, gas)
+          JOIN withdrawals w ON w.id = samples.id
+        GROUP BY
+          w.token ON CONFLICT (token) DO
+        UPDATE
+        SET
+          average_gas = ROUND(
+            (
+              token_gas_usage.average_gas * token_gas_usage.samples + EXCLUDED.average_gas * EXCLUDED.samples
+            ) / (token_gas_usage.samples + EXCLUDED.samples)
+          ),
+          samples = LEAST(token_gas_usage.samples + EXCLUDED.samples, $3),
+          updated_at = NOW()
+        ",
+        &ids,
+        &gas,
+        max_samples as i64,
+    )
+    .execute(pool)
+    .await?;
+
+    latency.observe();
+
+    Ok(())
+}
+
+/// Gas limits of finalization of withdrawals raised above the default one.
+///
+/// Returns pairs of withdrawal IDs and the greater of the gas limit of the
+/// withdrawal and the gas limit of its token, withdrawals without either are omitted.
+pub async fn withdrawals_gas_limits(pool: &PgPool, ids: &[i64]) -> Result<Vec<(u64, U256)>> {
+    let latency = STORAGE_METRICS.call[&"withdrawals_gas_limits"].start();
+
+    let gas_limits = sqlx::query!(
+        "
+        SELECT
+          w.id,
+          GREATEST(finalization_data.gas_limit, token_gas_limits.gas_limit) AS \"gas_limit!\"
+        FROM
+          withdrawals w
+          JOIN finalization_data ON finalization_data.withdrawal_id = w.id
+          LEFT JOIN token_gas_limits ON token_gas_limits.token = w.token
+        WHERE
+          w.id = ANY ($1)
+          AND (
+            finalization_data.gas_limit IS NOT NULL
+            OR token_gas_limits.gas_limit IS NOT NULL
+          )
+        ",
+        ids,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| (r.id as u64, utils::bigdecimal_to_u256(r.gas_limit)))
+    .collect();
+
+    latency.observe();
+
+    Ok(gas_limits)
+}
+
+/// Raise gas limits of finalization of withdrawals and of their tokens.
+///
+/// Gas limits of tokens are never lowered.
+pub async fn raise_withdrawals_gas_limits(pool: &PgPool, gas_limits: &[(i64, U256)]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let latency = STORAGE_METRICS.call[&"raise_withdrawals_gas_limits"].start();
+
+    let (ids, gas_limits): (Vec<_>, Vec<_>) = gas_limits
+        .iter()
+        .map(|(id, gas_limit)| (*id, u256_to_big_decimal(*gas_limit)))
+        .unzip();
+
+    sqlx::query!(
+        "
+        UPDATE
+          finalization_data
+        SET
+          gas_limit = u.gas_limit
+        FROM
+          UNNEST ($1 :: BIGINT [], $2 :: NUMERIC []) AS u (withdrawal_id, gas_limit)
+        WHERE
+          finalization_data.withdrawal_id = u.withdrawal_id
+        ",
+        &ids,
+        &gas_limits,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        INSERT INTO
+          token_gas_limits (token, gas_limit)
+        SELECT
+          w.token,
+          MAX(u.gas_limit)
+        FROM
+          UNNEST ($1 :: BIGINT [], $2 :: NUMERIC []) AS u (withdrawal_id, gas_limit)
+          JOIN withdrawals w ON w.id = u.withdrawal_id
+        GROUP BY
+          w.token ON CONFLICT (token) DO
+        UPDATE
+        SET
+          gas_limit = GREATEST(token_gas_limits.gas_limit, EXCLUDED.gas_limit),
+          updated_at = NOW()
+        ",
+        &ids,
+        &gas_limits,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    latency.observe();
+
+    Ok(())
+}
+
+/// A batch of withdrawals that would have been finalized in dry run mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DryRunBatch {
+    /// IDs of the withdrawals in the batch including the ones predicted to fail.
+    pub withdrawal_ids: Vec<i64>,
+
+    /// Predicted success of finalization of every withdrawal.
+    pub predicted_success: Vec<bool>,
+
+    /// Predicted gas spent on finalization of every withdrawal.
+    pub predicted_gas: Vec<U256>,
+
+    /// Size of the calldata of the transaction finalizing the batch.
+    pub calldata_size: u64,
+
+    /// Estimated gas of the transaction finalizing the batch, if it could be estimated.
+    pub estimated_gas: Option<U256>,
+}
+
+/// Records a batch of withdrawals that would have been finalized in dry run mode.
+pub async fn add_dry_run_batch(pool: &PgPool, batch: &DryRunBatch) -> Result<()> {
+    let latency = STORAGE_METRICS.call[&"add_dry_run_batch"].start();
+
+    let predicted_gas: Vec<_> = batch
+        .predicted_gas
+        .iter()
+        .copied()
+        .map(u256_to_big_decimal)
+        .collect();
+
+    sqlx::query!(
+        "
+        INSERT INTO
+          dry_run_batches (
+            withdrawal_ids,
+            predicted_success,
+            predicted_gas,
+            calldata_size,
+            estimated_gas
+          )
+        VALUES
+          ($1, $2, $3, $4, $5)
+        ",
+        &batch.withdrawal_ids,
+        &batch.predicted_success,
+        &predicted_gas,
+        batch.calldata_size as i64,
+        batch.estimated_gas.map(u256_to_big_decimal),
+    )
+    .execute(pool)
+    .await?;
+
+    latency.observe();
+
+    Ok(())
+}
+
+/// Fetch decimals and L1 address for a token.
+///
+/// # Arguments
+///
+/// * `pool` - `PgPool`
+/// * `token` - L2 token address.
+pub async fn token_decimals_and_l1_address(
+    pool: &PgPool,
+    token: Address,
+) -> Result<Option<(u32, Address)>> {
+    let latency = STORAGE_METRICS.call[&"token_decimals_and_l1_address"].start();
+
+    let result = sqlx::query!(
+        "
+        SELECT
+            decimals,
+            l1_token_address
+        FROM
+            tokens
+        WHERE
+            l2_token_address = $1
+        ",
+        token.as_bytes(),
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|r| (r.decimals as u32, Address::from_slice(&r.l1_token_address)));
+
+    latency.observe();
+    Ok(result)
+}
+
+async fn wipe_finalization_data(pool: &PgPool, delete_batch_size: usize) -> Result<()> {
+    loop {
+        let deleted_ids = sqlx::query!(
+            "
+            DELETE FROM
+              finalization_data
+            WHERE
+              withdrawal_id in (
+                SELECT
+                  withdrawal_id
+                from
+                  finalization_data
+                LIMIT
+                  $1
+              )
+            RETURNING withdrawal_id
+            ",
+            delete_batch_size as i64,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        if deleted_ids.is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+async fn wipe_l2_blocks(pool: &PgPool, delete_batch_size: usize) -> Result<()> {
+    loop {
+        let deleted_ids = sqlx::query!(
+            "
+            DELETE FROM
+              l2_blocks
+            WHERE
+              l2_block_number in (
+                SELECT
+                  l2_block_number
+                from
+                  l2_blocks
+                LIMIT
+                  $1
+              )
+            RETURNING l2_block_number
+            ",
+            delete_batch_size as i64,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        if deleted_ids.is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+async fn wipe_l2_to_l1_events(pool: &PgPool, delete_batch_size: usize) -> Result<()> {
+    loop {
+        let deleted_ids = sqlx::query!(
+            "
+            DELETE FROM
+              l2_to_l1_events
+            WHERE
+              l1_block_number in (
+                SELECT
+                  l1_block_number
+                from
+                  l2_to_l1_events
+                LIMIT
+                  $1
+              )
+            RETURNING l1_block_number
+            ",
+            delete_batch_size as i64,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        if deleted_ids.is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+async fn wipe_tokens(pool: &PgPool) -> Result<()> {
+    sqlx::query!("DELETE FROM tokens").execute(pool).await?;
+
+    Ok(())
+}
+
+async fn wipe_withdrawals(pool: &PgPool, delete_batch_size: usize) -> Result<()> {
+    loop {
+        let deleted_ids = sqlx::query!(
+            "
+            DELETE FROM
+              withdrawals
+            WHERE
+              id in (
+                SELECT
+                  id
+                from
+                  withdrawals
+                LIMIT
+                  $1
+              ) RETURNING id
+            ",
+            delete_batch_size as i64,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        if deleted_ids.is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+/// Release the withdrawals leased by the `holder`.
+///
+/// Returns the number of released withdrawals.
+pub async fn release_withdrawal_leases(pool: &PgPool, holder: &str) -> Result<u64> {
+    let latency = STORAGE_METRICS.call[&"release_withdrawal_leases"].start();
+
+    let released = sqlx::query!(
+        "
+        UPDATE
+          finalization_data
+        SET
+          leased_by = NULL,
+          lease_expires_at = NULL
+        WHERE
+          leased_by = $1
+        ",
+        holder
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    latency.observe();
+
+    Ok(released)
+}
+
+/// Release the withdrawals with `ids` leased by the `holder`.
+///
+/// Returns the number of released withdrawals.
+pub async fn release_leased_withdrawals(pool: &PgPool, holder: &str, ids: &[i64]) -> Result<u64> {
+    let latency = STORAGE_METRICS.call[&"release_leased_withdrawals"].start();
+
+    let released = sqlx::query!(
+        "
+        UPDATE
+          finalization_data
+        SET
+          leased_by = NULL,
+          lease_expires_at = NULL
+        WHERE
+          leased_by = $1
+          AND withdrawal_id = ANY ($2)
+        ",
+        holder,
+        ids,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    latency.observe();
+
+    Ok(released)
+}
+
+/// Try to become the leader of the instances of the `group` sharing the database.
+///
+/// The leadership is held through a session advisory lock as long as `conn` is open.
+pub async fn try_acquire_leadership(conn: &mut PgConnection, group: &str) -> Result<bool> {
+    let latency = STORAGE_METRICS.call[&"try_acquire_leadership"].start();
+
+    let acquired = sqlx::query!(
+        "SELECT pg_try_advisory_lock(hashtext('withdrawal-finalizer:' || $1)) AS \"acquired!\"",
+        group
+    )
+    .fetch_one(conn)
+    .await?
+    .acquired;
+
+    latency.observe();
+
+    Ok(acquired)
+}
+
+/// Check that the connection to the database is alive.
+pub async fn ping(conn: &mut PgConnection) -> Result<()> {
+    sqlx::query!("SELECT 1 AS \"one!\"").fetch_one(conn).await?;
+
+    Ok(())
+}
+
+/// Delete all content from finalizer db tables
+pub async fn delete_db_content(pool: &PgPool, delete_batch_size: usize) -> Result<()> {
+    wipe_finalization_data(pool, delete_batch_size).await?;
+
+    wipe_l2_blocks(pool, delete_batch_size).await?;
+
+    wipe_l2_to_l1_events(pool, delete_batch_size).await?;
+
+    wipe_tokens(pool).await?;
+
+    wipe_withdrawals(pool, delete_batch_size).await?;
+
+    Ok(())
+}
+
+/// Delete all content from `finalization_data` table.
+pub async fn delete_finalization_data_content(
+    pool: &PgPool,
+    delete_batch_size: usize,
+) -> Result<()> {
+    wipe_finalization_data(pool, delete_batch_size).await?;
+
+    Ok(())
+}
+
+/// Status of a withdrawal in its lifecycle.
+///
+/// Computed by the `withdrawal_statuses` view, a withdrawal
+/// is in the first of these statuses that applies to it:
+/// finalized ones first, then these that are not going to be finalized,
+/// then the ones being finalized and lastly by the progress of its L2 block on L1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinalizationStatus {
+    /// The L2 block of the withdrawal has not been committed on L1 yet.
+    PendingOnL2,
+    /// The L2 block of the withdrawal has been committed on L1.
+    Committed,
+    /// The L2 block of the withdrawal has been verified on L1.
+    Verified,
+    /// The L2 block of the withdrawal has been executed on L1.
+    Executed,
+    /// The withdrawal has been executed and its finalization parameters are fetched.
+    ParamsFetched,
+    /// A transaction finalizing the withdrawal has been sent and is pending.
+    Finalizing,
+    /// The withdrawal has been finalized by the finalizer.
+    Finalized,
+    /// The withdrawal has been found finalized by someone else.
+    FinalizedElsewhere,
+    /// The withdrawal is marked as never to be finalized.
+    Unfinalizable,
+    /// The finalizer has given up on the withdrawal after failed attempts.
+    GivenUp,
+}
+
+impl FinalizationStatus {
+    /// All the statuses in the order of the lifecycle.
+    pub const ALL: [Self; 10] = [
+        Self::PendingOnL2,
+        Self::Committed,
+        Self::Verified,
+        Self::Executed,
+        Self::ParamsFetched,
+        Self::Finalizing,
+        Self::Finalized,
+        Self::FinalizedElsewhere,
+        Self::Unfinalizable,
+        Self::GivenUp,
+    ];
+
+    /// Name of the status as computed by the `withdrawal_statuses` view.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingOnL2 => "pending_on_l2",
+            Self::Committed => "committed",
+            Self::Verified => "verified",
+            Self::Executed => "executed",
+            Self::ParamsFetched => "params_fetched",
+            Self::Finalizing => "finalizing",
+            Self::Finalized => "finalized",
+            Self::FinalizedElsewhere => "finalized_elsewhere",
+            Self::Unfinalizable => "unfinalizable",
+            Self::GivenUp => "given_up",
+        }
+    }
+
+    /// Whether the withdrawal has been finalized by anyone.
+    pub fn is_finalized(&self) -> bool {
+        matches!(self, Self::Finalized | Self::FinalizedElsewhere)
+    }
+}
+
+impl FromStr for FinalizationStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| Error::UnknownFinalizationStatus(s.to_string()))
+    }
+}
+
+/// Withdrawal event requested for address
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserWithdrawal {
+    /// Withdrawal id, withdrawals are listed by descending ids
+    pub id: u64,
+    /// Transaction hash
+    pub tx_hash: H256,
+    /// Event index in the transaction
+    pub event_index_in_tx: u32,
+    /// Token address
+    pub token: Address,
+    /// Amount
+    pub amount: U256,
+    /// Status
+    pub status: FinalizationStatus,
+    /// Number of the L1 block the withdrawal was committed in
+    pub commit_l1_block_number: Option<u64>,
+    /// When the withdrawal was seen, in seconds since the epoch
+    pub seen_at: Option<u64>,
+}
+
+/// A filter of withdrawals requested for address.
+#[derive(Debug, Clone, Default)]
+pub struct UserWithdrawalsFilter {
+    /// Only withdrawals with lower ids, the id of the last withdrawal of the previous page.
+    pub before_id: Option<u64>,
+    /// Only withdrawals of this L1 token.
+    pub token: Option<Address>,
+    /// Only withdrawals in this status.
+    pub status: Option<FinalizationStatus>,
+}
+
+/// Request withdrawals for a given address.
+pub async fn withdrawals_for_address(
+    pool: &PgPool,
+    address: Address,
+    filter: &UserWithdrawalsFilter,
+    limit: u64,
+) -> Result<Vec<UserWithdrawal>> {
+    let latency = STORAGE_METRICS.call[&"withdrawals_for_address"].start();
+
+    let events = sqlx::query!(
+        "
+         SELECT
+             withdrawals.id,
+             withdrawals.event_index_in_tx,
+             l2_to_l1_events.l1_token_addr,
+             l2_to_l1_events.amount,
+             withdrawals.tx_hash,
+             withdrawal_statuses.status AS \"status!\",
+             l2_blocks.commit_l1_block_number AS \"commit_l1_block_number?\",
+             EXTRACT(EPOCH FROM withdrawals.created_at) :: BIGINT AS seen_at
+         FROM
+             l2_to_l1_events
+         JOIN finalization_data ON
+             finalization_data.l1_batch_number = l2_to_l1_events.l2_block_number
+         AND finalization_data.l2_tx_number_in_block = l2_to_l1_events.tx_number_in_block
+         JOIN withdrawals ON
+             withdrawals.id = finalization_data.withdrawal_id
+         JOIN withdrawal_statuses ON
+             withdrawal_statuses.withdrawal_id = withdrawals.id
+         LEFT JOIN l2_blocks ON
+             l2_blocks.l2_block_number = withdrawals.l2_block_number
+         WHERE
+            (
+              l2_to_l1_events.to_address = $1
+              OR
+              finalization_data.sender = $1
+            )
+            AND ($3 :: BIGINT IS NULL OR withdrawals.id < $3)
+            AND ($4 :: BYTEA IS NULL OR l2_to_l1_events.l1_token_addr = $4)
+            AND ($5 :: TEXT IS NULL OR withdrawal_statuses.status = $5)
+         ORDER BY withdrawals.id DESC
+         LIMIT $2
+        ",
+        address.as_bytes(),
+        limit as i64,
+        filter.before_id.map(|id| id as i64),
+        filter.token.as_ref().map(|t| t.as_bytes()),
+        filter.status.map(|status| status.as_str()),
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| {
+        Ok(UserWithdrawal {
+            id: r.id as u64,
+            tx_hash: H256::from_slice(&r.tx_hash),
+            event_index_in_tx: r.event_index_in_tx as u32,
+            token: Address::from_slice(&r.l1_token_addr),
+            amount: utils::bigdecimal_to_u256(r.amount),
+            status: r.status.parse()?,
+            commit_l1_block_number: r.commit_l1_block_number.map(|b| b as u64),
+            seen_at: r.seen_at.map(|t| t as u64),
+        })
+    })
+    .collect::<Result<_>>()?;
+
+    latency.observe();
+
+    Ok(events)
+}
+
+/// Everything known about a withdrawal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithdrawalDetails {
+    /// Withdrawal id.
+    pub id: u64,
+    /// Transaction hash.
+    pub tx_hash: H256,
+    /// Event index in the transaction.
+    pub event_index_in_tx: u32,
+    /// L2 token address.
+    pub token: Address,
+    /// Amount.
+    pub amount: U256,
+    /// Number of the L2 block the withdrawal happened in.
+    pub l2_block_number: u64,
+    /// Number of the L1 batch the withdrawal is included in, known once it is executed.
+    pub l1_batch_number: Option<u64>,
+    /// Number of the L1 block the withdrawal was committed in.
+    pub commit_l1_block_number: Option<u64>,
+    /// Number of the L1 block the withdrawal was verified in.
+    pub verify_l1_block_number: Option<u64>,
+    /// Number of the L1 block the withdrawal was executed in.
+    pub execute_l1_block_number: Option<u64>,
+    /// Hash of the transaction that has finalized the withdrawal.
+    pub finalization_tx: Option<H256>,
+    /// Number of failed finalization attempts.
+    pub failed_finalization_attempts: u64,
+    /// Reason of the last failed attempt, if recorded.
+    pub last_failure_reason: Option<String>,
+    /// Whether the withdrawal is going to be finalized.
+    pub finalizable: bool,
+    /// Status.
+    pub status: FinalizationStatus,
+    /// When the withdrawal was seen, in seconds since the epoch.
+    pub seen_at: Option<u64>,
+}
+
+/// Request everything known about a withdrawal by its key.
+pub async fn withdrawal_details(
+    pool: &PgPool,
+    key: WithdrawalKey,
+) -> Result<Option<WithdrawalDetails>> {
+    let latency = STORAGE_METRICS.call[&"withdrawal_details"].start();
+
+    let details = details_of_withdrawals(pool, key.tx_hash, Some(key.event_index_in_tx))
+        .await?
+        .pop();
+
+    latency.observe();
+
+    Ok(details)
+}
+
+/// Request everything known about the withdrawals made in a transaction.
+pub async fn withdrawals_details_in_tx(
+    pool: &PgPool,
+    tx_hash: H256,
+) -> Result<Vec<WithdrawalDetails>> {
+    let latency = STORAGE_METRICS.call[&"withdrawals_details_in_tx"].start();
+
+    let details = details_of_withdrawals(pool, tx_hash, None).await?;
+
+    latency.observe();
+
+    Ok(details)
+}
+
+// Details of withdrawals made in a transaction, only of the one with `event_index_in_tx` if given.
+async fn details_of_withdrawals(
+    pool: &PgPool,
+    tx_hash: H256,
+    event_index_in_tx: Option<u32>,
+) -> Result<Vec<WithdrawalDetails>> {
+    sqlx::query!(
+        "
+        SELECT
+          w.id,
+          w.tx_hash,
+          w.event_index_in_tx,
+          w.token,
+          w.amount,
+          w.l2_block_number,
+          w.finalizable,
+          withdrawal_statuses.status AS \"status!\",
+          EXTRACT(EPOCH FROM w.created_at) :: BIGINT AS seen_at,
+          finalization_data.l1_batch_number AS \"l1_batch_number?\",
+          finalization_data.finalization_tx AS \"finalization_tx?\",
+          COALESCE(finalization_data.failed_finalization_attempts, 0) AS \"failed_finalization_attempts!\",
+          (
+            SELECT
+              reason
+            FROM
+              finalization_attempts
+            WHERE
+              finalization_attempts.withdrawal_id = w.id
+            ORDER BY
+              finalization_attempts.id DESC
+            LIMIT
+              1
+          ) AS last_failure_reason,
+          l2_blocks.commit_l1_block_number AS \"commit_l1_block_number?\",
+          l2_blocks.verify_l1_block_number AS \"verify_l1_block_number?\",
+          l2_blocks.execute_l1_block_number AS \"execute_l1_block_number?\"
+        FROM
+          withdrawals w
+          LEFT JOIN finalization_data ON finalization_data.withdrawal_id = w.id
+          LEFT JOIN l2_blocks ON l2_blocks.l2_block_number = w.l2_block_number
+          JOIN withdrawal_statuses ON withdrawal_statuses.withdrawal_id = w.id
+        WHERE
+          w.tx_hash = $1
+          AND ($2 :: INT IS NULL OR w.event_index_in_tx = $2)
+        ORDER BY
+          w.event_index_in_tx
+        ",
+        tx_hash.as_bytes(),
+        event_index_in_tx.map(|i| i as i32),
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| {
+        Ok(WithdrawalDetails {
+            id: r.id as u64,
+            tx_hash: H256::from_slice(&r.tx_hash),
+            event_index_in_tx: r.event_index_in_tx as u32,
+            token: Address::from_slice(&r.token),
+            amount: utils::bigdecimal_to_u256(r.amount),
+            l2_block_number: r.l2_block_number as u64,
+            l1_batch_number: r.l1_batch_number.map(|b| b as u64),
+            commit_l1_block_number: r.commit_l1_block_number.map(|b| b as u64),
+            verify_l1_block_number: r.verify_l1_block_number.map(|b| b as u64),
+            execute_l1_block_number: r.execute_l1_block_number.map(|b| b as u64),
+            finalization_tx: r.finalization_tx.map(|tx| H256::from_slice(&tx)),
+            failed_finalization_attempts: r.failed_finalization_attempts as u64,
+            last_failure_reason: r.last_failure_reason,
+            finalizable: r.finalizable,
+            status: r.status.parse()?,
+            seen_at: r.seen_at.map(|t| t as u64),
+        })
+    })
+    .collect()
+}
+
+/// Request the status of a withdrawal by its id.
+pub async fn withdrawal_status(pool: &PgPool, id: u64) -> Result<Option<FinalizationStatus>> {
+    let latency = STORAGE_METRICS.call[&"withdrawal_status"].start();
+
+    let status = sqlx::query!(
+        "
+        SELECT
+          status AS \"status!\"
+        FROM
+          withdrawal_statuses
+        WHERE
+          withdrawal_id = $1
+        ",
+        id as i64,
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|r| r.status.parse())
+    .transpose()?;
+
+    latency.observe();
+
+    Ok(status)
+}
+
+/// Count withdrawals that have not been finalized by their status.
+///
+/// Statuses no withdrawal is in are not returned.
+pub async fn unfinalized_withdrawals_count_by_status(
+    pool: &PgPool,
+) -> Result<Vec<(FinalizationStatus, u64)>> {
+    let latency = STORAGE_METRICS.call[&"unfinalized_withdrawals_count_by_status"].start();
+
+    let counts = sqlx::query!(
+        "
+        SELECT
+          status AS \"status!\",
+          COUNT(*) AS \"count!\"
+        FROM
+          withdrawal_statuses
+        WHERE
+          status NOT IN ('finalized', 'finalized_elsewhere')
+        GROUP BY
+          status
+        ",
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| Ok((r.status.parse()?, r.count as u64)))
+    .collect::<Result<_>>()?;
+
+    latency.observe();
+
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::types::{Address, H256, U256};
+    use pretty_assertions::assert_eq;
+    use sqlx::PgPool;
+
+    use chain_events::L2TokenInitEvent;
+    use client::{WithdrawalEvent, WithdrawalParams};
+
+    use super::StoredWithdrawal;
+
+    type L2BlockRow = (i64, Option<i64>, Option<i64>, Option<i64>);
+
+    async fn l2_blocks(pool: &PgPool) -> Vec<L2BlockRow> {
+        sqlx::query_as(
+            "
+            SELECT
+              l2_block_number,
+              commit_l1_block_number,
+              verify_l1_block_number,
+              execute_l1_block_number
+            FROM
+              l2_blocks
+            ORDER BY
+              l2_block_number
+            ",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap()
+    }
+
+    async fn finalization_data_ids(pool: &PgPool) -> Vec<i64> {
+        sqlx::query_scalar("SELECT withdrawal_id FROM finalization_data ORDER BY withdrawal_id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    fn withdrawal(block_number: u64) -> StoredWithdrawal {
+        StoredWithdrawal {
+            event: WithdrawalEvent {
+                tx_hash: H256::from_low_u64_be(block_number),
+                block_number,
+                token: Address::zero(),
+                amount: U256::one(),
+            },
+            index_in_tx: 0,
+        }
+    }
+
+    fn withdrawal_params(id: u64, block_number: u64, l1_batch_number: u64) -> WithdrawalParams {
+        WithdrawalParams {
+            tx_hash: H256::from_low_u64_be(block_number),
+            event_index_in_tx: 0,
+            id,
+            l2_block_number: block_number,
+            l1_batch_number: l1_batch_number.into(),
+            l2_message_index: 0,
+            l2_tx_number_in_block: 0,
+            message: Default::default(),
+            sender: Address::zero(),
+            proof: vec![],
+        }
+    }
+
+    fn failure(withdrawal_id: i64, reason: &str) -> super::FinalizationFailure {
+        super::FinalizationFailure {
+            withdrawal_id,
+            class: super::FailureClass::Unknown,
+            reason: reason.to_string(),
+            revert_data: None,
+        }
+    }
+
+    #[sqlx::test]
+    async fn revert_batches_rolls_back_statuses(pool: PgPool) {
+        // Batches 1..=4 contain two L2 blocks each.
+        for batch in 1..=4 {
+            let (begin, end) = (batch * 2 - 1, batch * 2);
+            super::committed_new_batch(&pool, begin, end, 100 + batch)
+                .await
+                .unwrap();
+        }
+        for batch in 1..=3 {
+            let (begin, end) = (batch * 2 - 1, batch * 2);
+            super::verified_new_batch(&pool, begin, end, 200 + batch)
+                .await
+                .unwrap();
+        }
+        for batch in 1..=2 {
+            let (begin, end) = (batch * 2 - 1, batch * 2);
+            super::executed_new_batch(&pool, begin, end, 300 + batch)
+                .await
+                .unwrap();
+        }
+
+        let withdrawals: Vec<_> = (1..=8).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=8)
+            .map(|b| withdrawal_params(b, b, b.div_ceil(2)))
+            .collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        // Batch 4 is no longer committed, batch 3 is no longer verified.
+        let reverted = super::revert_batches(&pool, 6, 4, 4, 3).await.unwrap();
+
+        assert_eq!(reverted, 2);
+        assert_eq!(
+            l2_blocks(&pool).await,
+            vec![
+                (1, Some(101), Some(201), Some(301)),
+                (2, Some(101), Some(201), Some(301)),
+                (3, Some(102), Some(202), Some(302)),
+                (4, Some(102), Some(202), Some(302)),
+                (5, Some(103), None, None),
+                (6, Some(103), None, None),
+                (7, None, None, None),
+                (8, None, None, None),
+            ]
+        );
+        assert_eq!(finalization_data_ids(&pool).await, vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[sqlx::test]
+    async fn revert_batches_refetches_dropped_withdrawals(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 4, 100).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=4).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=4).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        super::finalization_data_set_finalized_in_tx(
+            &pool,
+            &[params[3].key()],
+            H256::from_low_u64_be(42),
+        )
+        .await
+        .unwrap();
+
+        let reverted = super::revert_batches(&pool, 2, 2, 2, 0).await.unwrap();
+
+        assert_eq!(reverted, 2);
+        assert_eq!(finalization_data_ids(&pool).await, vec![1, 2, 4]);
+
+        // The dropped withdrawal is fetched again once its block is committed again
+        // even though a later withdrawal has kept its finalization data.
+        super::committed_new_batch(&pool, 3, 4, 101).await.unwrap();
+
+        let no_data: Vec<_> = super::get_withdrawals_with_no_data(&pool, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|w| w.id)
+            .collect();
+        assert_eq!(no_data, vec![3]);
+    }
+
+    #[sqlx::test]
+    async fn rewind_to_l1_block_rolls_back_statuses(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 2, 100).await.unwrap();
+        super::committed_new_batch(&pool, 3, 4, 101).await.unwrap();
+        super::committed_new_batch(&pool, 5, 6, 103).await.unwrap();
+        super::verified_new_batch(&pool, 1, 2, 101).await.unwrap();
+        super::verified_new_batch(&pool, 3, 4, 104).await.unwrap();
+        super::executed_new_batch(&pool, 1, 2, 102).await.unwrap();
+
+        for block_number in 100..=104 {
+            super::add_l1_block_hash(&pool, block_number, H256::from_low_u64_be(block_number), 64)
+                .await
+                .unwrap();
+        }
+
+        let rewound = super::rewind_to_l1_block(&pool, 102).await.unwrap();
+
+        assert_eq!(rewound, 2);
+        assert_eq!(
+            l2_blocks(&pool).await,
+            vec![
+                (1, Some(100), Some(101), None),
+                (2, Some(100), Some(101), None),
+                (3, Some(101), None, None),
+                (4, Some(101), None, None),
+                (5, None, None, None),
+                (6, None, None, None),
+            ]
+        );
+
+        let hashes = super::l1_block_hashes(&mut pool.acquire().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            hashes,
+            vec![
+                (100, H256::from_low_u64_be(100)),
+                (101, H256::from_low_u64_be(101)),
+            ]
+        );
+    }
+
+    #[sqlx::test]
+    async fn add_l1_block_hash_prunes_old_hashes(pool: PgPool) {
+        for block_number in 1..=10 {
+            super::add_l1_block_hash(&pool, block_number, H256::from_low_u64_be(block_number), 3)
+                .await
+                .unwrap();
+        }
+        super::add_l1_block_hash(&pool, 10, H256::from_low_u64_be(42), 3)
+            .await
+            .unwrap();
+
+        let hashes = super::l1_block_hashes(&mut pool.acquire().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            hashes,
+            vec![
+                (7, H256::from_low_u64_be(7)),
+                (8, H256::from_low_u64_be(8)),
+                (9, H256::from_low_u64_be(9)),
+                (10, H256::from_low_u64_be(42)),
+            ]
+        );
+    }
+
+    #[sqlx::test]
+    async fn withdrawals_to_finalize_respects_max_execute_l1_block(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 2, 100).await.unwrap();
+        super::committed_new_batch(&pool, 3, 4, 101).await.unwrap();
+        super::executed_new_batch(&pool, 1, 2, 110).await.unwrap();
+        super::executed_new_batch(&pool, 3, 4, 120).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=4).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=4)
+            .map(|b| withdrawal_params(b, b, b.div_ceil(2)))
+            .collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let ids = |w: Vec<WithdrawalParams>| w.into_iter().map(|w| w.id).collect::<Vec<_>>();
+
+        let all = super::WithdrawalsToFinalize::new(10)
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(all), vec![1, 2, 3, 4]);
+
+        let confirmed = super::WithdrawalsToFinalize::new(10)
+            .with_max_execute_l1_block(Some(119))
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(confirmed), vec![1, 2]);
+    }
+
+    #[sqlx::test]
+    async fn withdrawals_below_token_threshold_are_not_finalized(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 4, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 4, 110).await.unwrap();
+
+        let l1_token = Address::repeat_byte(0xa);
+        let l2_token = Address::repeat_byte(0xb);
+
+        super::add_token(
+            &pool,
+            &L2TokenInitEvent {
+                l1_token_address: l1_token,
+                l2_token_address: l2_token,
+                name: "USD Coin".to_string(),
+                symbol: "USDC".to_string(),
+                decimals: 6,
+                l2_block_number: 1,
+                initialization_transaction: H256::zero(),
+            },
+        )
+        .await
+        .unwrap();
+
+        // 5, 20 and 10 tokens and a withdrawal of another token.
+        let withdrawals: Vec<_> = [
+            (l2_token, 5_000_000),
+            (l2_token, 20_000_000),
+            (l2_token, 10_000_000),
+            (Address::repeat_byte(0xc), 1),
+        ]
+        .into_iter()
+        .zip(1..)
+        .map(|((token, amount), b)| {
+            let mut w = withdrawal(b);
+            w.event.token = token;
+            w.event.amount = amount.into();
+            w
+        })
+        .collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=4).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let ids = |w: Vec<WithdrawalParams>| w.into_iter().map(|w| w.id).collect::<Vec<_>>();
+
+        // Thresholds are looked up by either L1 or L2 address of the token.
+        for token in [l1_token, l2_token] {
+            let thresholds = [(token, 10.0)];
+
+            let profitable = super::WithdrawalsToFinalize::new(10)
+                .with_token_thresholds(&thresholds)
+                .fetch(&pool)
+                .await
+                .unwrap();
+            assert_eq!(ids(profitable), vec![2, 3, 4]);
+
+            let profitable = super::WithdrawalsToFinalize::new(10)
+                .with_whitelist(&[l2_token])
+                .with_token_thresholds(&thresholds)
+                .fetch(&pool)
+                .await
+                .unwrap();
+            assert_eq!(ids(profitable), vec![2, 3]);
+
+            assert_eq!(
+                super::unprofitable_withdrawals_count(&pool, None, &thresholds)
+                    .await
+                    .unwrap(),
+                1
+            );
+        }
+    }
+
+    #[sqlx::test]
+    async fn sent_transactions_journal(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 4, 100).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=4).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=4).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let account = Address::repeat_byte(1);
+        let to = Address::repeat_byte(2);
+
+        let first =
+            super::add_sent_transaction(&pool, account, 7.into(), to, &[1, 2], 100.into(), &[1, 2])
+                .await
+                .unwrap();
+        let second =
+            super::add_sent_transaction(&pool, account, 8.into(), to, &[3], 100.into(), &[3, 4])
+                .await
+                .unwrap();
+
+        // Nonces of the account can not be reused while a transaction may be mined with it.
+        assert!(
+            super::add_sent_transaction(&pool, account, 8.into(), to, &[3], 100.into(), &[3])
+                .await
+                .is_err()
+        );
+
+        let hashes: Vec<_> = (1..=2)
+            .map(|i| super::SentTransactionHash {
+                tx_hash: H256::from_low_u64_be(i),
+                max_fee_per_gas: (10 * i).into(),
+                max_priority_fee_per_gas: Some(i.into()),
+            })
+            .collect();
+
+        for hash in &hashes {
+            super::add_sent_transaction_hash(&pool, first, hash)
+                .await
+                .unwrap();
+        }
+
+        let pending = super::pending_sent_transactions(&pool, account)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            pending,
+            vec![
+                super::SentTransaction {
+                    id: first,
+                    nonce: 7.into(),
+                    to,
+                    calldata: vec![1, 2],
+                    gas_limit: 100.into(),
+                    withdrawal_ids: vec![1, 2],
+                    tx_hashes: hashes.clone(),
+                },
+                super::SentTransaction {
+                    id: second,
+                    nonce: 8.into(),
+                    to,
+                    calldata: vec![3],
+                    gas_limit: 100.into(),
+                    withdrawal_ids: vec![3, 4],
+                    tx_hashes: vec![],
+                },
+            ]
+        );
+
+        super::sent_transaction_mined(&pool, first, hashes[1].tx_hash, 1000.into())
+            .await
+            .unwrap();
+        super::sent_transaction_reverted(
+            &pool,
+            second,
+            H256::from_low_u64_be(3),
+            500.into(),
+            &[],
+            &Default::default(),
+        )
+        .await
+        .unwrap();
+
+        let finalization_data: Vec<(i64, Option<Vec<u8>>, i64)> = sqlx::query_as(
+            "
+            SELECT
+              withdrawal_id,
+              finalization_tx,
+              failed_finalization_attempts
+            FROM
+              finalization_data
+            ORDER BY
+              withdrawal_id
+            ",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+        let finalized_in = Some(hashes[1].tx_hash.as_bytes().to_vec());
+        assert_eq!(
+            finalization_data,
+            vec![
+                (1, finalized_in.clone(), 0),
+                (2, finalized_in, 0),
+                (3, None, 1),
+                (4, None, 1),
+            ]
+        );
+        assert_eq!(
+            super::pending_sent_transactions(&pool, account)
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+
+    #[sqlx::test]
+    async fn dropped_sent_transaction_frees_nonce(pool: PgPool) {
+        let account = Address::repeat_byte(1);
+        let to = Address::repeat_byte(2);
+
+        let id = super::add_sent_transaction(&pool, account, 0.into(), to, &[], 100.into(), &[])
+            .await
+            .unwrap();
+        super::sent_transaction_dropped(&pool, id).await.unwrap();
+
+        super::add_sent_transaction(&pool, account, 0.into(), to, &[], 100.into(), &[])
+            .await
+            .unwrap();
+    }
+
+    #[sqlx::test]
+    async fn fees_spent_include_pending_transactions(pool: PgPool) {
+        let account = Address::repeat_byte(1);
+        let to = Address::repeat_byte(2);
+        let hour = Duration::from_secs(3600);
+
+        let mined = super::add_sent_transaction(&pool, account, 0.into(), to, &[], 100.into(), &[])
+            .await
+            .unwrap();
+        let pending =
+            super::add_sent_transaction(&pool, account, 1.into(), to, &[], 100.into(), &[])
+                .await
+                .unwrap();
+
+        for (i, id) in [(1, mined), (2, pending), (3, pending)] {
+            let hash = super::SentTransactionHash {
+                tx_hash: H256::from_low_u64_be(i),
+                max_fee_per_gas: (10 * i).into(),
+                max_priority_fee_per_gas: Some(1.into()),
+            };
+            super::add_sent_transaction_hash(&pool, id, &hash)
+                .await
+                .unwrap();
+        }
+
+        super::sent_transaction_mined(&pool, mined, H256::from_low_u64_be(1), 700.into())
+            .await
+            .unwrap();
+
+        // The mined transaction and the last submission of the pending one.
+        assert_eq!(
+            super::fees_spent(&pool, account, hour).await.unwrap(),
+            (700 + 100 * 30).into()
+        );
+
+        sqlx::query("UPDATE sent_transactions SET updated_at = NOW() - INTERVAL '2 hours'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            super::fees_spent(&pool, account, hour).await.unwrap(),
+            (100 * 30).into()
+        );
+        assert_eq!(
+            super::fees_spent(&pool, Address::repeat_byte(3), hour)
+                .await
+                .unwrap(),
+            0.into()
+        );
+    }
+
+    #[sqlx::test]
+    async fn dry_run_batches_are_skipped_in_dry_run(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 4, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 4, 110).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=4).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=4).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        super::add_dry_run_batch(
+            &pool,
+            &super::DryRunBatch {
+                withdrawal_ids: vec![1, 3],
+                predicted_success: vec![true, false],
+                predicted_gas: vec![100.into(), 0.into()],
+                calldata_size: 1000,
+                estimated_gas: Some(200.into()),
+            },
+        )
+        .await
+        .unwrap();
+
+        let ids = |w: Vec<WithdrawalParams>| w.into_iter().map(|w| w.id).collect::<Vec<_>>();
+
+        let to_finalize = super::WithdrawalsToFinalize::new(10)
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(to_finalize), vec![1, 2, 3, 4]);
+
+        let to_dry_run = super::WithdrawalsToFinalize::new(10)
+            .skip_dry_run(true)
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(to_dry_run), vec![2, 4]);
+    }
+
+    #[sqlx::test]
+    async fn failed_withdrawals_are_retried_with_backoff_and_given_up(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 3, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 3, 110).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=3).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=3).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let no_backoff = super::RetryPolicy {
+            max_attempts: 2,
+            initial_backoff: Duration::ZERO,
+            jitter: 0.0,
+            ..Default::default()
+        };
+        let long_backoff = super::RetryPolicy {
+            max_attempts: 2,
+            jitter: 0.0,
+            ..Default::default()
+        };
+
+        super::inc_unsuccessful_finalization_attempts(&pool, &[failure(1, "a")], &no_backoff)
+            .await
+            .unwrap();
+        super::inc_unsuccessful_finalization_attempts(&pool, &[failure(2, "a")], &long_backoff)
+            .await
+            .unwrap();
+
+        let ids = |w: Vec<WithdrawalParams>| w.into_iter().map(|w| w.id).collect::<Vec<_>>();
+        let to_finalize = super::WithdrawalsToFinalize::new(10)
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(to_finalize), vec![1, 3]);
+        assert_eq!(super::given_up_withdrawals_count(&pool).await.unwrap(), 0);
+
+        super::inc_unsuccessful_finalization_attempts(&pool, &[failure(1, "b")], &no_backoff)
+            .await
+            .unwrap();
+
+        let to_finalize = super::WithdrawalsToFinalize::new(10)
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(to_finalize), vec![3]);
+        assert_eq!(super::given_up_withdrawals_count(&pool).await.unwrap(), 1);
+
+        let given_up: Vec<_> = super::given_up_withdrawals(&pool, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|w| (w.id, w.attempts, w.last_failure_reason))
+            .collect();
+        assert_eq!(given_up, vec![(1, 2, Some("b".to_string()))]);
+    }
+
+    async fn finalize(pool: &PgPool, withdrawals: &[WithdrawalParams]) {
+        let keys: Vec<_> = withdrawals
+            .iter()
+            .map(|w| client::WithdrawalKey {
+                tx_hash: w.tx_hash,
+                event_index_in_tx: w.event_index_in_tx,
+            })
+            .collect();
+
+        super::finalization_data_set_finalized_in_tx(pool, &keys, H256::zero())
+            .await
+            .unwrap();
+    }
+
+    // Drain the queue page by page returning blocks of withdrawals in the order they are finalized.
+    async fn finalization_order(pool: &PgPool, query: super::WithdrawalsToFinalize) -> Vec<u64> {
+        let mut order = vec![];
+
+        loop {
+            let page = query.fetch(pool).await.unwrap();
+            if page.is_empty() {
+                return order;
+            }
+            finalize(pool, &page).await;
+            order.extend(page.iter().map(|w| w.l2_block_number));
+        }
+    }
+
+    #[sqlx::test]
+    async fn old_withdrawals_are_not_starved_by_newer_ones(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 10, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 10, 110).await.unwrap();
+
+        // Ids are assigned in the reverse order of blocks.
+        let withdrawals: Vec<_> = (1..=4).rev().map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=4).map(|id| withdrawal_params(id, 5 - id, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let query = super::WithdrawalsToFinalize::new(2);
+        let page = query.fetch(&pool).await.unwrap();
+        assert_eq!(
+            page.iter().map(|w| w.l2_block_number).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        finalize(&pool, &page).await;
+
+        // Newer withdrawals keep coming but the older ones go first.
+        let withdrawals: Vec<_> = (5..=8).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (5..=8).map(|b| withdrawal_params(b, b, 2)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        assert_eq!(
+            finalization_order(&pool, query).await,
+            vec![3, 4, 5, 6, 7, 8]
+        );
+    }
+
+    #[sqlx::test]
+    async fn round_robin_does_not_starve_recipients(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 10, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 10, 110).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=6).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        // The first recipient withdraws in blocks 1 to 4, the others in blocks 5 and 6.
+        let params: Vec<_> = [1, 1, 1, 1, 2, 3]
+            .into_iter()
+            .zip(1..)
+            .map(|(recipient, b)| {
+                let mut message = vec![0; 4];
+                message.extend_from_slice(Address::repeat_byte(recipient).as_bytes());
+                message.extend_from_slice(&[0; 32]);
+
+                WithdrawalParams {
+                    message: message.into(),
+                    ..withdrawal_params(b, b, 1)
+                }
+            })
+            .collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let oldest_first = super::WithdrawalsToFinalize::new(3)
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            oldest_first
+                .iter()
+                .map(|w| w.l2_block_number)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        let round_robin =
+            super::WithdrawalsToFinalize::new(3).ordered_by(super::OrderingPolicy::RoundRobin);
+        assert_eq!(
+            finalization_order(&pool, round_robin).await,
+            vec![1, 5, 6, 2, 3, 4]
+        );
+    }
+
+    #[sqlx::test]
+    async fn largest_amounts_are_finalized_first(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 10, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 10, 110).await.unwrap();
+
+        let l2_token = Address::repeat_byte(0xb);
+        super::add_token(
+            &pool,
+            &L2TokenInitEvent {
+                l1_token_address: Address::repeat_byte(0xa),
+                l2_token_address: l2_token,
+                name: "USD Coin".to_string(),
+                symbol: "USDC".to_string(),
+                decimals: 6,
+                l2_block_number: 1,
+                initialization_transaction: H256::zero(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let eth: U256 = U256::exp10(18);
+
+        // 1 and 3 ETH, 2 tokens and another 1 ETH.
+        let withdrawals: Vec<_> = [
+            (Address::zero(), eth),
+            (Address::zero(), eth * 3),
+            (l2_token, U256::from(2_000_000)),
+            (Address::zero(), eth),
+        ]
+        .into_iter()
+        .zip(1..)
+        .map(|((token, amount), b)| {
+            let mut w = withdrawal(b);
+            w.event.token = token;
+            w.event.amount = amount;
+            w
+        })
+        .collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=4).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        assert!(super::set_withdrawal_priority(&pool, 4, 1).await.unwrap());
+
+        let largest_first = super::WithdrawalsToFinalize::new(1)
+            .ordered_by(super::OrderingPolicy::LargestAmountFirst);
+        assert_eq!(
+            finalization_order(&pool, largest_first).await,
+            vec![4, 2, 3, 1]
+        );
+    }
+
+    #[sqlx::test]
+    async fn leased_withdrawals_are_not_picked_by_other_instances(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 10, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 10, 110).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=6).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=6).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let lease = |holder: &str, secs| super::Lease {
+            holder: holder.to_string(),
+            duration: Duration::from_secs(secs),
+        };
+        let ids = |w: Vec<WithdrawalParams>| w.into_iter().map(|w| w.id).collect::<Vec<_>>();
+
+        let a = super::WithdrawalsToFinalize::new(2).with_lease(lease("a", 3600));
+        let b = super::WithdrawalsToFinalize::new(10).with_lease(lease("b", 0));
+
+        assert_eq!(ids(a.fetch(&pool).await.unwrap()), vec![1, 2]);
+        assert_eq!(ids(b.fetch(&pool).await.unwrap()), vec![3, 4, 5, 6]);
+
+        // Leases are renewed by their holders, expired leases are taken over.
+        assert_eq!(ids(a.fetch(&pool).await.unwrap()), vec![1, 2]);
+        assert_eq!(ids(a.fetch(&pool).await.unwrap()), vec![1, 2]);
+
+        let c = super::WithdrawalsToFinalize::new(10).with_lease(lease("c", 3600));
+        assert_eq!(ids(c.fetch(&pool).await.unwrap()), vec![3, 4, 5, 6]);
+        assert_eq!(ids(b.fetch(&pool).await.unwrap()), Vec::<u64>::new());
+
+        // Leases released on shutdown are taken over at once.
+        assert_eq!(
+            super::release_withdrawal_leases(&pool, "c").await.unwrap(),
+            4
+        );
+        assert_eq!(ids(b.fetch(&pool).await.unwrap()), vec![3, 4, 5, 6]);
+
+        // Leases of single withdrawals are only released by their holders.
+        assert_eq!(
+            super::release_leased_withdrawals(&pool, "b", &[1])
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            super::release_leased_withdrawals(&pool, "a", &[1])
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(ids(b.fetch(&pool).await.unwrap()), vec![1, 3, 4, 5, 6]);
+    }
+
+    #[sqlx::test]
+    async fn concurrent_instances_lease_disjoint_withdrawals(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 100, 100)
+            .await
+            .unwrap();
+        super::executed_new_batch(&pool, 1, 100, 110).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=100).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=100).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let queries: Vec<_> = (0..4)
+            .map(|i| {
+                super::WithdrawalsToFinalize::new(100).with_lease(super::Lease {
+                    holder: i.to_string(),
+                    duration: Duration::from_secs(3600),
+                })
+            })
+            .collect();
+
+        let leased = futures::future::join_all(queries.iter().map(|q| q.fetch(&pool))).await;
+
+        let mut ids: Vec<_> = leased
+            .into_iter()
+            .flat_map(|w| w.unwrap())
+            .map(|w| w.id)
+            .collect();
+        ids.sort();
+
+        assert_eq!(ids, (1..=100).collect::<Vec<_>>());
+    }
+
+    #[sqlx::test]
+    async fn leadership_passes_on_once_the_leader_is_gone(pool: PgPool) {
+        let mut leader = pool.acquire().await.unwrap().detach();
+        let mut standby = pool.acquire().await.unwrap().detach();
+
+        let mut other_group = pool.acquire().await.unwrap().detach();
+
+        assert!(super::try_acquire_leadership(&mut leader, "a")
+            .await
+            .unwrap());
+        assert!(!super::try_acquire_leadership(&mut standby, "a")
+            .await
+            .unwrap());
+        assert!(super::try_acquire_leadership(&mut other_group, "b")
+            .await
+            .unwrap());
+
+        super::ping(&mut leader).await.unwrap();
+        assert!(!super::try_acquire_leadership(&mut standby, "a")
+            .await
+            .unwrap());
+
+        sqlx::Connection::close(leader).await.unwrap();
+        assert!(super::try_acquire_leadership(&mut standby, "a")
+            .await
+            .unwrap());
+    }
+
+    #[sqlx::test]
+    async fn admin_overrides_of_withdrawals(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 4, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 4, 110).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=4).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=3).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let key = |block_number| client::WithdrawalKey {
+            tx_hash: H256::from_low_u64_be(block_number),
+            event_index_in_tx: 0,
+        };
+        assert_eq!(
+            super::withdrawal_id_by_key(&pool, key(3)).await.unwrap(),
+            Some((3, true))
+        );
+        assert_eq!(
+            super::withdrawal_id_by_key(&pool, key(4)).await.unwrap(),
+            Some((4, false))
+        );
+        assert_eq!(
+            super::withdrawal_id_by_key(&pool, key(5)).await.unwrap(),
+            None
+        );
+
+        assert!(super::set_withdrawal_priority(&pool, 3, 10).await.unwrap());
+        assert!(super::set_withdrawal_priority(&pool, 2, 5).await.unwrap());
+        assert!(!super::set_withdrawal_priority(&pool, 4, 5).await.unwrap());
+
+        let ids = |w: Vec<WithdrawalParams>| w.into_iter().map(|w| w.id).collect::<Vec<_>>();
+        let to_finalize = super::WithdrawalsToFinalize::new(10)
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(to_finalize), vec![3, 2, 1]);
+
+        let to_finalize = super::WithdrawalsToFinalize::new(1)
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(to_finalize), vec![3]);
+
+        let invalid_proof = super::FinalizationFailure {
+            class: super::FailureClass::InvalidProof,
+            ..failure(1, "nq")
+        };
+        super::inc_unsuccessful_finalization_attempts(
+            &pool,
+            &[invalid_proof],
+            &super::RetryPolicy::default(),
+        )
+        .await
+        .unwrap();
+        assert!(super::set_withdrawal_finalizable(&pool, 2, false)
+            .await
+            .unwrap());
+
+        let to_finalize = super::WithdrawalsToFinalize::new(10)
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(to_finalize), vec![3]);
+
+        assert!(super::reset_finalization_attempts(&pool, 1).await.unwrap());
+        assert!(super::set_withdrawal_finalizable(&pool, 2, true)
+            .await
+            .unwrap());
+
+        let to_finalize = super::WithdrawalsToFinalize::new(10)
+            .fetch(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(to_finalize), vec![3, 2, 1]);
+        assert_eq!(super::given_up_withdrawals_count(&pool).await.unwrap(), 0);
+    }
+
+    #[sqlx::test]
+    async fn withdrawals_with_invalid_proofs_are_given_up_at_once(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 2, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 2, 110).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=2).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=2).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let failures = [
+            super::FinalizationFailure {
+                withdrawal_id: 1,
+                class: super::FailureClass::InvalidProof,
+                reason: "nq".to_string(),
+                revert_data: Some(vec![1, 2, 3]),
+            },
+            super::FinalizationFailure {
+                withdrawal_id: 2,
+                class: super::FailureClass::TokenPaused,
+                reason: "Pausable: paused".to_string(),
+                revert_data: None,
+            },
+        ];
+
+        super::inc_unsuccessful_finalization_attempts(&pool, &failures, &Default::default())
+            .await
+            .unwrap();
+
+        let attempts: Vec<(i64, i64, String, Option<Vec<u8>>)> = sqlx::query_as(
+            "
+            SELECT
+              withdrawal_id,
+              attempt,
+              class,
+              revert_data
+            FROM
+              finalization_attempts
+            ORDER BY
+              withdrawal_id
+            ",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            attempts,
+            vec![
+                (1, 1, "invalid_proof".to_string(), Some(vec![1, 2, 3])),
+                (2, 1, "token_paused".to_string(), None),
+            ]
+        );
+
+        let given_up: Vec<_> = super::given_up_withdrawals(&pool, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|w| w.id)
+            .collect();
+        assert_eq!(given_up, vec![1]);
+    }
+
+    #[sqlx::test]
+    async fn raised_gas_limits_apply_to_tokens(pool: PgPool) {
+        let token = Address::repeat_byte(0xb);
+        let withdrawals: Vec<_> = (1..=3)
+            .map(|b| {
+                let mut w = withdrawal(b);
+                if b < 3 {
+                    w.event.token = token;
+                }
+                w
+            })
+            .collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=3).map(|b| withdrawal_params(b, b, 1)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        assert_eq!(
+            super::withdrawals_gas_limits(&pool, &[1, 2, 3])
+                .await
+                .unwrap(),
+            vec![]
+        );
+
+        super::raise_withdrawals_gas_limits(&pool, &[(1, 300.into())])
+            .await
+            .unwrap();
+        super::raise_withdrawals_gas_limits(&pool, &[(2, 200.into()), (3, 400.into())])
+            .await
+            .unwrap();
+
+        let mut gas_limits = super::withdrawals_gas_limits(&pool, &[1, 2, 3])
+            .await
+            .unwrap();
+        gas_limits.sort();
+
+        assert_eq!(
+            gas_limits,
+            vec![(1, 300.into()), (2, 300.into()), (3, 400.into())]
+        );
+    }
+
+    #[sqlx::test]
+    async fn withdrawals_gas_estimates_average_samples_by_token(pool: PgPool) {
+        let token = Address::repeat_byte(0xb);
+
+        let withdrawals: Vec<_> = (1..=4)
+            .map(|b| {
+                let mut w = withdrawal(b);
+                if b != 4 {
+                    w.event.token = token;
+                }
+                w
+            })
+            .collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        super::add_withdrawals_gas_samples(&pool, &[(1, 100.into()), (2, 200.into())], 2)
+            .await
+            .unwrap();
+
+        let mut estimates = super::withdrawals_gas_estimates(&pool, &[1, 3, 4])
+            .await
+            .unwrap();
+        estimates.sort();
+        assert_eq!(estimates, vec![(1, 150.into()), (3, 150.into())]);
+
+        // Only the last `max_samples` samples are approximately taken into account.
+        super::add_withdrawals_gas_samples(&pool, &[(3, 450.into())], 2)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            super::withdrawals_gas_estimates(&pool, &[3]).await.unwrap(),
+            vec![(3, 250.into())]
+        );
+
+        super::add_withdrawals_gas_samples(&pool, &[(3, 150.into())], 2)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            super::withdrawals_gas_estimates(&pool, &[3]).await.unwrap(),
+            vec![(3, 217.into())]
+        );
+    }
+
+    #[sqlx::test]
+    async fn withdrawals_for_address_are_paged_and_filtered(pool: PgPool) {
+        let recipient = Address::from_low_u64_be(42);
+        let (token_a, token_b) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+
+        let withdrawals: Vec<_> = (1..=5).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=5).map(|b| withdrawal_params(b, b, b)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let events: Vec<_> = (1..=5)
+            .map(|b| client::zksync_contract::L2ToL1Event {
+                token: if b % 2 == 0 { token_a } else { token_b },
+                to: recipient,
+                amount: U256::one(),
+                l1_block_number: 100,
+                l2_block_number: b,
+                tx_number_in_block: 0,
+            })
+            .collect();
+        super::l2_to_l1_events(&pool, &events).await.unwrap();
+
+        super::executed_new_batch(&pool, 1, 5, 100).await.unwrap();
+        finalize(&pool, &params[..1]).await;
+
+        let ids = |filter: super::UserWithdrawalsFilter, limit| {
+            let pool = pool.clone();
+            async move {
+                super::withdrawals_for_address(&pool, recipient, &filter, limit)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|w| w.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        let before = |id| super::UserWithdrawalsFilter {
+            before_id: Some(id),
+            ..Default::default()
+        };
+
+        assert_eq!(ids(Default::default(), 2).await, vec![5, 4]);
+        assert_eq!(ids(before(4), 2).await, vec![3, 2]);
+        assert_eq!(ids(before(2), 2).await, vec![1]);
+
+        let of_token_a = super::UserWithdrawalsFilter {
+            token: Some(token_a),
+            ..Default::default()
+        };
+        assert_eq!(ids(of_token_a, 10).await, vec![4, 2]);
+
+        let finalized = super::UserWithdrawalsFilter {
+            status: Some(super::FinalizationStatus::FinalizedElsewhere),
+            ..Default::default()
+        };
+        assert_eq!(ids(finalized, 10).await, vec![1]);
+
+        let not_finalized = super::UserWithdrawalsFilter {
+            before_id: Some(5),
+            status: Some(super::FinalizationStatus::ParamsFetched),
+            ..Default::default()
+        };
+        assert_eq!(ids(not_finalized, 10).await, vec![4, 3, 2]);
+    }
+
+    #[sqlx::test]
+    async fn withdrawal_details_follow_its_lifecycle(pool: PgPool) {
+        use super::FinalizationStatus::*;
+
+        let status = || async { super::withdrawal_status(&pool, 1).await.unwrap().unwrap() };
+
+        super::add_withdrawals(&pool, &[withdrawal(5)])
+            .await
+            .unwrap();
+        assert_eq!(status().await, PendingOnL2);
+
+        super::committed_new_batch(&pool, 1, 10, 100).await.unwrap();
+        assert_eq!(status().await, Committed);
+
+        super::verified_new_batch(&pool, 1, 10, 105).await.unwrap();
+
+        let key = client::WithdrawalKey {
+            tx_hash: H256::from_low_u64_be(5),
+            event_index_in_tx: 0,
+        };
+
+        let details = super::withdrawal_details(&pool, key)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            details,
+            super::WithdrawalDetails {
+                id: 1,
+                tx_hash: H256::from_low_u64_be(5),
+                event_index_in_tx: 0,
+                token: Address::zero(),
+                amount: U256::one(),
+                l2_block_number: 5,
+                l1_batch_number: None,
+                commit_l1_block_number: Some(100),
+                verify_l1_block_number: Some(105),
+                execute_l1_block_number: None,
+                finalization_tx: None,
+                failed_finalization_attempts: 0,
+                last_failure_reason: None,
+                finalizable: true,
+                status: Verified,
+                seen_at: details.seen_at,
+            }
+        );
+        assert!(details.seen_at.is_some());
+
+        super::executed_new_batch(&pool, 1, 10, 110).await.unwrap();
+        assert_eq!(status().await, Executed);
+
+        let params = withdrawal_params(1, 5, 3);
+        super::add_withdrawals_data(&pool, std::slice::from_ref(&params))
+            .await
+            .unwrap();
+        assert_eq!(status().await, ParamsFetched);
+
+        let sent = super::add_sent_transaction(
+            &pool,
+            Address::zero(),
+            U256::zero(),
+            Address::zero(),
+            &[],
+            U256::one(),
+            &[1],
+        )
+        .await
+        .unwrap();
+        assert_eq!(status().await, Finalizing);
+
+        super::sent_transaction_dropped(&pool, sent).await.unwrap();
+        assert_eq!(status().await, ParamsFetched);
+
+        let give_up_at_once = super::RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        super::inc_unsuccessful_finalization_attempts(
+            &pool,
+            &[failure(1, "out of gas")],
+            &give_up_at_once,
+        )
+        .await
+        .unwrap();
+        assert_eq!(status().await, GivenUp);
+
+        super::set_withdrawal_finalizable(&pool, 1, false)
+            .await
+            .unwrap();
+        assert_eq!(status().await, Unfinalizable);
+
+        assert_eq!(
+            super::unfinalized_withdrawals_count_by_status(&pool)
+                .await
+                .unwrap(),
+            vec![(Unfinalizable, 1)]
+        );
+
+        finalize(&pool, std::slice::from_ref(&params)).await;
+
+        let details = super::withdrawal_details(&pool, key)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(details.l1_batch_number, Some(3));
+        assert_eq!(details.execute_l1_block_number, Some(110));
+        assert_eq!(details.failed_finalization_attempts, 1);
+        assert_eq!(details.last_failure_reason.as_deref(), Some("out of gas"));
+        assert_eq!(details.finalization_tx, Some(H256::zero()));
+        assert_eq!(details.status, FinalizedElsewhere);
+
+        super::finalization_data_set_finalized_in_tx(&pool, &[key], H256::from_low_u64_be(1))
+            .await
+            .unwrap();
+        assert_eq!(status().await, Finalized);
+
+        assert_eq!(
+            super::unfinalized_withdrawals_count_by_status(&pool)
+                .await
+                .unwrap(),
+            vec![]
+        );
+
+        let unknown = client::WithdrawalKey {
+            tx_hash: H256::from_low_u64_be(6),
+            event_index_in_tx: 0,
+        };
+        assert_eq!(
+            super::withdrawal_details(&pool, unknown).await.unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn finalization_statuses_are_parsed_from_their_names() {
+        for status in super::FinalizationStatus::ALL {
+            assert_eq!(
+                status
+                    .as_str()
+                    .parse::<super::FinalizationStatus>()
+                    .unwrap(),
+                status
+            );
+        }
+
+        assert!("not_finalized"
+            .parse::<super::FinalizationStatus>()
+            .is_err());
+    }
+
+    #[sqlx::test]
+    async fn finalization_latencies_are_measured_from_withdrawals_being_seen(pool: PgPool) {
+        let withdrawals: Vec<_> = (1..=5).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        let params: Vec<_> = (1..=4).map(|b| withdrawal_params(b, b, b)).collect();
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        super::committed_new_batch(&pool, 1, 4, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 3, 130).await.unwrap();
+
+        sqlx::query("UPDATE withdrawals SET created_at = NOW() - id * INTERVAL '10 minutes'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let keys: Vec<_> = params
+            .iter()
+            .map(|w| client::WithdrawalKey {
+                tx_hash: w.tx_hash,
+                event_index_in_tx: w.event_index_in_tx,
+            })
+            .collect();
+        super::finalization_data_set_finalized_in_tx(&pool, &keys[..3], H256::from_low_u64_be(1))
+            .await
+            .unwrap();
+        // Withdrawals finalized by someone else are not counted.
+        finalize(&pool, &params[3..]).await;
+
+        let window = Duration::from_secs(3600);
+        let near = |d: Duration, secs: u64| d.as_secs().abs_diff(secs) < 10;
+
+        let latencies = super::finalization_latencies(&pool, window).await.unwrap();
+
+        assert_eq!(latencies.len(), 1);
+        assert_eq!(latencies[0].token, Address::zero());
+        assert_eq!(latencies[0].finalized, 3);
+        assert!(near(latencies[0].p50, 1200), "{latencies:?}");
+        assert!(near(latencies[0].p90, 1680), "{latencies:?}");
+
+        let model = super::eta_model(&pool, window).await.unwrap();
+
+        assert_eq!(model.l1_block, Some(130));
+        assert_eq!(model.commit_to_execute_blocks, Some(30));
+        assert_eq!(model.queue_depth, 0);
+        assert_eq!(model.finalized_last_hour, 4);
+        assert!(near(model.seen_to_finalized.unwrap(), 1200), "{model:?}");
+    }
+
+    #[test]
+    fn eta_is_estimated_by_the_progress_of_withdrawals() {
+        use super::FinalizationStatus::*;
+
+        let model = super::EtaModel {
+            l1_block: Some(130),
+            commit_to_execute_blocks: Some(30),
+            seen_to_finalized: Some(Duration::from_secs(1200)),
+            queue_depth: 10,
+            finalized_last_hour: 20,
+        };
+        let eta = |status, commit, seen_at| {
+            model
+                .eta(status, commit, seen_at, 1000)
+                .map(|eta| eta.as_secs())
+        };
+
+        assert_eq!(eta(Finalized, Some(100), Some(0)), None);
+        assert_eq!(eta(GivenUp, Some(100), Some(0)), None);
+        assert_eq!(eta(Finalizing, Some(100), Some(0)), Some(0));
+        assert_eq!(eta(ParamsFetched, Some(100), Some(0)), Some(1800));
+        assert_eq!(eta(Committed, Some(110), Some(900)), Some(120 + 1800));
+        assert_eq!(eta(Verified, Some(90), Some(900)), Some(1800));
+        assert_eq!(eta(PendingOnL2, None, Some(900)), Some(360 + 1800));
+
+        let empty_queue = super::EtaModel {
+            queue_depth: 0,
+            finalized_last_hour: 0,
+            ..model.clone()
+        };
+        assert_eq!(
+            empty_queue.eta(PendingOnL2, None, Some(900), 1000),
+            Some(Duration::from_secs(1100))
+        );
+
+        let stalled = super::EtaModel {
+            finalized_last_hour: 0,
+            ..model
+        };
+        assert_eq!(stalled.eta(Executed, Some(100), Some(0), 1000), None);
+    }
+
+    #[sqlx::test]
+    async fn changes_of_withdrawals_are_notified(pool: PgPool) {
+        let mut listener = sqlx::postgres::PgListener::connect_with(&pool)
+            .await
+            .unwrap();
+        listener.listen(super::WITHDRAWALS_CHANNEL).await.unwrap();
+
+        super::add_withdrawals(&pool, &[withdrawal(5)])
+            .await
+            .unwrap();
+        super::committed_new_batch(&pool, 1, 10, 100).await.unwrap();
+        super::add_withdrawals_data(&pool, &[withdrawal_params(1, 5, 1)])
+            .await
+            .unwrap();
+
+        super::revert_batches(&pool, 10, 10, 10, 1).await.unwrap();
+        for finalizable in [false, false, true] {
+            super::set_withdrawal_finalizable(&pool, 1, finalizable)
+                .await
+                .unwrap();
+        }
+
+        let mut changes = vec![];
+        for _ in 0..6 {
+            let payload = listener.recv().await.unwrap().payload().to_string();
+            changes.push(super::WithdrawalsChange::from_payload(&payload));
+        }
+        assert_eq!(
+            changes,
+            vec![
+                super::WithdrawalsChange {
+                    tx_hashes: vec![H256::from_low_u64_be(5)],
+                    ..super::WithdrawalsChange::of("seen", &[1])
+                },
+                super::WithdrawalsChange::of("committed", &[1]),
+                super::WithdrawalsChange {
+                    addresses: vec![Address::zero()],
+                    ..super::WithdrawalsChange::of("params_fetched", &[1])
+                },
+                super::WithdrawalsChange::any("reverted"),
+                super::WithdrawalsChange::of("unfinalizable", &[1]),
+                super::WithdrawalsChange::of("finalizable", &[1]),
+            ]
+        );
+
+        // Unknown payloads are taken as changes of any withdrawal.
+        assert_eq!(
+            super::WithdrawalsChange::from_payload("seen"),
+            super::WithdrawalsChange::any("seen")
+        );
+
+        let in_tx = super::withdrawals_details_in_tx(&pool, H256::from_low_u64_be(5))
+            .await
+            .unwrap();
+        assert_eq!(in_tx.len(), 1);
+        assert_eq!(in_tx[0].status, super::FinalizationStatus::Committed);
+    }
+
+    #[sqlx::test]
+    async fn changes_of_many_withdrawals_are_split_into_notifications(pool: PgPool) {
+        let mut listener = sqlx::postgres::PgListener::connect_with(&pool)
+            .await
+            .unwrap();
+        listener.listen(super::WITHDRAWALS_CHANNEL).await.unwrap();
+
+        let ids: Vec<i64> = (1..=3000).collect();
+        super::changes::notify_withdrawals_changed(
+            &pool,
+            &super::WithdrawalsChange::of("committed", &ids),
+        )
+        .await
+        .unwrap();
+
+        let mut notified = vec![];
+        let mut notifications = 0;
+        while notified.len() < ids.len() {
+            let payload = listener.recv().await.unwrap().payload().to_string();
+            let change = super::WithdrawalsChange::from_payload(&payload);
+
+            assert_eq!(change.change, "committed");
+            notified.extend(change.ids.unwrap());
+            notifications += 1;
+        }
+
+        assert!(notifications > 1);
+        assert_eq!(notified, (1..=3000).collect::<Vec<u64>>());
+    }
+
+    #[sqlx::test]
+    async fn webhook_deliveries_are_enqueued_for_matching_withdrawals(pool: PgPool) {
+        super::add_withdrawals(&pool, &[withdrawal(5), withdrawal(6), withdrawal(7)])
+            .await
+            .unwrap();
+        super::committed_new_batch(&pool, 1, 10, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 10, 110).await.unwrap();
+
+        let sender = Address::from_low_u64_be(1);
+        let params = [
+            withdrawal_params(1, 5, 1),
+            WithdrawalParams {
+                sender,
+                ..withdrawal_params(2, 6, 1)
+            },
+        ];
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+
+        let any = super::add_webhook(&pool, "http://a", "a", None, None)
+            .await
+            .unwrap();
+        let by_sender = super::add_webhook(&pool, "http://b", "b", Some(sender), None)
+            .await
+            .unwrap();
+        super::add_webhook(&pool, "http://c", "c", None, Some(Address::repeat_byte(1)))
+            .await
+            .unwrap();
+
+        // Finalizing twice does not deliver the event twice.
+        let keys = [client::WithdrawalKey {
+            tx_hash: params[0].tx_hash,
+            event_index_in_tx: params[0].event_index_in_tx,
+        }];
+        for _ in 0..2 {
+            super::finalization_data_set_finalized_in_tx(&pool, &keys, H256::repeat_byte(1))
+                .await
+                .unwrap();
+        }
+        // Withdrawals found finalized by someone else are told apart.
+        finalize(&pool, &params[1..]).await;
+        super::set_withdrawal_unfinalizable(&pool, H256::from_low_u64_be(7), 0)
+            .await
+            .unwrap();
+
+        let due = super::due_webhook_deliveries(&pool, 10).await.unwrap();
+        let mut due: Vec<_> = due
+            .iter()
+            .map(|d| {
+                (
+                    d.webhook_id,
+                    d.withdrawal.id,
+                    d.event,
+                    d.withdrawal.finalization_tx,
+                )
+            })
+            .collect();
+        due.sort_by_key(|&(webhook_id, withdrawal_id, _, _)| (withdrawal_id, webhook_id));
+        assert_eq!(
+            due,
+            vec![
+                (
+                    any,
+                    1,
+                    super::WebhookEvent::Finalized,
+                    Some(H256::repeat_byte(1))
+                ),
+                (any, 2, super::WebhookEvent::FinalizedElsewhere, None),
+                (by_sender, 2, super::WebhookEvent::FinalizedElsewhere, None),
+                (any, 3, super::WebhookEvent::Unfinalizable, None),
+            ]
+        );
+    }
+
+    #[sqlx::test]
+    async fn webhook_deliveries_are_enqueued_for_given_up_withdrawals(pool: PgPool) {
+        super::add_withdrawals(&pool, &[withdrawal(5), withdrawal(6)])
+            .await
+            .unwrap();
+        super::committed_new_batch(&pool, 1, 10, 100).await.unwrap();
+        super::executed_new_batch(&pool, 1, 10, 110).await.unwrap();
+        super::add_withdrawals_data(
+            &pool,
+            &[withdrawal_params(1, 5, 1), withdrawal_params(2, 6, 1)],
+        )
+        .await
+        .unwrap();
+        super::add_webhook(&pool, "http://a", "a", None, None)
+            .await
+            .unwrap();
+
+        let retry_policy = super::RetryPolicy {
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let failures = [failure(1, "a"), failure(2, "a")];
+
+        // Only the attempt that gives up on a withdrawal delivers the event.
+        super::inc_unsuccessful_finalization_attempts(&pool, &failures, &retry_policy)
+            .await
+            .unwrap();
+        assert!(super::due_webhook_deliveries(&pool, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        super::inc_unsuccessful_finalization_attempts(&pool, &failures[..1], &retry_policy)
+            .await
+            .unwrap();
+
+        let due: Vec<_> = super::due_webhook_deliveries(&pool, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.withdrawal.id, d.event))
+            .collect();
+        assert_eq!(due, vec![(1, super::WebhookEvent::GivenUp)]);
+    }
+
+    #[sqlx::test]
+    async fn only_unfinalized_withdrawals_are_marked_unfinalizable(pool: PgPool) {
+        super::add_withdrawals(&pool, &[withdrawal(5), withdrawal(6)])
+            .await
+            .unwrap();
+        let params = [withdrawal_params(1, 5, 1), withdrawal_params(2, 6, 1)];
+        super::add_withdrawals_data(&pool, &params).await.unwrap();
+        super::add_webhook(&pool, "http://a", "a", None, None)
+            .await
+            .unwrap();
+        finalize(&pool, &params[1..]).await;
+
+        assert!(super::set_withdrawal_finalizable(&pool, 1, false)
+            .await
+            .unwrap());
+        assert!(!super::set_withdrawal_finalizable(&pool, 1, false)
+            .await
+            .unwrap());
+        assert!(!super::set_withdrawal_finalizable(&pool, 2, false)
+            .await
+            .unwrap());
+        assert!(!super::set_withdrawal_finalizable(&pool, 3, false)
+            .await
+            .unwrap());
+        super::set_withdrawal_unfinalizable(&pool, H256::from_low_u64_be(5), 0)
+            .await
+            .unwrap();
+
+        // The webhook is only notified once the withdrawal becomes unfinalizable.
+        let unfinalizable: Vec<_> = super::due_webhook_deliveries(&pool, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|d| d.event == super::WebhookEvent::Unfinalizable)
+            .map(|d| d.withdrawal.id)
+            .collect();
+        assert_eq!(unfinalizable, vec![1]);
+
+        assert!(super::set_withdrawal_finalizable(&pool, 1, true)
+            .await
+            .unwrap());
+        assert!(!super::set_withdrawal_finalizable(&pool, 1, true)
+            .await
+            .unwrap());
+    }
+
+    #[sqlx::test]
+    async fn failed_webhook_deliveries_are_dead_lettered(pool: PgPool) {
+        super::add_withdrawals(&pool, &[withdrawal(5), withdrawal(6)])
+            .await
+            .unwrap();
+        super::add_webhook(&pool, "http://a", "a", None, None)
+            .await
+            .unwrap();
+        super::set_withdrawal_unfinalizable(&pool, H256::from_low_u64_be(5), 0)
+            .await
+            .unwrap();
+        super::set_withdrawal_finalizable(&pool, 2, false)
+            .await
+            .unwrap();
+
+        let due = super::due_webhook_deliveries(&pool, 10).await.unwrap();
+        assert_eq!(due.len(), 2);
+
+        let retry_policy = super::RetryPolicy {
+            max_attempts: 2,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            ..Default::default()
+        };
+
+        super::webhook_delivered(&pool, due[0].id).await.unwrap();
+        let dead = super::webhook_delivery_failed(&pool, due[1].id, "a", &retry_policy)
+            .await
+            .unwrap();
+        assert!(!dead);
+
+        let due = super::due_webhook_deliveries(&pool, 10).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].attempts, 1);
+        assert_eq!(due[0].last_error.as_deref(), Some("a"));
+
+        let dead = super::webhook_delivery_failed(&pool, due[0].id, "b", &retry_policy)
+            .await
+            .unwrap();
+        assert!(dead);
+        assert!(super::due_webhook_deliveries(&pool, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let dead = super::dead_webhook_deliveries(&pool, 10).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].withdrawal.id, 2);
+        assert_eq!(dead[0].last_error.as_deref(), Some("b"));
+
+        assert!(super::retry_dead_webhook_delivery(&pool, dead[0].id)
+            .await
+            .unwrap());
+        assert!(!super::retry_dead_webhook_delivery(&pool, dead[0].id)
+            .await
+            .unwrap());
+
+        let due = super::due_webhook_deliveries(&pool, 10).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].attempts, 0);
+    }
+
+    #[sqlx::test]
+    async fn enqueued_withdrawal_does_not_hide_earlier_ones(pool: PgPool) {
+        super::committed_new_batch(&pool, 1, 5, 100).await.unwrap();
+
+        let withdrawals: Vec<_> = (1..=5).map(withdrawal).collect();
+        super::add_withdrawals(&pool, &withdrawals).await.unwrap();
+
+        super::add_withdrawals_data(&pool, &[withdrawal_params(1, 1, 1)])
+            .await
+            .unwrap();
+
+        // Withdrawal 4 is enqueued by hand ahead of withdrawals 2 and 3.
+        super::set_withdrawal_unfinalizable(&pool, H256::from_low_u64_be(4), 0)
+            .await
+            .unwrap();
+        super::add_withdrawals_data(&pool, &[withdrawal_params(4, 4, 1)])
+            .await
+            .unwrap();
+        assert!(super::enqueue_withdrawal(&pool, 4, 7).await.unwrap());
+
+        let no_data: Vec<_> = super::get_withdrawals_with_no_data(&pool, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|w| w.id)
+            .collect();
+        assert_eq!(no_data, vec![2, 3, 5]);
+
+        let (finalizable, priority): (bool, i64) = sqlx::query_as(
+            "SELECT finalizable, priority FROM withdrawals \
+             JOIN finalization_data ON withdrawal_id = id WHERE id = 4",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!((finalizable, priority), (true, 7));
+
+        // Finalized withdrawals are left untouched.
+        finalize(&pool, &[withdrawal_params(4, 4, 1)]).await;
+        assert!(!super::enqueue_withdrawal(&pool, 4, 9).await.unwrap());
+        assert!(!super::enqueue_withdrawal(&pool, 2, 9).await.unwrap());
+    }
 }
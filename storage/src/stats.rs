@@ -0,0 +1,240 @@
+//! Statistics of how long withdrawals take to get finalized.
+
+use std::time::Duration;
+
+use ethers::types::{Address, H256};
+use sqlx::PgPool;
+
+use crate::{metrics::STORAGE_METRICS, FinalizationStatus, Result};
+
+/// Time between L1 blocks.
+pub const L1_BLOCK_TIME: Duration = Duration::from_secs(12);
+
+/// Historic delays withdrawals are estimated to be finalized with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EtaModel {
+    /// The last L1 block seen.
+    pub l1_block: Option<u64>,
+    /// Median number of L1 blocks between a commit and an execution of a block.
+    pub commit_to_execute_blocks: Option<u64>,
+    /// Median time between a withdrawal is seen and finalized.
+    pub seen_to_finalized: Option<Duration>,
+    /// Number of executed withdrawals waiting to be finalized.
+    pub queue_depth: u64,
+    /// Number of withdrawals finalized within the last hour.
+    pub finalized_last_hour: u64,
+}
+
+impl EtaModel {
+    /// Estimate the time left until a withdrawal is finalized.
+    ///
+    /// Returns `None` for withdrawals that are finalized or are never going to be
+    /// and if there is not enough history to estimate with.
+    ///
+    /// # Arguments
+    ///
+    /// * `commit_l1_block_number`: L1 block the L2 block of the withdrawal was committed in.
+    /// * `seen_at`: When the withdrawal was seen, in seconds since the epoch.
+    /// * `now`: Current time in seconds since the epoch.
+    pub fn eta(
+        &self,
+        status: FinalizationStatus,
+        commit_l1_block_number: Option<u64>,
+        seen_at: Option<u64>,
+        now: u64,
+    ) -> Option<Duration> {
+        use FinalizationStatus::*;
+
+        match status {
+            Finalized | FinalizedElsewhere | Unfinalizable | GivenUp => None,
+            Finalizing => Some(Duration::ZERO),
+            Executed | ParamsFetched => self.queue_delay(),
+            Committed | Verified => {
+                let blocks_since_commit = self.l1_block?.saturating_sub(commit_l1_block_number?);
+                let execution = self
+                    .commit_to_execute_blocks?
+                    .saturating_sub(blocks_since_commit);
+
+                Some(L1_BLOCK_TIME * execution as u32 + self.queue_delay()?)
+            }
+            PendingOnL2 => {
+                let after_commit =
+                    L1_BLOCK_TIME * self.commit_to_execute_blocks? as u32 + self.queue_delay()?;
+
+                // Commit delays are not known, take these of the finalized withdrawals.
+                let age = Duration::from_secs(now.saturating_sub(seen_at?));
+                let total = self.seen_to_finalized?.saturating_sub(age);
+
+                Some(total.max(after_commit))
+            }
+        }
+    }
+
+    // Time to finalize the withdrawals already in the queue at the recent rate.
+    fn queue_delay(&self) -> Option<Duration> {
+        if self.queue_depth == 0 {
+            return Some(Duration::ZERO);
+        }
+
+        if self.finalized_last_hour == 0 {
+            return None;
+        }
+
+        Some(Duration::from_secs(
+            self.queue_depth * 3600 / self.finalized_last_hour,
+        ))
+    }
+}
+
+/// Request the delays of withdrawals finalized within the `window`.
+pub async fn eta_model(pool: &PgPool, window: Duration) -> Result<EtaModel> {
+    let latency = STORAGE_METRICS.call[&"eta_model"].start();
+
+    // Withdrawals found finalized by someone else are marked with a zero hash.
+    let zero = H256::zero();
+
+    let r = sqlx::query!(
+        "
+        SELECT
+          (
+            SELECT
+              GREATEST(MAX(commit_l1_block_number), MAX(execute_l1_block_number))
+            FROM
+              l2_blocks
+          ) AS l1_block,
+          (
+            SELECT
+              PERCENTILE_CONT(0.5) WITHIN GROUP (
+                ORDER BY
+                  execute_l1_block_number - commit_l1_block_number
+              )
+            FROM
+              l2_blocks
+            WHERE
+              execute_l1_block_number IS NOT NULL
+              AND commit_l1_block_number IS NOT NULL
+              AND execute_l1_block_number > (
+                SELECT
+                  MAX(execute_l1_block_number)
+                FROM
+                  l2_blocks
+              ) - $1
+          ) AS commit_to_execute_blocks,
+          (
+            SELECT
+              PERCENTILE_CONT(0.5) WITHIN GROUP (
+                ORDER BY
+                  EXTRACT(EPOCH FROM finalization_data.finalized_at - withdrawals.created_at) :: FLOAT8
+              )
+            FROM
+              finalization_data
+              JOIN withdrawals ON withdrawals.id = finalization_data.withdrawal_id
+            WHERE
+              finalization_data.finalized_at > NOW() - MAKE_INTERVAL(secs => $2)
+              AND withdrawals.created_at IS NOT NULL
+              AND finalization_data.finalization_tx <> $3
+          ) AS seen_to_finalized,
+          (
+            SELECT
+              COUNT(*)
+            FROM
+              withdrawal_statuses
+            WHERE
+              status IN ('executed', 'params_fetched')
+          ) AS \"queue_depth!\",
+          (
+            SELECT
+              COUNT(*)
+            FROM
+              finalization_data
+            WHERE
+              finalized_at > NOW() - INTERVAL '1 hour'
+          ) AS \"finalized_last_hour!\"
+        ",
+        (window.as_secs() / L1_BLOCK_TIME.as_secs()) as i64,
+        window.as_secs_f64(),
+        zero.as_bytes(),
+    )
+    .fetch_one(pool)
+    .await?;
+
+    latency.observe();
+
+    Ok(EtaModel {
+        l1_block: r.l1_block.map(|b| b as u64),
+        commit_to_execute_blocks: r.commit_to_execute_blocks.map(|b| b.round() as u64),
+        seen_to_finalized: r
+            .seen_to_finalized
+            .map(|secs| Duration::from_secs_f64(secs.max(0.0))),
+        queue_depth: r.queue_depth as u64,
+        finalized_last_hour: r.finalized_last_hour as u64,
+    })
+}
+
+/// Percentiles of the time withdrawals of a token take from being seen to being finalized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenLatency {
+    /// L2 token address.
+    pub token: Address,
+    /// Number of withdrawals finalized.
+    pub finalized: u64,
+    /// Median latency.
+    pub p50: Duration,
+    /// 90th percentile of latency.
+    pub p90: Duration,
+    /// 99th percentile of latency.
+    pub p99: Duration,
+}
+
+/// Request latencies of withdrawals finalized within the `window` by token.
+///
+/// Withdrawals found finalized by someone else are not counted.
+pub async fn finalization_latencies(pool: &PgPool, window: Duration) -> Result<Vec<TokenLatency>> {
+    let latency = STORAGE_METRICS.call[&"finalization_latencies"].start();
+
+    let zero = H256::zero();
+
+    let latencies = sqlx::query!(
+        "
+        SELECT
+          withdrawals.token,
+          COUNT(*) AS \"finalized!\",
+          PERCENTILE_CONT(ARRAY [0.5, 0.9, 0.99]) WITHIN GROUP (
+            ORDER BY
+              EXTRACT(EPOCH FROM finalization_data.finalized_at - withdrawals.created_at) :: FLOAT8
+          ) AS \"percentiles!\"
+        FROM
+          finalization_data
+          JOIN withdrawals ON withdrawals.id = finalization_data.withdrawal_id
+        WHERE
+          finalization_data.finalized_at > NOW() - MAKE_INTERVAL(secs => $1)
+          AND withdrawals.created_at IS NOT NULL
+          AND finalization_data.finalization_tx <> $2
+        GROUP BY
+          withdrawals.token
+        ORDER BY
+          withdrawals.token
+        ",
+        window.as_secs_f64(),
+        zero.as_bytes(),
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| {
+        let percentile = |i: usize| Duration::from_secs_f64(r.percentiles[i].max(0.0));
+
+        TokenLatency {
+            token: Address::from_slice(&r.token),
+            finalized: r.finalized as u64,
+            p50: percentile(0),
+            p90: percentile(1),
+            p99: percentile(2),
+        }
+    })
+    .collect();
+
+    latency.observe();
+
+    Ok(latencies)
+}
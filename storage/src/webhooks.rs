@@ -0,0 +1,468 @@
+//! Registrations of webhooks and the outbox of their deliveries.
+
+use std::str::FromStr;
+
+use ethers::types::{Address, H256, U256};
+use sqlx::{PgConnection, PgPool};
+
+use crate::{metrics::STORAGE_METRICS, utils, Error, Result, RetryPolicy};
+
+/// An event of a withdrawal webhooks are delivered on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEvent {
+    /// The withdrawal has been finalized.
+    Finalized,
+    /// The withdrawal has been found finalized by someone else.
+    FinalizedElsewhere,
+    /// The withdrawal has been marked as never to be finalized.
+    Unfinalizable,
+    /// Finalization of the withdrawal has been given up on after failed attempts.
+    GivenUp,
+}
+
+impl WebhookEvent {
+    /// Name of the event as stored in the `webhook_outbox` table.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Finalized => "finalized",
+            Self::FinalizedElsewhere => "finalized_elsewhere",
+            Self::Unfinalizable => "unfinalizable",
+            Self::GivenUp => "given_up",
+        }
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "finalized" => Ok(Self::Finalized),
+            "finalized_elsewhere" => Ok(Self::FinalizedElsewhere),
+            "unfinalizable" => Ok(Self::Unfinalizable),
+            "given_up" => Ok(Self::GivenUp),
+            s => Err(Error::UnknownWebhookEvent(s.to_string())),
+        }
+    }
+}
+
+/// A registration of a webhook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    /// Webhook id.
+    pub id: u64,
+    /// URL the events are posted to.
+    pub url: String,
+    /// Secret the deliveries are signed with.
+    pub secret: String,
+    /// Only withdrawals to or from this L1 address if set.
+    pub address: Option<Address>,
+    /// Only withdrawals of this L1 or L2 token if set.
+    pub token: Option<Address>,
+}
+
+/// Register a webhook, returns its id.
+pub async fn add_webhook(
+    pool: &PgPool,
+    url: &str,
+    secret: &str,
+    address: Option<Address>,
+    token: Option<Address>,
+) -> Result<u64> {
+    let latency = STORAGE_METRICS.call[&"add_webhook"].start();
+
+    let id = sqlx::query!(
+        "
+        INSERT INTO
+          webhooks (url, secret, address, token)
+        VALUES
+          ($1, $2, $3, $4)
+        RETURNING id
+        ",
+        url,
+        secret,
+        address.as_ref().map(|a| a.as_bytes()),
+        token.as_ref().map(|t| t.as_bytes()),
+    )
+    .fetch_one(pool)
+    .await?
+    .id;
+
+    latency.observe();
+
+    Ok(id as u64)
+}
+
+/// Request all registered webhooks.
+pub async fn webhooks(pool: &PgPool) -> Result<Vec<Webhook>> {
+    let latency = STORAGE_METRICS.call[&"webhooks"].start();
+
+    let webhooks = sqlx::query!(
+        "
+        SELECT
+          id,
+          url,
+          secret,
+          address,
+          token
+        FROM
+          webhooks
+        ORDER BY
+          id
+        "
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| Webhook {
+        id: r.id as u64,
+        url: r.url,
+        secret: r.secret,
+        address: r.address.map(|a| Address::from_slice(&a)),
+        token: r.token.map(|t| Address::from_slice(&t)),
+    })
+    .collect();
+
+    latency.observe();
+
+    Ok(webhooks)
+}
+
+/// Remove a webhook along with its pending deliveries.
+///
+/// Returns `false` if there is no such webhook.
+pub async fn delete_webhook(pool: &PgPool, id: u64) -> Result<bool> {
+    let latency = STORAGE_METRICS.call[&"delete_webhook"].start();
+
+    let deleted = sqlx::query!(
+        "
+        DELETE FROM
+          webhooks
+        WHERE
+          id = $1
+        ",
+        id as i64,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    latency.observe();
+
+    Ok(deleted > 0)
+}
+
+// Enqueue deliveries of an `event` of withdrawals to the webhooks they match.
+//
+// Addresses of withdrawals are only known once their finalization parameters are stored.
+pub(crate) async fn enqueue_webhook_deliveries(
+    conn: &mut PgConnection,
+    withdrawal_ids: &[i64],
+    event: WebhookEvent,
+) -> Result<()> {
+    sqlx::query!(
+        "
+        INSERT INTO
+          webhook_outbox (webhook_id, withdrawal_id, event)
+        SELECT DISTINCT
+          webhooks.id,
+          w.id,
+          $2 :: VARCHAR
+        FROM
+          withdrawals w
+          LEFT JOIN finalization_data fd ON fd.withdrawal_id = w.id
+          LEFT JOIN l2_to_l1_events e ON e.l2_block_number = fd.l1_batch_number
+          AND e.tx_number_in_block = fd.l2_tx_number_in_block
+          JOIN webhooks ON (
+            webhooks.address IS NULL
+            OR webhooks.address IN (e.to_address, fd.sender)
+          )
+          AND (
+            webhooks.token IS NULL
+            OR webhooks.token IN (w.token, e.l1_token_addr)
+          )
+        WHERE
+          w.id = ANY ($1)
+        ON CONFLICT (webhook_id, withdrawal_id, event) DO NOTHING
+        ",
+        withdrawal_ids,
+        event.as_str(),
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// A withdrawal a webhook is delivered about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookWithdrawal {
+    /// Withdrawal id.
+    pub id: u64,
+    /// Transaction hash.
+    pub tx_hash: H256,
+    /// Event index in the transaction.
+    pub event_index_in_tx: u32,
+    /// L2 token address.
+    pub token: Address,
+    /// Amount.
+    pub amount: U256,
+    /// Hash of the transaction that has finalized the withdrawal, if known.
+    pub finalization_tx: Option<H256>,
+}
+
+/// A delivery of an event to a webhook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookDelivery {
+    /// Delivery id.
+    pub id: u64,
+    /// Webhook id.
+    pub webhook_id: u64,
+    /// URL the event is posted to.
+    pub url: String,
+    /// Secret the delivery is signed with.
+    pub secret: String,
+    /// The event.
+    pub event: WebhookEvent,
+    /// Number of failed attempts to deliver the event.
+    pub attempts: u64,
+    /// Error of the last failed attempt.
+    pub last_error: Option<String>,
+    /// The withdrawal.
+    pub withdrawal: WebhookWithdrawal,
+}
+
+/// Request deliveries due to be attempted, the oldest first.
+pub async fn due_webhook_deliveries(pool: &PgPool, limit: u64) -> Result<Vec<WebhookDelivery>> {
+    let latency = STORAGE_METRICS.call[&"due_webhook_deliveries"].start();
+
+    let deliveries = sqlx::query!(
+        "
+        SELECT
+          o.id,
+          o.webhook_id,
+          webhooks.url,
+          webhooks.secret,
+          o.event,
+          o.attempts,
+          o.last_error,
+          w.id AS withdrawal_id,
+          w.tx_hash,
+          w.event_index_in_tx,
+          w.token,
+          w.amount,
+          fd.finalization_tx AS \"finalization_tx?\"
+        FROM
+          webhook_outbox o
+          JOIN webhooks ON webhooks.id = o.webhook_id
+          JOIN withdrawals w ON w.id = o.withdrawal_id
+          LEFT JOIN finalization_data fd ON fd.withdrawal_id = w.id
+        WHERE
+          o.delivered_at IS NULL
+          AND o.dead_at IS NULL
+          AND o.next_attempt_at <= NOW()
+        ORDER BY
+          o.id
+        LIMIT
+          $1
+        ",
+        limit as i64,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| {
+        Ok(WebhookDelivery {
+            id: r.id as u64,
+            webhook_id: r.webhook_id as u64,
+            url: r.url,
+            secret: r.secret,
+            event: r.event.parse()?,
+            attempts: r.attempts as u64,
+            last_error: r.last_error,
+            withdrawal: WebhookWithdrawal {
+                id: r.withdrawal_id as u64,
+                tx_hash: H256::from_slice(&r.tx_hash),
+                event_index_in_tx: r.event_index_in_tx as u32,
+                token: Address::from_slice(&r.token),
+                amount: utils::bigdecimal_to_u256(r.amount),
+                // Withdrawals finalized by someone else are marked with a zero hash.
+                finalization_tx: r
+                    .finalization_tx
+                    .map(|tx| H256::from_slice(&tx))
+                    .filter(|tx| !tx.is_zero()),
+            },
+        })
+    })
+    .collect::<Result<_>>()?;
+
+    latency.observe();
+
+    Ok(deliveries)
+}
+
+/// Record a successful delivery.
+pub async fn webhook_delivered(pool: &PgPool, id: u64) -> Result<()> {
+    let latency = STORAGE_METRICS.call[&"webhook_delivered"].start();
+
+    sqlx::query!(
+        "
+        UPDATE
+          webhook_outbox
+        SET
+          delivered_at = NOW()
+        WHERE
+          id = $1
+        ",
+        id as i64,
+    )
+    .execute(pool)
+    .await?;
+
+    latency.observe();
+
+    Ok(())
+}
+
+/// Record a failed attempt of a delivery, the next one is scheduled according to `retry_policy`.
+///
+/// Returns `true` if the delivery has been dead-lettered and is not going to be attempted again.
+pub async fn webhook_delivery_failed(
+    pool: &PgPool,
+    id: u64,
+    error: &str,
+    retry_policy: &RetryPolicy,
+) -> Result<bool> {
+    let latency = STORAGE_METRICS.call[&"webhook_delivery_failed"].start();
+
+    let dead = sqlx::query!(
+        "
+        UPDATE
+          webhook_outbox
+        SET
+          attempts = attempts + 1,
+          last_error = $2,
+          next_attempt_at = NOW() + LEAST(
+            $3 :: FLOAT8 * POWER($5 :: FLOAT8, attempts),
+            $4 :: FLOAT8
+          ) * (1 + $6 :: FLOAT8 * RANDOM()) * INTERVAL '1 second',
+          dead_at = CASE
+            WHEN attempts + 1 >= $7 THEN NOW()
+            ELSE NULL
+          END
+        WHERE
+          id = $1
+        RETURNING
+          dead_at IS NOT NULL AS \"dead!\"
+        ",
+        id as i64,
+        error,
+        retry_policy.initial_backoff.as_secs_f64(),
+        retry_policy.max_backoff.as_secs_f64(),
+        retry_policy.multiplier,
+        retry_policy.jitter,
+        retry_policy.max_attempts as i64,
+    )
+    .fetch_optional(pool)
+    .await?
+    .is_some_and(|r| r.dead);
+
+    latency.observe();
+
+    Ok(dead)
+}
+
+/// Request the deliveries that have been dead-lettered, the latest first.
+pub async fn dead_webhook_deliveries(pool: &PgPool, limit: u64) -> Result<Vec<WebhookDelivery>> {
+    let latency = STORAGE_METRICS.call[&"dead_webhook_deliveries"].start();
+
+    let deliveries = sqlx::query!(
+        "
+        SELECT
+          o.id,
+          o.webhook_id,
+          webhooks.url,
+          webhooks.secret,
+          o.event,
+          o.attempts,
+          o.last_error,
+          w.id AS withdrawal_id,
+          w.tx_hash,
+          w.event_index_in_tx,
+          w.token,
+          w.amount,
+          fd.finalization_tx AS \"finalization_tx?\"
+        FROM
+          webhook_outbox o
+          JOIN webhooks ON webhooks.id = o.webhook_id
+          JOIN withdrawals w ON w.id = o.withdrawal_id
+          LEFT JOIN finalization_data fd ON fd.withdrawal_id = w.id
+        WHERE
+          o.dead_at IS NOT NULL
+        ORDER BY
+          o.dead_at DESC
+        LIMIT
+          $1
+        ",
+        limit as i64,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| {
+        Ok(WebhookDelivery {
+            id: r.id as u64,
+            webhook_id: r.webhook_id as u64,
+            url: r.url,
+            secret: r.secret,
+            event: r.event.parse()?,
+            attempts: r.attempts as u64,
+            last_error: r.last_error,
+            withdrawal: WebhookWithdrawal {
+                id: r.withdrawal_id as u64,
+                tx_hash: H256::from_slice(&r.tx_hash),
+                event_index_in_tx: r.event_index_in_tx as u32,
+                token: Address::from_slice(&r.token),
+                amount: utils::bigdecimal_to_u256(r.amount),
+                finalization_tx: r
+                    .finalization_tx
+                    .map(|tx| H256::from_slice(&tx))
+                    .filter(|tx| !tx.is_zero()),
+            },
+        })
+    })
+    .collect::<Result<_>>()?;
+
+    latency.observe();
+
+    Ok(deliveries)
+}
+
+/// Attempt a dead-lettered delivery again from scratch.
+///
+/// Returns `false` if there is no such dead-lettered delivery.
+pub async fn retry_dead_webhook_delivery(pool: &PgPool, id: u64) -> Result<bool> {
+    let latency = STORAGE_METRICS.call[&"retry_dead_webhook_delivery"].start();
+
+    let updated = sqlx::query!(
+        "
+        UPDATE
+          webhook_outbox
+        SET
+          attempts = 0,
+          next_attempt_at = NOW(),
+          dead_at = NULL
+        WHERE
+          id = $1
+          AND dead_at IS NOT NULL
+        ",
+        id as i64,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    latency.observe();
+
+    Ok(updated > 0)
+}
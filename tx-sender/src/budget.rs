@@ -0,0 +1,96 @@
+//! Limits on fees spent by sent transactions.
+
+use std::time::Duration;
+
+use ethers::types::U256;
+
+/// Default limit of a single transaction fee in wei, 0.8 ether.
+const DEFAULT_TX_FEE_LIMIT: u64 = 800_000_000_000_000_000;
+
+/// Limits on fees an account may spend on transactions.
+#[derive(Debug, Clone)]
+pub struct FeeBudget {
+    per_tx: U256,
+    per_hour: Option<U256>,
+    per_day: Option<U256>,
+}
+
+/// Fees spent by an account measured against a [`FeeBudget`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeBudgetStatus {
+    /// Fees spent within the last hour.
+    pub spent_last_hour: U256,
+    /// Fees spent within the last day.
+    pub spent_last_day: U256,
+    /// Fees that may be spent before any of the limits is exceeded,
+    /// `None` if there are no hourly or daily limits.
+    pub remaining: Option<U256>,
+}
+
+impl Default for FeeBudget {
+    fn default() -> Self {
+        Self {
+            per_tx: DEFAULT_TX_FEE_LIMIT.into(),
+            per_hour: None,
+            per_day: None,
+        }
+    }
+}
+
+impl FeeBudget {
+    /// The period the hourly limit applies to.
+    pub const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    /// The period the daily limit applies to.
+    pub const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Never spend more than this amount on a single transaction.
+    pub fn with_tx_limit(mut self, per_tx: U256) -> Self {
+        self.per_tx = per_tx;
+        self
+    }
+
+    /// Never spend more than this amount within an hour.
+    pub fn with_hourly_limit(mut self, per_hour: U256) -> Self {
+        self.per_hour = Some(per_hour);
+        self
+    }
+
+    /// Never spend more than this amount within a day.
+    pub fn with_daily_limit(mut self, per_day: U256) -> Self {
+        self.per_day = Some(per_day);
+        self
+    }
+
+    /// The limit of a single transaction fee.
+    pub fn tx_limit(&self) -> U256 {
+        self.per_tx
+    }
+
+    /// The limit of fees spent within an hour.
+    pub fn hourly_limit(&self) -> Option<U256> {
+        self.per_hour
+    }
+
+    /// The limit of fees spent within a day.
+    pub fn daily_limit(&self) -> Option<U256> {
+        self.per_day
+    }
+
+    /// Measure fees spent within the last [`Self::HOUR`] and [`Self::DAY`] against the limits.
+    pub fn status(&self, spent_last_hour: U256, spent_last_day: U256) -> FeeBudgetStatus {
+        let remaining = [
+            self.per_hour.map(|l| l.saturating_sub(spent_last_hour)),
+            self.per_day.map(|l| l.saturating_sub(spent_last_day)),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+
+        FeeBudgetStatus {
+            spent_last_hour,
+            spent_last_day,
+            remaining,
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Strategies to price transactions.
+
+use async_trait::async_trait;
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, BlockNumber, U256},
+};
+
+use crate::{bump_predicted_fees, RETRY_BUMP_FEES_PERCENT};
+
+/// Number of recent blocks to consider when estimating priority fees.
+const FEE_HISTORY_BLOCKS: u64 = 10;
+
+/// A multiplier of the base fee in the `max_fee_per_gas` to keep
+/// the transaction includable for several blocks of growing base fee.
+const BASE_FEE_MULTIPLIER: u64 = 2;
+
+/// Default percentile of priority fees paid in recent blocks.
+const DEFAULT_REWARD_PERCENTILE: f64 = 50.0;
+
+/// A strategy to set and bump fees of transactions sent through the middleware `M`.
+#[async_trait]
+pub trait FeeStrategy<M: Middleware>: Send + Sync {
+    /// Set fees of a transaction that is about to be sent for the first time.
+    ///
+    /// Returns `false` and leaves the transaction intact if
+    /// the fees can not cover the base fee within the configured limits.
+    async fn estimate(&self, m: &M, tx: &mut TypedTransaction) -> Result<bool, M::Error>;
+
+    /// Bump fees of a transaction to replace its previous submission.
+    ///
+    /// Returns `false` and leaves the transaction intact if
+    /// the fees can not be bumped within the configured limits.
+    async fn bump(&self, m: &M, tx: &mut TypedTransaction) -> Result<bool, M::Error>;
+
+    /// Maximal number of times a transaction may be replaced.
+    fn max_replacements(&self) -> Option<usize>;
+}
+
+/// A [`FeeStrategy`] paying a percentile of priority fees
+/// paid in recent blocks as reported by `eth_feeHistory`.
+///
+/// Legacy transactions keep the gas price suggested by the node,
+/// both are capped by the max fee per gas but never below the base fee.
+#[derive(Debug, Clone)]
+pub struct FeeHistoryStrategy {
+    reward_percentile: f64,
+    max_fee_per_gas: Option<U256>,
+    max_replacements: Option<usize>,
+}
+
+impl Default for FeeHistoryStrategy {
+    fn default() -> Self {
+        Self {
+            reward_percentile: DEFAULT_REWARD_PERCENTILE,
+            max_fee_per_gas: None,
+            max_replacements: None,
+        }
+    }
+}
+
+impl FeeHistoryStrategy {
+    /// Pay this percentile of priority fees paid in recent blocks.
+    pub fn with_reward_percentile(mut self, reward_percentile: f64) -> Self {
+        self.reward_percentile = reward_percentile;
+        self
+    }
+
+    /// Never pay more than this amount per gas.
+    pub fn with_max_fee_per_gas(mut self, max_fee_per_gas: U256) -> Self {
+        self.max_fee_per_gas = Some(max_fee_per_gas);
+        self
+    }
+
+    /// Leave transactions pending after they have been replaced this many times.
+    pub fn with_max_replacements(mut self, max_replacements: usize) -> Self {
+        self.max_replacements = Some(max_replacements);
+        self
+    }
+
+    fn cap(&self, fee: U256) -> U256 {
+        match self.max_fee_per_gas {
+            Some(max_fee_per_gas) => std::cmp::min(fee, max_fee_per_gas),
+            None => fee,
+        }
+    }
+
+    fn exceeds_cap(&self, fee: Option<U256>) -> bool {
+        match (self.max_fee_per_gas, fee) {
+            (Some(max_fee_per_gas), Some(fee)) => fee > max_fee_per_gas,
+            _ => false,
+        }
+    }
+}
+
+#[async_trait]
+impl<M: Middleware> FeeStrategy<M> for FeeHistoryStrategy {
+    async fn estimate(&self, m: &M, tx: &mut TypedTransaction) -> Result<bool, M::Error> {
+        match tx {
+            TypedTransaction::Eip1559(ref mut tx) => {
+                let history = m
+                    .fee_history(
+                        FEE_HISTORY_BLOCKS,
+                        BlockNumber::Latest,
+                        &[self.reward_percentile],
+                    )
+                    .await?;
+
+                // The last base fee in the history is the one of the next block.
+                let base_fee_per_gas = history.base_fee_per_gas.last().copied().unwrap_or_default();
+
+                let mut rewards: Vec<_> = history
+                    .reward
+                    .iter()
+                    .filter_map(|r| r.first().copied())
+                    .collect();
+                rewards.sort();
+
+                let priority_fee_per_gas =
+                    rewards.get(rewards.len() / 2).copied().unwrap_or_default();
+
+                let max_fee_per_gas = self.cap(
+                    base_fee_per_gas
+                        .saturating_mul(BASE_FEE_MULTIPLIER.into())
+                        .saturating_add(priority_fee_per_gas),
+                );
+
+                // A transaction not covering the base fee is never going to be included.
+                if max_fee_per_gas < base_fee_per_gas {
+                    return Ok(false);
+                }
+
+                tx.max_fee_per_gas = Some(max_fee_per_gas);
+                tx.max_priority_fee_per_gas =
+                    Some(std::cmp::min(priority_fee_per_gas, max_fee_per_gas));
+            }
+            tx => {
+                if let Some(gas_price) = tx.gas_price() {
+                    let capped_gas_price = self.cap(gas_price);
+
+                    // The gas price of a legacy transaction has to cover the base fee as well.
+                    if capped_gas_price < gas_price {
+                        let base_fee_per_gas = m
+                            .get_block(BlockNumber::Latest)
+                            .await?
+                            .and_then(|block| block.base_fee_per_gas);
+
+                        if base_fee_per_gas.is_some_and(|base_fee| capped_gas_price < base_fee) {
+                            return Ok(false);
+                        }
+                    }
+
+                    tx.set_gas_price(capped_gas_price);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn bump(&self, m: &M, tx: &mut TypedTransaction) -> Result<bool, M::Error> {
+        let mut bumped = tx.clone();
+        bump_predicted_fees(&mut bumped, RETRY_BUMP_FEES_PERCENT, m).await?;
+
+        // For EIP-1559 transactions `gas_price` is the `max_fee_per_gas`.
+        if self.exceeds_cap(bumped.gas_price()) {
+            return Ok(false);
+        }
+
+        *tx = bumped;
+
+        Ok(true)
+    }
+
+    fn max_replacements(&self) -> Option<usize> {
+        self.max_replacements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        providers::{Middleware, Provider, ProviderExt},
+        types::{
+            transaction::eip2718::TypedTransaction, Eip1559TransactionRequest, TransactionRequest,
+            U256,
+        },
+        utils::Anvil,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{FeeHistoryStrategy, FeeStrategy};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fees_are_capped_by_max_fee_per_gas() {
+        let anvil = Anvil::new().arg("--no-mining").spawn();
+
+        let provider = Provider::<ethers::providers::Http>::connect(&anvil.endpoint()).await;
+
+        let accounts = provider.get_accounts().await.unwrap();
+
+        let mut tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(accounts[1])
+            .value(1000)
+            .from(accounts[0])
+            .into();
+
+        let strategy = FeeHistoryStrategy::default();
+        assert!(strategy.estimate(&provider, &mut tx).await.unwrap());
+
+        let estimated = tx.gas_price().unwrap();
+        let max_fee_per_gas = estimated - 1;
+
+        let strategy = FeeHistoryStrategy::default().with_max_fee_per_gas(max_fee_per_gas);
+        assert!(strategy.estimate(&provider, &mut tx).await.unwrap());
+
+        assert_eq!(tx.gas_price().unwrap(), max_fee_per_gas);
+        assert!(!strategy.bump(&provider, &mut tx).await.unwrap());
+        assert_eq!(tx.gas_price().unwrap(), max_fee_per_gas);
+
+        let strategy = FeeHistoryStrategy::default().with_max_fee_per_gas(U256::MAX);
+        assert!(strategy.bump(&provider, &mut tx).await.unwrap());
+        assert!(tx.gas_price().unwrap() > max_fee_per_gas);
+
+        // Transactions are not priced below the base fee.
+        let priced = tx.clone();
+        let strategy = FeeHistoryStrategy::default().with_max_fee_per_gas(U256::one());
+        assert!(!strategy.estimate(&provider, &mut tx).await.unwrap());
+        assert_eq!(tx, priced);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn legacy_gas_price_is_not_capped_below_the_base_fee() {
+        let anvil = Anvil::new().arg("--no-mining").spawn();
+
+        let provider = Provider::<ethers::providers::Http>::connect(&anvil.endpoint()).await;
+
+        let accounts = provider.get_accounts().await.unwrap();
+
+        let mut tx: TypedTransaction = TransactionRequest::new()
+            .to(accounts[1])
+            .value(1000)
+            .from(accounts[0])
+            .into();
+        provider.fill_transaction(&mut tx, None).await.unwrap();
+
+        let priced = tx.clone();
+        let strategy = FeeHistoryStrategy::default().with_max_fee_per_gas(U256::one());
+        assert!(!strategy.estimate(&provider, &mut tx).await.unwrap());
+        assert_eq!(tx, priced);
+    }
+}
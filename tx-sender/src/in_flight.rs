@@ -0,0 +1,112 @@
+//! Tracking of concurrently sent transactions with consecutive nonces.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use ethers::types::U256;
+use tokio::sync::watch;
+
+/// Transactions of a single account that are in flight at the same time.
+///
+/// A transaction with a higher nonce can not be mined before all
+/// transactions with lower nonces are, so bumping its fees ahead of
+/// them is a waste of money. This structure keeps track of the
+/// number of times each in-flight transaction has had its fees bumped
+/// and lets the higher nonces wait for the lower ones to catch up.
+#[derive(Debug, Clone)]
+pub struct InFlightTransactions {
+    bumps: Arc<watch::Sender<BTreeMap<U256, usize>>>,
+}
+
+impl Default for InFlightTransactions {
+    fn default() -> Self {
+        Self {
+            bumps: Arc::new(watch::Sender::new(BTreeMap::new())),
+        }
+    }
+}
+
+impl InFlightTransactions {
+    /// Start tracking a transaction with a given nonce.
+    pub fn register(&self, nonce: U256) {
+        self.bumps.send_modify(|bumps| {
+            bumps.insert(nonce, 0);
+        });
+    }
+
+    /// Stop tracking a transaction with a given nonce.
+    pub fn finished(&self, nonce: U256) {
+        self.bumps.send_modify(|bumps| {
+            bumps.remove(&nonce);
+        });
+    }
+
+    /// Nonces of all transactions currently in flight.
+    pub fn nonces(&self) -> Vec<U256> {
+        self.bumps.borrow().keys().copied().collect()
+    }
+
+    /// Number of transactions currently in flight.
+    pub fn len(&self) -> usize {
+        self.bumps.borrow().len()
+    }
+
+    /// Whether there are no transactions in flight.
+    pub fn is_empty(&self) -> bool {
+        self.bumps.borrow().is_empty()
+    }
+
+    // Wait until all transactions with nonces lower than `nonce` have
+    // had their fees bumped at least as many times as the one with `nonce`
+    // is going to be bumped and record the bump.
+    pub(crate) async fn bump(&self, nonce: U256) {
+        let mut rx = self.bumps.subscribe();
+
+        let bump = self.bumps.borrow().get(&nonce).copied().unwrap_or_default() + 1;
+
+        // The sender is owned by `self` and is never dropped while waiting.
+        let _ = rx
+            .wait_for(|bumps| bumps.range(..nonce).all(|(_, b)| *b >= bump))
+            .await;
+
+        self.bumps.send_modify(|bumps| {
+            bumps.insert(nonce, bump);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::InFlightTransactions;
+
+    #[tokio::test]
+    async fn higher_nonces_are_bumped_after_lower_ones() {
+        let in_flight = InFlightTransactions::default();
+
+        in_flight.register(1.into());
+        in_flight.register(2.into());
+
+        // Nonce `2` can not be bumped until nonce `1` is bumped.
+        let bump_2 = tokio::spawn({
+            let in_flight = in_flight.clone();
+            async move { in_flight.bump(2.into()).await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!bump_2.is_finished());
+
+        in_flight.bump(1.into()).await;
+        bump_2.await.unwrap();
+
+        // Nonce `2` does not wait for finished transactions.
+        in_flight.finished(1.into());
+        tokio::time::timeout(Duration::from_secs(1), in_flight.bump(2.into()))
+            .await
+            .unwrap();
+
+        assert_eq!(in_flight.nonces(), vec![2.into()]);
+    }
+}
@@ -0,0 +1,64 @@
+//! Journaling of submissions of transactions to the network.
+
+use async_trait::async_trait;
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, H256, U256};
+
+/// A submission of a transaction to the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Submission {
+    /// Hash of the submitted transaction.
+    pub tx_hash: H256,
+
+    /// Max fee per gas or the gas price of a legacy transaction.
+    pub max_fee_per_gas: U256,
+
+    /// Max priority fee per gas, `None` for legacy transactions.
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+impl Submission {
+    pub(crate) fn new(tx_hash: H256, tx: &TypedTransaction) -> Self {
+        let (max_fee_per_gas, max_priority_fee_per_gas) = match tx {
+            TypedTransaction::Eip1559(tx) => (
+                tx.max_fee_per_gas.unwrap_or_default(),
+                tx.max_priority_fee_per_gas,
+            ),
+            tx => (tx.gas_price().unwrap_or_default(), None),
+        };
+
+        Self {
+            tx_hash,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        }
+    }
+}
+
+/// A journaled transaction that has not been seen mined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournaledTransaction {
+    /// Nonce of the transaction.
+    pub nonce: U256,
+
+    /// Recipient of the transaction.
+    pub to: Address,
+
+    /// Calldata of the transaction.
+    pub calldata: Vec<u8>,
+
+    /// Gas limit of the transaction.
+    pub gas_limit: U256,
+
+    /// Submissions of the transaction to the network, the latest last.
+    pub submissions: Vec<Submission>,
+}
+
+/// A journal of a transaction every submission of it is recorded into.
+#[async_trait]
+pub trait Journal: Send + Sync {
+    /// Record a submission of the transaction once it has been sent.
+    async fn record(
+        &self,
+        submission: &Submission,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
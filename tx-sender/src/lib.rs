@@ -8,16 +8,27 @@
 use std::{time::Duration, u8};
 
 use ethers::{
-    providers::{Middleware, MiddlewareError, ProviderError},
+    providers::{Middleware, MiddlewareError, PendingTransaction, ProviderError},
     types::{
-        transaction::eip2718::TypedTransaction, BlockNumber, Eip2930TransactionRequest,
-        TransactionReceipt, U256,
+        transaction::eip2718::TypedTransaction, BlockNumber, Eip1559TransactionRequest,
+        Eip2930TransactionRequest, TransactionReceipt, TransactionRequest, H256, U256,
     },
 };
 
 use crate::metrics::TX_SENDER_METRICS;
 
+mod budget;
+mod fees;
+mod in_flight;
+mod journal;
 mod metrics;
+mod nonce;
+
+pub use budget::{FeeBudget, FeeBudgetStatus};
+pub use fees::{FeeHistoryStrategy, FeeStrategy};
+pub use in_flight::InFlightTransactions;
+pub use journal::{Journal, JournaledTransaction, Submission};
+pub use nonce::NonceManager;
 
 const RETRY_BUMP_FEES_PERCENT: u8 = 15;
 
@@ -32,7 +43,7 @@ const RETRY_BUMP_FEES_PERCENT: u8 = 15;
 async fn bump_predicted_fees<M: Middleware>(
     tx: &mut TypedTransaction,
     percent: u8,
-    m: M,
+    m: &M,
 ) -> Result<(), M::Error> {
     match tx {
         TypedTransaction::Legacy(ref mut tx)
@@ -80,35 +91,232 @@ fn inc_u256_percent(num: U256, percent: u8) -> U256 {
     num.saturating_mul(percent.into()) / 100
 }
 
-/// Send a transaction with specified number of retries.
+// Price a transaction that is about to be sent for the first time.
+//
+// Returns `false` if it can not be priced to cover the base fee, such
+// a transaction would never be included and is not sent at all.
+async fn estimate_fees<M, F>(
+    m: &M,
+    fee_strategy: &F,
+    tx: &mut TypedTransaction,
+) -> Result<bool, <M as Middleware>::Error>
+where
+    M: Middleware,
+    F: FeeStrategy<M> + ?Sized,
+{
+    let priced = fee_strategy.estimate(m, tx).await?;
+
+    if !priced {
+        tracing::warn!("max fee per gas is below the base fee, not sending the transaction");
+        TX_SENDER_METRICS.max_fee_per_gas_below_base_fee.inc();
+    }
+
+    Ok(priced)
+}
+
+/// Send a transaction replacing it with bumped fees until it is mined.
+///
+/// Returns `None` if the transaction has not been mined, it is not sent
+/// at all if the max fee per gas of `fee_strategy` is below the base fee.
+///
+/// # Arguments
+///
+/// * `m`: [`Middleware`] to perform request with
+/// * `fee_strategy`: [`FeeStrategy`] to price the transaction with, it also
+///   caps the fees and the number of replacements.
+/// * `tx`: Transaction to be sent
+/// * `retry_timeout`: A period after which to retry transaction.
+/// * `nonce`: Nonce of the transaction.
+/// * `gas_limit`: Gas limit of the transaction.
+pub async fn send_tx_adjust_gas<M, F, T>(
+    m: M,
+    fee_strategy: &F,
+    tx: T,
+    retry_timeout: Duration,
+    nonce: U256,
+    gas_limit: U256,
+) -> Result<Option<TransactionReceipt>, <M as Middleware>::Error>
+where
+    M: Middleware,
+    F: FeeStrategy<M> + ?Sized,
+    T: Into<TypedTransaction> + Send + Sync + Clone,
+{
+    let mut submit_tx = tx.into();
+    m.fill_transaction(&mut submit_tx, None).await?;
+    if !estimate_fees(&m, fee_strategy, &mut submit_tx).await? {
+        return Ok(None);
+    }
+    submit_tx.set_nonce(nonce);
+    submit_tx.set_gas(gas_limit);
+
+    send_and_wait(
+        m,
+        fee_strategy,
+        submit_tx,
+        retry_timeout,
+        nonce,
+        None,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Send a transaction with specified number of retries recording
+/// every submission into the `journal`.
+///
+/// Like with [`send_tx_adjust_gas`] the transaction is not sent if
+/// the max fee per gas of `fee_strategy` is below the base fee.
 ///
 /// # Arguments
 ///
 /// * `m`: [`Middleware`] to perform request with
+/// * `fee_strategy`: [`FeeStrategy`] to price the transaction with
+/// * `journal`: [`Journal`] of this transaction
 /// * `tx`: Transaction to be sent
 /// * `retry_timeout`: A period after which to retry transaction.
-/// * `retries`: Amount of retries to perform.
-/// * `gas_increase_step`: If present increase gas price by this  number on every step, else ask
-///    actual gas price from `m` on every retry.
-pub async fn send_tx_adjust_gas<M, T>(
+/// * `in_flight`: Other transactions of the same account sent concurrently.
+#[allow(clippy::too_many_arguments)]
+pub async fn send_journaled_tx_adjust_gas<M, F, T>(
     m: M,
+    fee_strategy: &F,
+    journal: &dyn Journal,
     tx: T,
     retry_timeout: Duration,
     nonce: U256,
     gas_limit: U256,
+    in_flight: &InFlightTransactions,
 ) -> Result<Option<TransactionReceipt>, <M as Middleware>::Error>
 where
     M: Middleware,
+    F: FeeStrategy<M> + ?Sized,
     T: Into<TypedTransaction> + Send + Sync + Clone,
 {
     let mut submit_tx = tx.into();
     m.fill_transaction(&mut submit_tx, None).await?;
+    if !estimate_fees(&m, fee_strategy, &mut submit_tx).await? {
+        return Ok(None);
+    }
     submit_tx.set_nonce(nonce);
     submit_tx.set_gas(gas_limit);
 
-    for retry_num in 0..usize::MAX {
-        if retry_num > 0 {
-            bump_predicted_fees(&mut submit_tx, RETRY_BUMP_FEES_PERCENT, &m).await?;
+    send_and_wait(
+        m,
+        fee_strategy,
+        submit_tx,
+        retry_timeout,
+        nonce,
+        Some(journal),
+        Some(in_flight),
+        None,
+    )
+    .await
+}
+
+/// Resume sending a journaled transaction that has not been seen mined.
+///
+/// If the transaction has already been submitted it is replaced with
+/// the fees of its last submission bumped, so that the replacement is
+/// accepted even if the previous submission is still in the mempool.
+/// Previous submissions count towards the replacements allowed by `fee_strategy`.
+pub async fn resume_journaled_tx<M, F>(
+    m: M,
+    fee_strategy: &F,
+    journal: &dyn Journal,
+    sent_tx: &JournaledTransaction,
+    retry_timeout: Duration,
+) -> Result<Option<TransactionReceipt>, <M as Middleware>::Error>
+where
+    M: Middleware,
+    F: FeeStrategy<M> + ?Sized,
+{
+    let last_sent = sent_tx.submissions.last();
+
+    let mut submit_tx: TypedTransaction = match last_sent {
+        Some(Submission {
+            max_fee_per_gas,
+            max_priority_fee_per_gas: None,
+            ..
+        }) => TransactionRequest::new().gas_price(*max_fee_per_gas).into(),
+        Some(Submission {
+            max_fee_per_gas,
+            max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
+            ..
+        }) => Eip1559TransactionRequest::new()
+            .max_fee_per_gas(*max_fee_per_gas)
+            .max_priority_fee_per_gas(*max_priority_fee_per_gas)
+            .into(),
+        None => Eip1559TransactionRequest::new().into(),
+    };
+
+    submit_tx.set_to(sent_tx.to);
+    submit_tx.set_data(sent_tx.calldata.clone().into());
+    m.fill_transaction(&mut submit_tx, None).await?;
+    if last_sent.is_none() && !estimate_fees(&m, fee_strategy, &mut submit_tx).await? {
+        return Ok(None);
+    }
+    submit_tx.set_nonce(sent_tx.nonce);
+    submit_tx.set_gas(sent_tx.gas_limit);
+
+    send_and_wait(
+        m,
+        fee_strategy,
+        submit_tx,
+        retry_timeout,
+        sent_tx.nonce,
+        Some(journal),
+        None,
+        last_sent.map(|sent| (sent.tx_hash, sent_tx.submissions.len() - 1)),
+    )
+    .await
+}
+
+// Send a transaction replacing it with bumped fees every `retry_timeout`.
+//
+// `pending` is the hash of the last submission of this transaction, if any,
+// and the number of times it has already been replaced.
+#[allow(clippy::too_many_arguments)]
+async fn send_and_wait<M, F>(
+    m: M,
+    fee_strategy: &F,
+    mut submit_tx: TypedTransaction,
+    retry_timeout: Duration,
+    nonce: U256,
+    journal: Option<&dyn Journal>,
+    in_flight: Option<&InFlightTransactions>,
+    mut pending: Option<(H256, usize)>,
+) -> Result<Option<TransactionReceipt>, <M as Middleware>::Error>
+where
+    M: Middleware,
+    F: FeeStrategy<M> + ?Sized,
+{
+    loop {
+        if let Some((tx_hash, replacements)) = pending {
+            if fee_strategy
+                .max_replacements()
+                .is_some_and(|max| replacements >= max)
+            {
+                tracing::warn!(
+                    "transaction {tx_hash:?} has been replaced {replacements} times, leaving it pending"
+                );
+                TX_SENDER_METRICS.max_replacements_reached.inc();
+
+                return wait_for_pending(&m, tx_hash, retry_timeout).await;
+            }
+
+            if let Some(in_flight) = in_flight {
+                in_flight.bump(nonce).await;
+            }
+
+            if !fee_strategy.bump(&m, &mut submit_tx).await? {
+                tracing::warn!(
+                    "replacing transaction {tx_hash:?} exceeds max fee per gas, leaving it pending"
+                );
+                TX_SENDER_METRICS.max_fee_per_gas_reached.inc();
+
+                return wait_for_pending(&m, tx_hash, retry_timeout).await;
+            }
+
             submit_tx.set_nonce(nonce);
         }
 
@@ -116,6 +324,10 @@ where
 
         let tx_hash = sent_tx.tx_hash();
 
+        if let Some(journal) = journal {
+            journal_tx_hash(journal, tx_hash, &submit_tx).await;
+        }
+
         let result = tokio::time::timeout(retry_timeout, sent_tx).await;
 
         match result {
@@ -126,11 +338,45 @@ where
             Err(_e) => {
                 tracing::info!("waiting for mined transaction {tx_hash:?} timed out",);
                 TX_SENDER_METRICS.timedout_transactions.inc();
+
+                let replacements = pending.map_or(0, |(_, replacements)| replacements + 1);
+                pending = Some((tx_hash, replacements));
             }
         }
     }
+}
+
+// Wait for a transaction that is not going to be replaced anymore.
+//
+// Returns `None` if it has not been mined within `timeout` either,
+// for the caller not to wait for an underpriced transaction forever.
+async fn wait_for_pending<M>(
+    m: &M,
+    tx_hash: H256,
+    timeout: Duration,
+) -> Result<Option<TransactionReceipt>, <M as Middleware>::Error>
+where
+    M: Middleware,
+{
+    match tokio::time::timeout(timeout, PendingTransaction::new(tx_hash, m.provider())).await {
+        Ok(res) => res.map_err(MiddlewareError::from_provider_err),
+        Err(_) => {
+            tracing::warn!("pending transaction {tx_hash:?} has not been mined within {timeout:?}");
+            TX_SENDER_METRICS.timedout_transactions.inc();
+
+            Ok(None)
+        }
+    }
+}
 
-    panic!("going to usize::MAX with delays is too far away in the future; qed");
+// The transaction has already been sent at this point, so failing
+// to record it is not fatal: the worst outcome is that in case of a
+// restart it will not be attributed to the withdrawals it finalizes.
+async fn journal_tx_hash(journal: &dyn Journal, tx_hash: H256, tx: &TypedTransaction) {
+    if let Err(e) = journal.record(&Submission::new(tx_hash, tx)).await {
+        tracing::error!("failed to journal sent transaction {tx_hash:?}: {e}");
+        TX_SENDER_METRICS.failed_to_journal_transactions.inc();
+    }
 }
 
 #[cfg(test)]
@@ -147,7 +393,10 @@ mod tests {
     };
     use pretty_assertions::assert_eq;
 
-    use crate::{inc_u256_percent, send_tx_adjust_gas, RETRY_BUMP_FEES_PERCENT};
+    use crate::{
+        inc_u256_percent, send_tx_adjust_gas, FeeHistoryStrategy, FeeStrategy, NonceManager,
+        RETRY_BUMP_FEES_PERCENT,
+    };
 
     #[tokio::test(flavor = "multi_thread")]
     async fn retry_sending_single_tx() {
@@ -178,6 +427,7 @@ mod tests {
         tokio::time::timeout(Duration::from_secs(3), async {
             send_tx_adjust_gas(
                 provider.clone(),
+                &FeeHistoryStrategy::default(),
                 tx,
                 Duration::from_secs(1),
                 0.into(),
@@ -244,10 +494,13 @@ mod tests {
             .gas_price(gas_price)
             .nonce(1);
 
+        let fee_strategy = FeeHistoryStrategy::default();
+
         tokio::time::timeout(Duration::from_secs(3), async {
             let (first, second) = tokio::join!(
                 send_tx_adjust_gas(
                     provider.clone(),
+                    &fee_strategy,
                     tx_1,
                     Duration::from_secs(1),
                     0.into(),
@@ -255,6 +508,7 @@ mod tests {
                 ),
                 send_tx_adjust_gas(
                     provider.clone(),
+                    &fee_strategy,
                     tx_2,
                     Duration::from_secs(1),
                     1.into(),
@@ -382,7 +636,20 @@ mod tests {
         let from = accounts[0];
         let to = accounts[1];
 
-        let (mut max_fee, mut priority_fee) = provider.estimate_eip1559_fees(None).await.unwrap();
+        // The fees are estimated by the fee strategy and bumped on every retry.
+        let mut estimated: TypedTransaction = Eip1559TransactionRequest::new().into();
+        assert!(FeeHistoryStrategy::default()
+            .estimate(&provider, &mut estimated)
+            .await
+            .unwrap());
+
+        let (mut max_fee, mut priority_fee) = match estimated {
+            TypedTransaction::Eip1559(ref tx) => (
+                tx.max_fee_per_gas.unwrap(),
+                tx.max_priority_fee_per_gas.unwrap(),
+            ),
+            _ => panic!("expected eip1559 tx"),
+        };
 
         for _ in 0..2 {
             let bump = inc_u256_percent(priority_fee, RETRY_BUMP_FEES_PERCENT);
@@ -401,6 +668,7 @@ mod tests {
         tokio::time::timeout(Duration::from_secs(3), async {
             send_tx_adjust_gas(
                 provider.clone(),
+                &FeeHistoryStrategy::default(),
                 tx,
                 Duration::from_secs(1),
                 0.into(),
@@ -430,4 +698,29 @@ mod tests {
         assert_eq!(tx.max_priority_fee_per_gas.unwrap(), priority_fee);
         assert_eq!(tx.max_fee_per_gas.unwrap(), max_fee);
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn nonce_manager_counts_pending_transactions() {
+        let anvil = Anvil::new().arg("--no-mining").spawn();
+
+        let provider = Provider::<ethers::providers::Http>::connect(&anvil.endpoint()).await;
+
+        let accounts = provider.get_accounts().await.unwrap();
+        let from = accounts[0];
+        let to = accounts[1];
+
+        let provider = Arc::new(provider);
+
+        let tx = TransactionRequest::new().to(to).value(1000).from(from);
+        provider.send_transaction(tx, None).await.unwrap();
+
+        let mut nonce_manager = NonceManager::new(from);
+
+        assert_eq!(nonce_manager.next(&provider).await.unwrap(), 1.into());
+        assert_eq!(nonce_manager.next(&provider).await.unwrap(), 2.into());
+
+        nonce_manager.reset();
+
+        assert_eq!(nonce_manager.next(&provider).await.unwrap(), 1.into());
+    }
 }
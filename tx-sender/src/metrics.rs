@@ -8,6 +8,18 @@ use vise::{Counter, Metrics};
 pub(super) struct TxSenderMetrics {
     /// Timedout transactions count.
     pub timedout_transactions: Counter,
+
+    /// Sent transactions that failed to be recorded into the journal.
+    pub failed_to_journal_transactions: Counter,
+
+    /// Transactions left pending after reaching the max number of replacements.
+    pub max_replacements_reached: Counter,
+
+    /// Transactions left pending because bumping fees would exceed the max fee per gas.
+    pub max_fee_per_gas_reached: Counter,
+
+    /// Transactions not sent because the max fee per gas is below the base fee.
+    pub max_fee_per_gas_below_base_fee: Counter,
 }
 
 #[vise::register]
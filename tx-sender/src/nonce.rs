@@ -0,0 +1,49 @@
+//! Nonce management for consecutive transactions of a single account.
+
+use ethers::{
+    providers::Middleware,
+    types::{Address, BlockNumber, U256},
+};
+
+/// Hands out nonces for consecutive transactions sent from an account.
+///
+/// The next nonce is queried from the `pending` state of the account
+/// on first use and after every [`NonceManager::reset`].
+#[derive(Debug)]
+pub struct NonceManager {
+    account: Address,
+    next_nonce: Option<U256>,
+}
+
+impl NonceManager {
+    /// Create a new [`NonceManager`] for a given account.
+    pub fn new(account: Address) -> Self {
+        Self {
+            account,
+            next_nonce: None,
+        }
+    }
+
+    /// Get the nonce for the next transaction.
+    pub async fn next<M: Middleware>(&mut self, m: M) -> Result<U256, M::Error> {
+        let nonce = match self.next_nonce {
+            Some(nonce) => nonce,
+            None => {
+                m.get_transaction_count(self.account, Some(BlockNumber::Pending.into()))
+                    .await?
+            }
+        };
+
+        self.next_nonce = Some(nonce + 1);
+
+        Ok(nonce)
+    }
+
+    /// Forget the locally tracked nonce.
+    ///
+    /// Should be called whenever it is not known if the last handed out
+    /// nonce has been used by a transaction that reached the network.
+    pub fn reset(&mut self) {
+        self.next_nonce = None;
+    }
+}
@@ -3,8 +3,11 @@ use std::{
     time::{Duration, Instant},
 };
 
-use chain_events::L2Event;
-use ethers::providers::{JsonRpcClient, Middleware};
+use chain_events::{L2Event, L1_BLOCK_HASHES_WINDOW};
+use ethers::{
+    providers::{JsonRpcClient, Middleware},
+    types::H256,
+};
 use futures::{stream::StreamExt, Stream};
 use sqlx::PgPool;
 use storage::StoredWithdrawal;
@@ -103,14 +106,24 @@ where
 
         pin!(l1_loop_handler);
         pin!(l2_loop_handler);
+        // Both loops end once their streams do on shutdown, the other one
+        // is then awaited to flush the events it has accumulated.
         tokio::select! {
-            l1 = l1_loop_handler => {
+            l1 = &mut l1_loop_handler => {
                 tracing::error!("watcher l1 loop ended with {l1:?}");
                 l1.unwrap()?;
+
+                let l2 = l2_loop_handler.await;
+                tracing::info!("watcher l2 loop ended with {l2:?}");
+                l2.unwrap()?;
             }
-            l2 = l2_loop_handler => {
+            l2 = &mut l2_loop_handler => {
                 tracing::error!("watcher l2 loop ended with {l2:?}");
                 l2.unwrap()?;
+
+                let l1 = l1_loop_handler.await;
+                tracing::info!("watcher l1 loop ended with {l1:?}");
+                l1.unwrap()?;
             }
         }
 
@@ -140,9 +153,22 @@ enum BlockRangesParams {
         range_end: u64,
         block_number: u64,
     },
+    Revert {
+        last_committed_l2_block: u64,
+        last_verified_l2_block: u64,
+        last_executed_l2_block: u64,
+        last_committed_batch: u64,
+    },
     L2ToL1Events {
         events: Vec<L2ToL1Event>,
     },
+    NewL1Block {
+        block_number: u64,
+        block_hash: H256,
+    },
+    L1Reorg {
+        fork_block_number: u64,
+    },
 }
 
 impl BlockRangesParams {
@@ -180,9 +206,49 @@ impl BlockRangesParams {
                     "Changed withdrawals status to executed for range {range_begin}-{range_end}"
                 );
             }
+            BlockRangesParams::Revert {
+                last_committed_l2_block,
+                last_verified_l2_block,
+                last_executed_l2_block,
+                last_committed_batch,
+            } => {
+                let reverted = storage::revert_batches(
+                    pool,
+                    last_committed_l2_block,
+                    last_verified_l2_block,
+                    last_executed_l2_block,
+                    last_committed_batch,
+                )
+                .await?;
+
+                WATCHER_METRICS.l2_reverted_blocks.inc_by(reverted);
+
+                tracing::warn!(
+                    "Reverted {reverted} blocks, withdrawals are now committed up to {}, verified up to {}, executed up to {}",
+                    last_committed_l2_block,
+                    last_verified_l2_block,
+                    last_executed_l2_block,
+                );
+            }
             BlockRangesParams::L2ToL1Events { events } => {
                 process_l2_to_l1_events(pool, events).await?;
             }
+            BlockRangesParams::NewL1Block {
+                block_number,
+                block_hash,
+            } => {
+                storage::add_l1_block_hash(pool, block_number, block_hash, L1_BLOCK_HASHES_WINDOW)
+                    .await?;
+            }
+            BlockRangesParams::L1Reorg { fork_block_number } => {
+                let rewound = storage::rewind_to_l1_block(pool, fork_block_number).await?;
+
+                WATCHER_METRICS.l2_rewound_blocks.inc_by(rewound);
+
+                tracing::warn!(
+                    "L1 reorg at block {fork_block_number}, statuses of {rewound} L2 blocks have been rewound"
+                );
+            }
         }
         Ok(())
     }
@@ -266,14 +332,87 @@ where
                 Ok(None)
             }
         }
-        BlockEvent::BlocksRevert { .. } => {
-            tracing::error!("Received a blocks revert event: {event:?}");
-            Ok(None)
+        BlockEvent::BlocksRevert { event, .. } => {
+            tracing::warn!("Received a blocks revert event: {event:?}");
+
+            let last_committed_batch = event.total_batches_committed.as_u64();
+            let last_verified_batch = event.total_batches_verified.as_u64();
+            let last_executed_batch = event.total_batches_executed.as_u64();
+
+            let last_committed_l2_block =
+                last_l2_block_in_batch(&l2_middleware, last_committed_batch).await?;
+            let last_verified_l2_block =
+                last_l2_block_in_batch(&l2_middleware, last_verified_batch).await?;
+            let last_executed_l2_block =
+                last_l2_block_in_batch(&l2_middleware, last_executed_batch).await?;
+
+            if let (
+                Some(last_committed_l2_block),
+                Some(last_verified_l2_block),
+                Some(last_executed_l2_block),
+            ) = (
+                last_committed_l2_block,
+                last_verified_l2_block,
+                last_executed_l2_block,
+            ) {
+                WATCHER_METRICS
+                    .l2_last_committed_block
+                    .set(last_committed_l2_block as i64);
+                WATCHER_METRICS
+                    .l2_last_verified_block
+                    .set(last_verified_l2_block as i64);
+                WATCHER_METRICS
+                    .l2_last_executed_block
+                    .set(last_executed_l2_block as i64);
+
+                Ok(Some(BlockRangesParams::Revert {
+                    last_committed_l2_block,
+                    last_verified_l2_block,
+                    last_executed_l2_block,
+                    last_committed_batch,
+                }))
+            } else {
+                tracing::error!(
+                    "One of the reverted ranges not found: {last_committed_l2_block:?}, {last_verified_l2_block:?}, {last_executed_l2_block:?}"
+                );
+                Ok(None)
+            }
         }
         BlockEvent::L2ToL1Events { events } => Ok(Some(BlockRangesParams::L2ToL1Events { events })),
+        BlockEvent::NewL1Block {
+            block_number,
+            block_hash,
+        } => Ok(Some(BlockRangesParams::NewL1Block {
+            block_number,
+            block_hash,
+        })),
+        BlockEvent::L1Reorg { fork_block_number } => {
+            tracing::warn!("Received an L1 reorg event, fork at block {fork_block_number}");
+
+            Ok(Some(BlockRangesParams::L1Reorg { fork_block_number }))
+        }
     }
 }
 
+// Get the number of the last L2 block in a given L1 batch.
+//
+// Batch `0` is the genesis batch and contains L2 block `0` only.
+async fn last_l2_block_in_batch<M2>(l2_middleware: M2, batch_number: u64) -> Result<Option<u64>>
+where
+    M2: ZksyncMiddleware,
+{
+    if batch_number == 0 {
+        return Ok(Some(0));
+    }
+
+    let range_end = l2_middleware
+        .get_l1_batch_block_range(batch_number as u32)
+        .await?
+        .map(|range| range.1.as_u64());
+
+    Ok(range_end)
+}
+
 async fn process_block_events<M2>(
     pool: &PgPool,
     events: Vec<BlockEvent>,
@@ -372,6 +511,16 @@ where
         }
     }
 
+    // The stream ends on shutdown, the events accumulated so far are not lost.
+    if !block_event_batch.is_empty() {
+        tracing::info!(
+            "flushing batch of l1 events {} on stream end",
+            block_event_batch.len()
+        );
+
+        process_block_events(&pool, block_event_batch, &l2_middleware).await?;
+    }
+
     Ok(())
 }
 
@@ -429,5 +578,15 @@ where
         }
     }
 
+    // The stream ends on shutdown, the events of the last seen block are not lost.
+    if !in_block_events.is_empty() {
+        tracing::info!(
+            "flushing {} withdrawal events on stream end",
+            in_block_events.len()
+        );
+
+        process_withdrawals_in_block(&pool, in_block_events, &mut withdrawals_meterer).await?;
+    }
+
     Ok(())
 }
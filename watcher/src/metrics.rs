@@ -1,6 +1,6 @@
 //! Metrics for withdrawal watcher
 
-use vise::{Gauge, Metrics};
+use vise::{Counter, Gauge, Metrics};
 
 /// Watcher metrics
 #[derive(Debug, Metrics)]
@@ -17,6 +17,12 @@ pub(super) struct WatcherMetrics {
 
     /// Last seen L2 block number.
     pub l2_last_seen_block: Gauge,
+
+    /// Number of L2 blocks whose commitment has been reverted on L1.
+    pub l2_reverted_blocks: Counter,
+
+    /// Number of L2 blocks whose statuses have been rewound due to L1 reorgs.
+    pub l2_rewound_blocks: Counter,
 }
 
 #[vise::register]
@@ -0,0 +1,391 @@
+#![deny(unused_crate_dependencies)]
+#![warn(missing_docs)]
+#![warn(unused_extern_crates)]
+#![warn(unused_imports)]
+
+//! Delivery of webhooks on finalization and failures of withdrawals.
+//!
+//! Events are taken from the outbox in the database, posted to the registered
+//! URLs signed with the secrets of the webhooks and retried with backoff until
+//! they are delivered or dead-lettered.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ethers::types::{Address, H256, U256};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use sqlx::PgPool;
+use storage::{RetryPolicy, WebhookDelivery};
+
+use crate::metrics::WEBHOOKS_METRICS;
+
+mod metrics;
+
+/// Header carrying the signature of a delivery, `sha256=<hex of HMAC-SHA256>`.
+///
+/// The signed payload is the timestamp of the delivery and its body joined with a `.`.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// Header carrying the Unix time in seconds a delivery has been sent at.
+///
+/// It is covered by the signature so receivers can reject stale deliveries replayed to them.
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+
+/// Header carrying the name of the event delivered.
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+
+/// Header carrying the id of a delivery, it is the same on every retry of it.
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+// How many deliveries are attempted at once.
+const DELIVERY_BATCH_SIZE: u64 = 50;
+
+// Deliveries not answered in time are considered failed.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Backoff period if there are no deliveries due.
+const NO_DUE_DELIVERIES_BACKOFF: Duration = Duration::from_secs(5);
+
+// Backoff period if one of the loop iterations has failed.
+const LOOP_ITERATION_ERROR_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Retry policy of deliveries of webhooks used by default.
+pub fn default_retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 10,
+        initial_backoff: Duration::from_secs(10),
+        max_backoff: Duration::from_secs(60 * 60),
+        multiplier: 2.0,
+        jitter: 0.1,
+    }
+}
+
+/// Sign a body of a delivery sent at `timestamp` with a secret of a webhook.
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    // HMAC accepts keys of any size.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key size");
+    mac.update(format!("{timestamp}.").as_bytes());
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// The withdrawal in a body of a delivery.
+#[derive(Debug, Serialize)]
+struct WithdrawalPayload {
+    id: u64,
+    tx_hash: H256,
+    event_index_in_tx: u32,
+    token: Address,
+    amount: U256,
+    finalization_tx: Option<H256>,
+}
+
+/// A body of a delivery.
+#[derive(Debug, Serialize)]
+struct Payload {
+    delivery_id: u64,
+    event: &'static str,
+    withdrawal: WithdrawalPayload,
+}
+
+impl From<&WebhookDelivery> for Payload {
+    fn from(delivery: &WebhookDelivery) -> Self {
+        let w = &delivery.withdrawal;
+
+        Self {
+            delivery_id: delivery.id,
+            event: delivery.event.as_str(),
+            withdrawal: WithdrawalPayload {
+                id: w.id,
+                tx_hash: w.tx_hash,
+                event_index_in_tx: w.event_index_in_tx,
+                token: w.token,
+                amount: w.amount,
+                finalization_tx: w.finalization_tx,
+            },
+        }
+    }
+}
+
+/// Sender of the deliveries of webhooks.
+pub struct WebhookSender {
+    pool: PgPool,
+    client: reqwest::Client,
+    retry_policy: RetryPolicy,
+}
+
+impl WebhookSender {
+    /// Create a new [`WebhookSender`], failed deliveries are retried according to `retry_policy`.
+    pub fn new(pool: PgPool, retry_policy: RetryPolicy) -> Self {
+        Self {
+            pool,
+            client: reqwest::Client::new(),
+            retry_policy,
+        }
+    }
+
+    /// [`WebhookSender`] main loop.
+    pub async fn run(self) {
+        loop {
+            match self.deliver_due().await {
+                Ok(0) => tokio::time::sleep(NO_DUE_DELIVERIES_BACKOFF).await,
+                Ok(_) => (),
+                Err(e) => {
+                    tracing::error!("iteration of webhooks loop has ended with {e}");
+                    tokio::time::sleep(LOOP_ITERATION_ERROR_BACKOFF).await;
+                }
+            }
+        }
+    }
+
+    /// Attempt the deliveries that are due.
+    ///
+    /// Returns the number of deliveries attempted.
+    pub async fn deliver_due(&self) -> storage::Result<usize> {
+        let due = storage::due_webhook_deliveries(&self.pool, DELIVERY_BATCH_SIZE).await?;
+
+        let results =
+            futures::future::join_all(due.iter().map(|delivery| self.deliver(delivery))).await;
+
+        for (delivery, result) in due.iter().zip(results) {
+            let error = match result {
+                Ok(()) => {
+                    storage::webhook_delivered(&self.pool, delivery.id).await?;
+                    WEBHOOKS_METRICS.deliveries[&"delivered"].inc();
+                    continue;
+                }
+                Err(e) => e,
+            };
+
+            tracing::warn!(
+                "failed to deliver {} of withdrawal {} to webhook {}: {error}",
+                delivery.event.as_str(),
+                delivery.withdrawal.id,
+                delivery.webhook_id,
+            );
+
+            let dead = storage::webhook_delivery_failed(
+                &self.pool,
+                delivery.id,
+                &error,
+                &self.retry_policy,
+            )
+            .await?;
+
+            if dead {
+                tracing::error!(
+                    "giving up on delivery {} to webhook {} after {} attempts",
+                    delivery.id,
+                    delivery.webhook_id,
+                    delivery.attempts + 1,
+                );
+                WEBHOOKS_METRICS.deliveries[&"dead"].inc();
+            } else {
+                WEBHOOKS_METRICS.deliveries[&"failed"].inc();
+            }
+        }
+
+        Ok(due.len())
+    }
+
+    async fn deliver(&self, delivery: &WebhookDelivery) -> Result<(), String> {
+        // Serializing the payload never fails.
+        let body = serde_json::to_vec(&Payload::from(delivery)).map_err(|e| e.to_string())?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?
+            .as_secs();
+
+        let response = self
+            .client
+            .post(&delivery.url)
+            .timeout(DELIVERY_TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, sign(&delivery.secret, timestamp, &body))
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(EVENT_HEADER, delivery.event.as_str())
+            .header(DELIVERY_HEADER, delivery.id)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("responded with {status}"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+    use ethers::types::H256;
+    use pretty_assertions::assert_eq;
+    use sqlx::PgPool;
+    use storage::RetryPolicy;
+    use tokio::sync::Mutex;
+
+    use super::{WebhookSender, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+
+    // Requests received by the stand-in and statuses it responds with, in order.
+    #[derive(Clone, Default)]
+    struct StandIn {
+        received: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+        statuses: Arc<Mutex<Vec<StatusCode>>>,
+    }
+
+    async fn receive(
+        State(stand_in): State<StandIn>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        stand_in.received.lock().await.push((headers, body));
+
+        let mut statuses = stand_in.statuses.lock().await;
+        if statuses.is_empty() {
+            StatusCode::OK
+        } else {
+            statuses.remove(0)
+        }
+    }
+
+    // Serve a local stand-in of the receiver of webhooks.
+    async fn serve(stand_in: StandIn) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(stand_in);
+
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        addr
+    }
+
+    async fn unfinalizable_withdrawal(pool: &PgPool) {
+        sqlx::query(
+            "INSERT INTO withdrawals (tx_hash, l2_block_number, token, amount, event_index_in_tx) \
+             VALUES ($1, 5, $2, 1, 0)",
+        )
+        .bind(H256::from_low_u64_be(5).as_bytes())
+        .bind([0u8; 20].as_slice())
+        .execute(pool)
+        .await
+        .unwrap();
+
+        storage::set_withdrawal_unfinalizable(pool, H256::from_low_u64_be(5), 0)
+            .await
+            .unwrap();
+    }
+
+    fn no_backoff(max_attempts: u64) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            ..Default::default()
+        }
+    }
+
+    #[sqlx::test(migrations = "../storage/migrations")]
+    async fn deliveries_are_signed_and_retried(pool: PgPool) {
+        let stand_in = StandIn::default();
+        stand_in
+            .statuses
+            .lock()
+            .await
+            .push(StatusCode::INTERNAL_SERVER_ERROR);
+        let addr = serve(stand_in.clone()).await;
+
+        storage::add_webhook(&pool, &format!("http://{addr}/hook"), "secret", None, None)
+            .await
+            .unwrap();
+        unfinalizable_withdrawal(&pool).await;
+
+        let sender = WebhookSender::new(pool.clone(), no_backoff(3));
+        assert_eq!(sender.deliver_due().await.unwrap(), 1);
+        assert_eq!(sender.deliver_due().await.unwrap(), 1);
+        assert_eq!(sender.deliver_due().await.unwrap(), 0);
+
+        let received = stand_in.received.lock().await;
+        assert_eq!(received.len(), 2);
+
+        let (headers, body) = &received[1];
+        let timestamp: u64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert!(now.abs_diff(timestamp) < 60);
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            super::sign("secret", timestamp, body.as_bytes())
+        );
+        assert_ne!(
+            super::sign("secret", timestamp + 1, body.as_bytes()),
+            super::sign("secret", timestamp, body.as_bytes())
+        );
+        assert_eq!(headers[EVENT_HEADER], "unfinalizable");
+        assert_eq!(headers[DELIVERY_HEADER], received[0].0[DELIVERY_HEADER]);
+
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["event"], "unfinalizable");
+        assert_eq!(body["withdrawal"]["id"], 1);
+        assert_eq!(
+            body["withdrawal"]["finalization_tx"],
+            serde_json::Value::Null
+        );
+
+        assert!(storage::dead_webhook_deliveries(&pool, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[sqlx::test(migrations = "../storage/migrations")]
+    async fn undeliverable_webhooks_are_dead_lettered(pool: PgPool) {
+        let stand_in = StandIn::default();
+        stand_in
+            .statuses
+            .lock()
+            .await
+            .extend([StatusCode::BAD_GATEWAY, StatusCode::NOT_FOUND]);
+        let addr = serve(stand_in.clone()).await;
+
+        storage::add_webhook(&pool, &format!("http://{addr}/hook"), "secret", None, None)
+            .await
+            .unwrap();
+        unfinalizable_withdrawal(&pool).await;
+
+        let sender = WebhookSender::new(pool.clone(), no_backoff(2));
+        assert_eq!(sender.deliver_due().await.unwrap(), 1);
+        assert_eq!(sender.deliver_due().await.unwrap(), 1);
+        assert_eq!(sender.deliver_due().await.unwrap(), 0);
+
+        let dead = storage::dead_webhook_deliveries(&pool, 10).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].attempts, 2);
+        assert_eq!(
+            dead[0].last_error.as_deref(),
+            Some("responded with 404 Not Found")
+        );
+
+        // Once retried by hand the delivery is attempted again.
+        assert!(storage::retry_dead_webhook_delivery(&pool, dead[0].id)
+            .await
+            .unwrap());
+        assert_eq!(sender.deliver_due().await.unwrap(), 1);
+        assert_eq!(stand_in.received.lock().await.len(), 3);
+    }
+}
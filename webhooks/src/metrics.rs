@@ -0,0 +1,15 @@
+//! Metrics for webhooks
+
+use vise::{Counter, LabeledFamily, Metrics};
+
+/// Webhooks metrics
+#[derive(Debug, Metrics)]
+#[metrics(prefix = "webhooks")]
+pub(super) struct WebhooksMetrics {
+    /// Number of attempts to deliver webhooks by their results.
+    #[metrics(labels = ["result"])]
+    pub deliveries: LabeledFamily<&'static str, Counter>,
+}
+
+#[vise::register]
+pub(super) static WEBHOOKS_METRICS: vise::Global<WebhooksMetrics> = vise::Global::new();